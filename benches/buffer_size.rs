@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use simple_file_transfer_v2::{
+    fs::browser::Response,
+    protocol::{read_frame, write_frame, CompressionAlgorithm}
+};
+use tokio::{io::{AsyncWriteExt, BufReader, BufWriter}, net::{TcpListener, TcpStream}, runtime::Runtime};
+
+const TRANSFER_SIZE: usize = 100 * 1024 * 1024;
+// Small enough that a serialized chunk always fits in a frame
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Send TRANSFER_SIZE bytes over a loopback connection as DownloadChunk frames, with both ends buffered by
+/// `buffer_size` bytes
+async fn transfer(buffer_size: usize) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let sender = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut stream = BufWriter::with_capacity(buffer_size, socket);
+
+        let chunk = vec![0xAB; CHUNK_SIZE];
+        let mut bytes_remaining = TRANSFER_SIZE;
+        while bytes_remaining > 0 {
+            bytes_remaining -= CHUNK_SIZE;
+            let frame = Response::DownloadChunk {
+                download_id: 0,
+                data: chunk.clone(),
+                bytes_remaining: bytes_remaining.try_into().unwrap()
+            };
+            write_frame(&mut stream, CompressionAlgorithm::None, &frame).await.unwrap();
+        }
+        stream.flush().await.unwrap();
+    });
+
+    let mut stream = BufReader::with_capacity(buffer_size, TcpStream::connect(address).await.unwrap());
+    let mut buffer = vec![0; 4096];
+    loop {
+        match read_frame(&mut stream, &mut buffer, CompressionAlgorithm::None).await.unwrap() {
+            Response::DownloadChunk { bytes_remaining: 0, .. } => break,
+            Response::DownloadChunk { .. } => (),
+            _ => panic!("Unexpected response type"),
+        }
+    }
+
+    sender.await.unwrap();
+}
+
+fn bench_buffer_sizes(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("transfer_100_mib");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(TRANSFER_SIZE.try_into().unwrap()));
+    for buffer_size in [4096, 65536] {
+        group.bench_with_input(BenchmarkId::from_parameter(buffer_size), &buffer_size, |b, &buffer_size| {
+            b.iter(|| runtime.block_on(transfer(buffer_size)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_buffer_sizes);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+use std::{path::PathBuf, thread};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use simple_file_transfer_v2::fs::mapped_fs::MappedFS;
+
+const MAPPINGS: usize = 256;
+const LOOKUPS_PER_READER: usize = 1000;
+
+fn populate(mut mapped_fs: MappedFS) -> MappedFS {
+    for i in 0..MAPPINGS {
+        mapped_fs.add(format!("/data/dir{i}")).unwrap();
+    }
+    mapped_fs
+}
+
+/// Run `readers` threads which all resolve paths through the mapped FS at the same time
+fn concurrent_unmap(mapped_fs: &MappedFS, readers: usize) {
+    thread::scope(|scope| {
+        for reader in 0..readers {
+            scope.spawn(move || {
+                for i in 0..LOOKUPS_PER_READER {
+                    let path = PathBuf::from(format!("/dir{}/file.txt", (reader + i) % MAPPINGS));
+                    mapped_fs.unmap(path).unwrap();
+                }
+            });
+        }
+    });
+}
+
+fn bench_backends(c: &mut Criterion) {
+    let locked = populate(MappedFS::new());
+    let concurrent = populate(MappedFS::new_concurrent());
+
+    let mut group = c.benchmark_group("multi_reader_unmap");
+    for readers in [1, 4, 16] {
+        group.bench_with_input(BenchmarkId::new("rwlock", readers), &readers, |b, &readers| {
+            b.iter(|| concurrent_unmap(&locked, readers))
+        });
+        group.bench_with_input(BenchmarkId::new("dashmap", readers), &readers, |b, &readers| {
+            b.iter(|| concurrent_unmap(&concurrent, readers))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_backends);
+criterion_main!(benches);
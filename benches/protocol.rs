@@ -0,0 +1,60 @@
+//! Measures MessagePack encoding and decoding of the most common messages. Compare both profiles to see how
+//! much of the per-request overhead comes from serialization:
+//!
+//! ```text
+//! cargo bench --bench protocol
+//! cargo bench --bench protocol --profile dev
+//! ```
+
+use std::{borrow::Cow, ffi::OsString, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use simple_file_transfer_v2::fs::{browser::{Request, Response}, FSElement};
+use time::OffsetDateTime;
+
+fn elements(count: usize) -> Vec<FSElement> {
+    (0..count)
+        .map(|i| FSElement {
+            name: OsString::from(format!("file{i}.txt")),
+            created: Some(OffsetDateTime::UNIX_EPOCH),
+            modified: Some(OffsetDateTime::UNIX_EPOCH),
+            size: i as u64,
+            is_file: true,
+            is_symlink: false,
+            hash: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            inode: None
+        })
+        .collect()
+}
+
+fn bench_request(c: &mut Criterion) {
+    let request = Request::Read { id: 1 };
+    let data = rmp_serde::to_vec(&request).unwrap();
+
+    let mut group = c.benchmark_group("request_read");
+    group.bench_function("serialize", |b| b.iter(|| rmp_serde::to_vec(&request).unwrap()));
+    group.bench_function("deserialize", |b| b.iter(|| rmp_serde::from_slice::<Request>(&data).unwrap()));
+    group.finish();
+}
+
+fn bench_response(c: &mut Criterion) {
+    let mut group = c.benchmark_group("response_read");
+    for count in [100, 1000, 10000] {
+        let response = Response::Read(Ok((PathBuf::from("/bench"), Cow::Owned(elements(count)))));
+        let data = rmp_serde::to_vec(&response).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("serialize", count), &response, |b, response| {
+            b.iter(|| rmp_serde::to_vec(response).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("deserialize", count), &data, |b, data| {
+            b.iter(|| rmp_serde::from_slice::<Response>(data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_request, bench_response);
+criterion_main!(benches);
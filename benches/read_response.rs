@@ -0,0 +1,84 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    borrow::Cow,
+    ffi::OsString,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering}
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use simple_file_transfer_v2::fs::{browser::Response, FSElement};
+use time::OffsetDateTime;
+
+const ELEMENTS: usize = 1000;
+
+/// Counts every allocation so the two ways of building a response can be compared
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn directory() -> Vec<FSElement> {
+    (0..ELEMENTS)
+        .map(|i| FSElement {
+            name: OsString::from(format!("file{i}.txt")),
+            created: Some(OffsetDateTime::UNIX_EPOCH),
+            modified: Some(OffsetDateTime::UNIX_EPOCH),
+            size: i as u64,
+            is_file: true,
+            is_symlink: false,
+            hash: None,
+            mode: None,
+            uid: None,
+            gid: None,
+            inode: None
+        })
+        .collect()
+}
+
+/// Serialize a Read response the way the server did before it could borrow the Cursor's listing
+fn serialize_cloned(elements: &[FSElement]) -> Vec<u8> {
+    rmp_serde::to_vec(&Response::Read(Ok((PathBuf::from("/bench"), Cow::Owned(elements.to_vec()))))).unwrap()
+}
+
+/// Serialize a Read response which borrows the Cursor's listing
+fn serialize_borrowed(elements: &Vec<FSElement>) -> Vec<u8> {
+    rmp_serde::to_vec(&Response::Read(Ok((PathBuf::from("/bench"), Cow::Borrowed(elements))))).unwrap()
+}
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn bench_read_response(c: &mut Criterion) {
+    let elements = directory();
+
+    println!(
+        "Allocations per {ELEMENTS} element response: cloned {}, borrowed {}",
+        allocations(|| { serialize_cloned(&elements); }),
+        allocations(|| { serialize_borrowed(&elements); })
+    );
+
+    let mut group = c.benchmark_group("read_response");
+    group.bench_function("cloned", |b| b.iter(|| serialize_cloned(&elements)));
+    group.bench_function("borrowed", |b| b.iter(|| serialize_borrowed(&elements)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_read_response);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! An example of a server plugin. The plugin answers every request with the request data reversed, followed by
+//! the number of paths registered in the mapped file system.
+//!
+//! This runs the full server with the plugin registered, so it accepts the same arguments as the server binary,
+//! e.g. `cargo run --example echo_plugin -- --port 8000`. Send a `Request::Plugin` with type ID 1 to reach it
+
+use async_trait::async_trait;
+use simple_file_transfer_v2::{fs::mapped_fs::MappedFS, plugin::Plugin, server::ServerBuilder};
+
+struct ReverseEchoPlugin;
+
+#[async_trait]
+impl Plugin for ReverseEchoPlugin {
+    fn request_type_id(&self) -> u16 {
+        1
+    }
+
+    async fn handle(&self, raw: &[u8], fs: &MappedFS) -> Vec<u8> {
+        let mut response: Vec<u8> = raw.iter().rev().copied().collect();
+        response.extend_from_slice(&(fs.registered().len() as u32).to_be_bytes());
+        response
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    ServerBuilder::new()
+        .register_plugin(ReverseEchoPlugin)
+        .run()
+        .await
+}
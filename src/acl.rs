@@ -0,0 +1,146 @@
+//! Access control lists restricting which client addresses may list each virtual root
+
+use std::{collections::HashMap, ffi::{OsStr, OsString}, fmt, net::IpAddr, str::FromStr, sync::{Arc, RwLock}};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AclError {
+    #[error("{0} is not a valid IP address")]
+    InvalidAddress(String),
+
+    #[error("The prefix length {prefix_len} is longer than the {max} bits of the address")]
+    InvalidPrefixLength { prefix_len: u8, max: u8 },
+
+    #[error("{0} is not a valid prefix length")]
+    InvalidPrefix(String)
+}
+
+/// A range of IP addresses written in CIDR notation, such as "10.0.0.0/8" or "::1/128". An address without a prefix
+/// length is a range holding only that address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, AclError> {
+        let max = max_prefix_len(addr);
+        if prefix_len > max {
+            return Err(AclError::InvalidPrefixLength { prefix_len, max });
+        }
+        Ok(Cidr { addr, prefix_len })
+    }
+
+    /// Returns true if `ip` is in the range. IPv4 addresses written as IPv6, such as "::ffff:10.0.0.1", are treated as
+    /// IPv4 addresses
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => same_prefix(u32::from(addr).into(), u32::from(ip).into(), 32, self.prefix_len),
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => same_prefix(addr.into(), ip.into(), 128, self.prefix_len),
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix_len(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Compare the first `prefix_len` bits of two addresses which are `bits` long
+fn same_prefix(addr: u128, ip: u128, bits: u32, prefix_len: u8) -> bool {
+    let ignored = bits - u32::from(prefix_len);
+    addr.checked_shr(ignored).unwrap_or(0) == ip.checked_shr(ignored).unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = AclError;
+
+    fn from_str(s: &str) -> Result<Self, AclError> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr.parse().map_err(|_| AclError::InvalidAddress(addr.to_owned()))?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| AclError::InvalidPrefix(prefix_len.to_owned()))?,
+            None => max_prefix_len(addr),
+        };
+        Cidr::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclAction {
+    Allow,
+    Deny
+}
+
+/// The address ranges allowed and denied for each virtual root. A root without any rules may be listed by every
+/// client. Otherwise a client may list it if its address is not in a denied range, and either is in an allowed range
+/// or the root has no allowed ranges. Clones share their rules, so changes made through the server's CLI are seen by
+/// every connection
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    rules: Arc<RwLock<Rules>>
+}
+
+/// The rules of each virtual root, in the order they were added
+type Rules = HashMap<OsString, Vec<(AclAction, Cidr)>>;
+
+impl Acl {
+    pub fn new() -> Self {
+        Acl::default()
+    }
+
+    /// Let clients in `range` list the virtual root `name`. Once a root has an allowed range, clients outside of
+    /// every allowed range are refused
+    pub fn allow<N: Into<OsString>>(&self, name: N, range: Cidr) {
+        self.add(name.into(), AclAction::Allow, range);
+    }
+
+    /// Refuse clients in `range` when they list the virtual root `name`, even if they are also in an allowed range
+    pub fn deny<N: Into<OsString>>(&self, name: N, range: Cidr) {
+        self.add(name.into(), AclAction::Deny, range);
+    }
+
+    fn add(&self, name: OsString, action: AclAction, range: Cidr) {
+        let mut rules = self.rules.write().unwrap();
+        let rules = rules.entry(name).or_default();
+        if !rules.contains(&(action, range)) {
+            rules.push((action, range));
+        }
+    }
+
+    /// The rules for the virtual root `name`, in the order they were added
+    pub fn rules(&self, name: &OsStr) -> Vec<(AclAction, Cidr)> {
+        self.rules.read().unwrap().get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns true if a client at `ip` may list the virtual root `name`. A client whose address is unknown is in no
+    /// range
+    pub fn is_permitted(&self, name: &OsStr, ip: Option<IpAddr>) -> bool {
+        let rules = self.rules.read().unwrap();
+        let Some(rules) = rules.get(name) else {
+            return true;
+        };
+
+        let matches = |wanted: AclAction| rules
+            .iter()
+            .any(|(action, range)| *action == wanted && ip.is_some_and(|ip| range.contains(ip)));
+        let has_allowed = rules.iter().any(|(action, _)| *action == AclAction::Allow);
+
+        !matches(AclAction::Deny) && (!has_allowed || matches(AclAction::Allow))
+    }
+}
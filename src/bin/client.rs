@@ -1,28 +1,134 @@
-use std::{unreachable, io, path::PathBuf};
+use std::{unreachable, ffi::OsString, io, path::{Path, PathBuf}, sync::OnceLock};
 
-use anyhow::bail;
-use simple_file_transfer_v2::{fs::browser::{Request, Response}, read_input};
-use tokio::{net::TcpStream, io::{BufStream, AsyncRead, AsyncWrite, AsyncWriteExt, AsyncReadExt}};
+use anyhow::{bail, anyhow};
+use clap::{Parser, Subcommand, ValueEnum};
+use simple_file_transfer_v2::{
+    fs::{browser::{Request, Response, MAX_UPLOAD_SIZE}, archive::ArchiveFormat, checksum::ChecksumAlgorithm, sort::{SortOrder, SortDirection}, FSElement},
+    formatter::format_timestamp,
+    protocol::{
+        read_frame, read_magic, write_frame, write_magic, next_heartbeat, ClientHello, ServerHello, CompressionAlgorithm, HeartbeatConfig,
+        ProtocolError, PROTOCOL_VERSION, SUPPORTED_COMPRESSION
+    },
+    tls,
+    read_input
+};
+use thiserror::Error;
+use tokio::{net::TcpStream, io::{BufStream, AsyncRead, AsyncWrite, AsyncWriteExt}};
 
-async fn make_request(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>, request: Request) -> Result<Response, anyhow::Error> {
-    let data = rmp_serde::to_vec(&request)?;
+/// Returned in place of a response when the server announces it is shutting down
+#[derive(Error, Debug)]
+#[error("The server is shutting down: {0}")]
+struct ServerShutdown(String);
 
-    stream.write_u16(data.len().try_into()?).await?;
-    stream.write(&data).await?;
+async fn make_request(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>, request: Request) -> Result<Response<'static>, anyhow::Error> {
+    send_request(stream, request).await?;
+    read_response(stream, buffer).await
+}
+
+/// Send a request without waiting for its response, for requests answered by several frames
+async fn send_request(stream: &mut (impl AsyncWrite + Unpin), request: Request) -> Result<(), anyhow::Error> {
+    let compression = COMPRESSION.get().copied().unwrap_or(CompressionAlgorithm::None);
+
+    write_frame(stream, compression, &request).await?;
     stream.flush().await?;
+    Ok(())
+}
 
-    let request_len: usize = stream.read_u16().await?.into();
-    if request_len > buffer.len() {
-        buffer.resize(request_len, 0);
+/// Read the next frame which is not a heartbeat. Fails if the server sends nothing within the heartbeat timeout
+async fn read_response(stream: &mut (impl AsyncRead + Unpin), buffer: &mut Vec<u8>) -> Result<Response<'static>, anyhow::Error> {
+    let compression = COMPRESSION.get().copied().unwrap_or(CompressionAlgorithm::None);
+    loop {
+        let response = match HEARTBEAT.get().copied().flatten() {
+            Some(heartbeat) => tokio::time::timeout(heartbeat.timeout, read_frame(stream, buffer, compression))
+                .await
+                .map_err(|_| anyhow!("The server stopped responding"))??,
+            None => read_frame(stream, buffer, compression).await?,
+        };
+
+        match response {
+            Response::Heartbeat => (),
+            Response::UnknownRequest { name } => bail!("The server does not support the {name} request"),
+            Response::Shutdown { reason } => return Err(ServerShutdown(reason).into()),
+            response => return Ok(response),
+        }
     }
+}
 
-    let slice = &mut buffer[..request_len];
-    stream.read_exact(slice).await?;
+/// Tell the server the client is done, and wait for it to answer before the connection is closed
+async fn say_goodbye(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    match make_request(stream, buffer, Request::Goodbye).await? {
+        Response::Goodbye => Ok(()),
+        _ => bail!("Unexpected response type")
+    }
+}
 
-    Ok(rmp_serde::from_slice(&slice)?)
+/// Read a file to send in a [`Request::Upload`], refusing files too large to fit in one
+async fn read_upload(path: &Path) -> Result<Vec<u8>, anyhow::Error> {
+    let len = tokio::fs::metadata(path).await?.len();
+    if len > MAX_UPLOAD_SIZE as u64 {
+        bail!("The file is {len} bytes, more than the limit of {MAX_UPLOAD_SIZE} bytes for an upload");
+    }
+    Ok(tokio::fs::read(path).await?)
+}
+
+/// Collect the DownloadChunk frames which follow a successful Archive or Download response
+async fn receive_download(stream: &mut (impl AsyncRead + Unpin), buffer: &mut Vec<u8>, download_id: u32) -> Result<Vec<u8>, anyhow::Error> {
+    let mut data = vec![];
+    loop {
+        match read_response(stream, buffer).await? {
+            Response::DownloadChunk { download_id: chunk_id, data: chunk, bytes_remaining } if chunk_id == download_id => {
+                data.extend_from_slice(&chunk);
+                if bytes_remaining == 0 {
+                    return Ok(data);
+                }
+            }
+            _ => bail!("Unexpected response type")
+        }
+    }
 }
 
-fn ask_for_command_selection<S: AsRef<str>>(commands: &Vec<S>) -> Result<u32, io::Error> {
+/// Run a blocking prompt, sending heartbeats to the server for as long as the user takes to answer
+async fn prompt<T: Send + 'static>(
+    stream: &mut (impl AsyncWrite + Unpin),
+    f: impl FnOnce() -> T + Send + 'static
+) -> Result<T, anyhow::Error> {
+    let mut heartbeat_interval = HEARTBEAT.get().copied().flatten().map(|heartbeat| heartbeat.interval());
+    let task = tokio::task::spawn_blocking(f);
+    tokio::pin!(task);
+
+    loop {
+        tokio::select! {
+            result = &mut task => return Ok(result?),
+            _ = next_heartbeat(&mut heartbeat_interval) => send_request(stream, Request::Heartbeat).await?,
+        }
+    }
+}
+
+/// The compression algorithm selected by the server during the handshake
+static COMPRESSION: OnceLock<CompressionAlgorithm> = OnceLock::new();
+
+/// The heartbeat settings agreed with the server during the handshake, None if heartbeats are disabled
+static HEARTBEAT: OnceLock<Option<HeartbeatConfig>> = OnceLock::new();
+
+/// Advertise the supported compression algorithms and record the one the server selects
+async fn handshake(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    let hello = ClientHello {
+        supported_compression: SUPPORTED_COMPRESSION.to_vec(),
+        heartbeat: Some(HeartbeatConfig::default()),
+        protocol_version: PROTOCOL_VERSION
+    };
+    write_magic(stream).await?;
+    write_frame(stream, CompressionAlgorithm::None, &hello).await?;
+    stream.flush().await?;
+
+    read_magic(stream).await?;
+    let hello: ServerHello = read_frame(stream, buffer, CompressionAlgorithm::None).await?;
+    _ = COMPRESSION.set(hello.selected_compression);
+    _ = HEARTBEAT.set(hello.heartbeat);
+    Ok(())
+}
+
+fn ask_for_command_selection<S: AsRef<str>>(commands: &[S]) -> Result<u32, io::Error> {
     let options = commands.iter()
         .enumerate()
         .map(|(idx, command)| format!("{} - {}\n", idx + 1, command.as_ref()))
@@ -44,16 +150,400 @@ fn ask_for_command_selection<S: AsRef<str>>(commands: &Vec<S>) -> Result<u32, io
     }
 }
 
+fn format_elements(elements: &[FSElement]) -> String {
+    elements.iter()
+        .map(|element| format!("{} - Name: {}\t\tSize: {}\tCreated: {}\tModified: {}{}\n",
+            if element.is_symlink {"L"} else if element.is_file {"F"} else {"D"},
+            element.name_lossy(),
+            element.size_human(),
+            format_timestamp(element.created),
+            format_timestamp(element.modified),
+            format_ownership(element)
+        ))
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// The permission bits in octal and the owner's UID, for servers which report them
+fn format_ownership(element: &FSElement) -> String {
+    let mut ownership = String::new();
+    if let Some(mode) = element.mode {
+        ownership += &format!("\tMode: {:04o}", mode & 0o7777);
+    }
+    if let Some(uid) = element.uid {
+        ownership += &format!("\tOwner: {uid}");
+    }
+    ownership
+}
+
+/// A single line of a batch script
+enum BatchCommand {
+    Create,
+    Select(u16),
+    Navigate(PathBuf),
+    Read,
+    Download { path: PathBuf, dest: PathBuf },
+    Exit
+}
+
+fn parse_batch_command(line: &str) -> Result<BatchCommand, anyhow::Error> {
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+        Some((command, argument)) => (command, Some(argument.trim())),
+        None => (line, None),
+    };
+
+    match (command.to_lowercase().as_str(), argument) {
+        ("create", None) => Ok(BatchCommand::Create),
+        ("select", Some(id)) => Ok(BatchCommand::Select(id.parse()?)),
+        // "move" is the old name of "navigate", kept so existing scripts still work
+        ("navigate" | "move", Some(path)) => Ok(BatchCommand::Navigate(path.into())),
+        ("read", None) => Ok(BatchCommand::Read),
+        // The path on the server comes first, so the local destination may contain spaces
+        ("download", Some(arguments)) => match arguments.split_once(char::is_whitespace) {
+            Some((path, dest)) => Ok(BatchCommand::Download { path: path.into(), dest: dest.trim().into() }),
+            None => bail!("Unknown command or wrong number of arguments: {line}"),
+        },
+        ("exit", None) => Ok(BatchCommand::Exit),
+        _ => bail!("Unknown command or wrong number of arguments: {line}")
+    }
+}
+
+/// Execute a single batch command. Returns Ok(false) if the script should stop
+async fn run_batch_command(
+    stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    buffer: &mut Vec<u8>,
+    selected_cursor: &mut Option<u16>,
+    command: BatchCommand
+) -> Result<bool, anyhow::Error> {
+    let selected = || selected_cursor.ok_or_else(|| anyhow!("No cursor is selected"));
+
+    match command {
+        BatchCommand::Create => match make_request(stream, buffer, Request::Create).await? {
+            // Cursor IDs are random, so the new cursor is selected for the rest of the script
+            Response::Create(Ok(id)) => {
+                println!("Cursor {id} created and selected");
+                *selected_cursor = Some(id);
+            }
+            Response::Create(Err(err)) => bail!(err),
+            _ => bail!("Unexpected response type")
+        }
+        BatchCommand::Select(id) => {
+            println!("Selected cursor {id}");
+            *selected_cursor = Some(id);
+        }
+        BatchCommand::Navigate(path) => match make_request(stream, buffer, Request::Navigate { id: selected()?, path: path.clone() }).await? {
+            Response::Navigate(Ok(())) => println!("Moved to {path:?}"),
+            Response::Navigate(Err(err)) => bail!(err),
+            _ => bail!("Unexpected response type")
+        }
+        BatchCommand::Read => match make_request(stream, buffer, Request::Read { id: selected()? }).await? {
+            Response::Read(Ok((_, elements))) => print!("{}", format_elements(&elements)),
+            Response::Read(Err(err)) => bail!(err),
+            _ => bail!("Unexpected response type")
+        }
+        BatchCommand::Download { path, dest } => match make_request(stream, buffer, Request::Download { id: selected()?, path: path.clone() }).await? {
+            Response::Download(Ok(download_id)) => {
+                let data = receive_download(stream, buffer, download_id).await?;
+                tokio::fs::write(&dest, &data).await?;
+                println!("Downloaded {} bytes from {path:?} to {dest:?}", data.len());
+            }
+            Response::Download(Err(err)) => bail!(err),
+            _ => bail!("Unexpected response type")
+        }
+        BatchCommand::Exit => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Run each command of a batch script in order. Errors are reported and skipped unless `fail_fast` is set
+async fn run_batch(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>, script: PathBuf, fail_fast: bool) -> Result<(), anyhow::Error> {
+    let script = tokio::fs::read_to_string(script).await?;
+    let mut selected_cursor = None;
+
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        println!("> {line}");
+        let result = match parse_batch_command(line) {
+            Ok(command) => run_batch_command(stream, buffer, &mut selected_cursor, command).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) if fail_fast => return Err(err.context(format!("Line {} failed", number + 1))),
+            // Nothing else can run once the server has gone
+            Err(err) if err.is::<ServerShutdown>() => return Err(err),
+            Err(err) => println!("Error on line {}: {err}", number + 1),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Parser)]
+struct Args {
+    /// Run a single command and exit, for use in scripts. Without one, the client is interactive
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Address of the server to connect to when no command is given
+    #[arg(long, default_value = "127.0.0.1:8000")]
+    server: String,
+
+    /// Run the commands in a script file rather than interactively. One command per line:
+    /// create, select <id>, navigate <path>, read, download <path> <dest>, exit
+    #[arg(long)]
+    batch: Option<PathBuf>,
+
+    /// Stop running the batch script at the first command which fails
+    #[arg(long, requires = "batch")]
+    fail_fast: bool,
+
+    /// Size in bytes of the connection's read and write buffers
+    #[arg(long, default_value_t = 65536)]
+    buffer_size: usize,
+
+    /// Print the server's version and capabilities, then exit
+    #[arg(long, conflicts_with = "batch")]
+    server_info: bool,
+
+    /// Connect using TLS, trusting servers whose certificate is signed by the certificate authority in this PEM file
+    #[arg(long, value_name = "CA_CERT")]
+    tls: Option<PathBuf>,
+
+    /// PEM file holding the certificate chain to present to servers which require client certificates
+    #[arg(long, requires = "tls", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file holding the private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+/// The commands run without prompting. Each except `interactive` works through a Cursor which is destroyed once the
+/// command is done, and fails with a non-zero exit code if the server reports an error
+#[derive(Subcommand)]
+enum Command {
+    /// Browse the server through menus
+    Interactive { addr: String },
+
+    /// Print the names of the elements at a path, one per line. Directories end with '/'
+    List {
+        addr: String,
+        path: PathBuf,
+
+        /// Print every field of each element as a line of JSON instead
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Save a file from the server
+    Download { addr: String, remote_path: PathBuf, local_path: PathBuf },
+
+    /// Print the checksum of a file on the server in hex
+    Checksum {
+        addr: String,
+        path: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+    },
+
+    /// Send a file to the server. The file is sent in a single message, so it may be at most 16 MiB less 64 KiB
+    Upload { addr: String, local_path: PathBuf, remote_path: PathBuf },
+}
+
+impl Command {
+    fn addr(&self) -> &str {
+        match self {
+            Command::Interactive { addr }
+            | Command::List { addr, .. }
+            | Command::Download { addr, .. }
+            | Command::Checksum { addr, .. }
+            | Command::Upload { addr, .. } => addr,
+        }
+    }
+}
+
+/// The names of the checksum algorithms on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum Algorithm {
+    Sha256,
+    Blake3
+}
+
+impl From<Algorithm> for ChecksumAlgorithm {
+    fn from(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => ChecksumAlgorithm::Sha256,
+            Algorithm::Blake3 => ChecksumAlgorithm::Blake3,
+        }
+    }
+}
+
+/// Run a command other than `interactive` through a Cursor created for it
+async fn run_command(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>, command: &Command) -> Result<(), anyhow::Error> {
+    let id = match make_request(stream, buffer, Request::Create).await? {
+        Response::Create(Ok(id)) => id,
+        Response::Create(Err(err)) => bail!(err),
+        _ => bail!("Unexpected response type")
+    };
+
+    let result = run_cursor_command(stream, buffer, id, command).await;
+    // An error from the command is more useful than one from destroying the Cursor
+    let destroyed = make_request(stream, buffer, Request::Destroy { id }).await;
+    result?;
+    match destroyed? {
+        Response::Destroy(Ok(())) => Ok(()),
+        Response::Destroy(Err(err)) => bail!(err),
+        _ => bail!("Unexpected response type")
+    }
+}
+
+async fn run_cursor_command(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>, id: u16, command: &Command) -> Result<(), anyhow::Error> {
+    match command {
+        Command::Interactive { .. } => unreachable!("The interactive client does not run through a single Cursor"),
+        Command::List { path, json, .. } => {
+            match make_request(stream, buffer, Request::Navigate { id, path: path.clone() }).await? {
+                Response::Navigate(Ok(())) => (),
+                Response::Navigate(Err(err)) => bail!(err),
+                _ => bail!("Unexpected response type")
+            }
+
+            let elements = match make_request(stream, buffer, Request::Read { id }).await? {
+                Response::Read(Ok((_, elements))) => elements,
+                Response::Read(Err(err)) => bail!(err),
+                _ => bail!("Unexpected response type")
+            };
+            for element in elements.iter() {
+                match json {
+                    true => println!("{}", element_json(element)?),
+                    false => println!("{}{}", element.name_lossy(), if element.is_file { "" } else { "/" }),
+                }
+            }
+        }
+        Command::Download { remote_path, local_path, .. } => {
+            match make_request(stream, buffer, Request::Download { id, path: remote_path.clone() }).await? {
+                Response::Download(Ok(download_id)) => {
+                    let data = receive_download(stream, buffer, download_id).await?;
+                    tokio::fs::write(local_path, &data).await?;
+                }
+                Response::Download(Err(err)) => bail!(err),
+                _ => bail!("Unexpected response type")
+            }
+        }
+        Command::Checksum { path, algorithm, .. } => {
+            let algorithm = (*algorithm).into();
+            match make_request(stream, buffer, Request::Checksum { id, path: path.clone(), algorithm }).await? {
+                Response::Checksum(Ok(checksum)) => println!("{}", hex::encode(checksum)),
+                Response::Checksum(Err(err)) => bail!(err),
+                _ => bail!("Unexpected response type")
+            }
+        }
+        Command::Upload { local_path, remote_path, .. } => {
+            let data = read_upload(local_path).await?;
+            match make_request(stream, buffer, Request::Upload { id, dest_path: remote_path.clone(), data }).await? {
+                Response::Upload(Ok(())) => (),
+                Response::Upload(Err(err)) => bail!(err),
+                _ => bail!("Unexpected response type")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+fn element_json(element: &FSElement) -> Result<String, anyhow::Error> {
+    Ok(element.to_json()?)
+}
+
+#[cfg(not(feature = "json"))]
+fn element_json(_element: &FSElement) -> Result<String, anyhow::Error> {
+    bail!("The client was built without the json feature, which --json needs")
+}
+
+/// Ask the server for its version and capabilities and print them
+async fn print_server_info(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    match make_request(stream, buffer, Request::GetServerInfo).await? {
+        Response::GetServerInfo { version: [major, minor, patch], capabilities } => {
+            println!("Server version: {major}.{minor}.{patch}");
+            println!("Capabilities: {capabilities:?}");
+            Ok(())
+        }
+        _ => bail!("Unexpected response type")
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let server = args.command.as_ref().map_or(args.server.as_str(), Command::addr);
+
+    let socket = TcpStream::connect(server).await?;
+    match &args.tls {
+        Some(ca_path) => {
+            let identity = args.tls_cert.as_deref().zip(args.tls_key.as_deref());
+            let connector = tls::connector(ca_path, identity)?;
+            let socket = connector.connect(tls::server_name(server)?, socket).await?;
+            run_connection(socket, &args).await
+        }
+        None => run_connection(socket, &args).await,
+    }
+}
+
+/// Complete the handshake over `socket`, which is either a plain TCP stream or one wrapped in TLS, and run the
+/// commands chosen by `args`
+async fn run_connection(socket: impl AsyncRead + AsyncWrite + Unpin, args: &Args) -> Result<(), anyhow::Error> {
+    // Scratch space for decoding frames, which grows to fit the largest frame received
     const SIZE: usize = 4096;
     let mut buffer = vec![0; SIZE];
-    let mut stream = BufStream::new(TcpStream::connect("127.0.0.1:8000").await?);
+    let mut stream = BufStream::with_capacity(args.buffer_size, args.buffer_size, socket);
+
+    let result = match handshake(&mut stream, &mut buffer).await {
+        Ok(()) => {
+            let result = match &args.command {
+                // Only what was asked for is printed, so the output can be read by other programs
+                Some(command @ (Command::List { .. } | Command::Download { .. } | Command::Checksum { .. } | Command::Upload { .. })) => {
+                    run_command(&mut stream, &mut buffer, command).await
+                }
+                Some(Command::Interactive { .. }) | None => {
+                    println!("Connected!");
+                    match args.batch.clone() {
+                        _ if args.server_info => print_server_info(&mut stream, &mut buffer).await,
+                        Some(script) => run_batch(&mut stream, &mut buffer, script, args.fail_fast).await,
+                        None => run_interactive(&mut stream, &mut buffer).await,
+                    }
+                }
+            };
+            match result {
+                Ok(()) => say_goodbye(&mut stream, &mut buffer).await,
+                Err(err) => Err(err),
+            }
+        }
+        Err(err) => Err(err),
+    };
 
-    println!("Connected!");
+    if let Some(err) = result.as_ref().err().and_then(|err| err.downcast_ref::<ProtocolError>()) {
+        println!("The connection was closed because of a protocol error: {err}");
+    }
+    // Being shut down is not a failure of the client
+    if let Some(err) = result.as_ref().err().and_then(|err| err.downcast_ref::<ServerShutdown>()) {
+        println!("{err}");
+        return Ok(());
+    }
+    result
+}
 
-    let root_commands = vec!["Create Cursor", "Delete Cursor", "List Cursors", "Select Cursor", "Exit"];
-    let cursor_commands = vec!["Read", "Move", "Get Location", "Deselect"];
+async fn run_interactive(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), buffer: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    let root_commands = vec!["Create Cursor", "Delete Cursor", "List Cursors", "Select Cursor", "Show Hidden Files", "Follow Symlinks", "Health Check", "Exit"];
+    let cursor_commands = vec!["Read", "Navigate", "Get Location", "Copy", "Archive", "Checksum", "Glob", "Read (Streaming)", "Stat", "Rename", "Download", "Read (Recursive)", "Sort", "Read (Page)", "Upload", "Back", "Forward", "Search", "Search (Recursive)", "Verify Checksum", "Download (Streaming)", "Deselect"];
 
     let mut cursors = vec![];
     let mut selected_cursor = None;
@@ -61,21 +551,11 @@ async fn main() -> Result<(), anyhow::Error> {
     loop {
         if let Some(id) = selected_cursor {
             let commands = cursor_commands.clone();
-            match tokio::task::spawn_blocking(move || ask_for_command_selection(&commands)).await?? {
+            match prompt(stream, move || ask_for_command_selection(&commands)).await?? {
                 1 => {
-                    match make_request(&mut stream, &mut buffer, Request::Read { id }).await? {
-                        Response::Read(Ok(elements)) => {
-                            println!("Elements:\n{}", elements.into_iter()
-                                .map(|element| format!("{} - Name: {}\t\tSize: {}\tCreated: {}\tModified: {}\n",
-                                    if element.is_file {"F"} else {"D"},
-                                    element.name.to_string_lossy(),
-                                    element.size,
-                                    element.created.unwrap(),
-                                    element.modified.unwrap()
-                                ))
-                                .collect::<Vec<String>>()
-                                .concat()
-                            )
+                    match make_request(stream, buffer, Request::Read { id }).await? {
+                        Response::Read(Ok((path, elements))) => {
+                            println!("Elements at {path:?}:\n{}", format_elements(&elements))
                         }
                         Response::Read(Err(err)) => {
                             println!("Error while attempting to read cursor: {err}\n");
@@ -84,22 +564,22 @@ async fn main() -> Result<(), anyhow::Error> {
                     }
                 }
                 2 => {
-                    let path: PathBuf = tokio::task::spawn_blocking(move || read_input(Some("New Path: ")))
+                    let path: PathBuf = prompt(stream, move || read_input(Some("New Path: ")))
                         .await??
                         .into();
 
-                    match make_request(&mut stream, &mut buffer, Request::Move { id, path: path.clone() }).await? {
-                        Response::Move(Ok(())) => {
+                    match make_request(stream, buffer, Request::Navigate { id, path: path.clone() }).await? {
+                        Response::Navigate(Ok(())) => {
                             println!("Moved to {path:?}\n");
                         }
-                        Response::Move(Err(err)) => {
+                        Response::Navigate(Err(err)) => {
                             println!("Error while attempting to move cursor: {err}\n");
                         }
                         _ => bail!("Unexpected response type")
                     }
                 }
                 3 => {
-                    match make_request(&mut stream, &mut buffer, Request::GetLocation { id }).await? {
+                    match make_request(stream, buffer, Request::GetLocation { id }).await? {
                         Response::GetLocation(Ok(path)) => {
                             println!("Cursor is at {path:?}\n");
                         }
@@ -110,15 +590,457 @@ async fn main() -> Result<(), anyhow::Error> {
                     }
                 }
                 4 => {
+                    let from: PathBuf = prompt(stream, move || read_input(Some("Copy From: ")))
+                        .await??
+                        .into();
+                    let to: PathBuf = prompt(stream, move || read_input(Some("Copy To: ")))
+                        .await??
+                        .into();
+
+                    match make_request(stream, buffer, Request::Copy { id, from: from.clone(), to: to.clone() }).await? {
+                        Response::Copy(Ok(())) => {
+                            println!("Copied {from:?} to {to:?}\n");
+                        }
+                        Response::Copy(Err(err)) => {
+                            println!("Error while attempting to copy: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                5 => {
+                    let paths: Vec<PathBuf> = prompt(stream, move || read_input(Some("Paths (comma separated): ")))
+                        .await??
+                        .split(',')
+                        .map(|path| path.trim().into())
+                        .collect();
+
+                    let format = match prompt(stream, move || read_input(Some("Format (zip/tar.gz): ")))
+                        .await??
+                        .trim()
+                    {
+                        "zip" => ArchiveFormat::Zip,
+                        "tar.gz" => ArchiveFormat::TarGz,
+                        _ => {
+                            println!("Unknown archive format\n");
+                            continue;
+                        }
+                    };
+
+                    let destination: PathBuf = prompt(stream, move || read_input(Some("Save As: ")))
+                        .await??
+                        .into();
+
+                    match make_request(stream, buffer, Request::Archive { id, paths, format }).await? {
+                        Response::Archive(Ok(download_id)) => {
+                            let data = receive_download(stream, buffer, download_id).await?;
+                            tokio::fs::write(&destination, &data).await?;
+                            println!("Saved {} bytes to {destination:?}\n", data.len());
+                        }
+                        Response::Archive(Err(err)) => {
+                            println!("Error while attempting to create archive: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                6 => {
+                    let paths: Vec<PathBuf> = prompt(stream, move || read_input(Some("Paths (comma separated): ")))
+                        .await??
+                        .split(',')
+                        .map(|path| path.trim().into())
+                        .collect();
+
+                    let algorithm = match prompt(stream, move || read_input(Some("Algorithm (sha256/blake3): ")))
+                        .await??
+                        .trim()
+                    {
+                        "sha256" => ChecksumAlgorithm::Sha256,
+                        "blake3" => ChecksumAlgorithm::Blake3,
+                        _ => {
+                            println!("Unknown checksum algorithm\n");
+                            continue;
+                        }
+                    };
+
+                    match make_request(stream, buffer, Request::ChecksumMany { id, paths, algorithm }).await? {
+                        Response::ChecksumMany(Ok(checksums)) => {
+                            println!("Checksums:\n{}", checksums.into_iter()
+                                .map(|(path, checksum)| format!("{checksum}  {}\n", path.display()))
+                                .collect::<Vec<String>>()
+                                .concat()
+                            );
+                        }
+                        Response::ChecksumMany(Err(err)) => {
+                            println!("Error while attempting to compute checksums: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                7 => {
+                    let pattern = prompt(stream, move || read_input(Some("Pattern: "))).await??;
+
+                    match make_request(stream, buffer, Request::Glob { id, pattern }).await? {
+                        Response::Glob(Ok(elements)) => {
+                            println!("Matches:\n{}", format_elements(&elements))
+                        }
+                        Response::Glob(Err(err)) => {
+                            println!("Error while attempting to glob: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                8 => {
+                    send_request(stream, Request::ReadStreaming { id }).await?;
+                    println!("Elements:");
+                    loop {
+                        match read_response(stream, buffer).await? {
+                            Response::Element(element) => print!("{}", format_elements(&[element])),
+                            Response::EndOfStream(Ok(())) => {
+                                println!();
+                                break;
+                            }
+                            Response::EndOfStream(Err(err)) => {
+                                println!("Error while attempting to read cursor: {err}\n");
+                                break;
+                            }
+                            _ => bail!("Unexpected response type")
+                        }
+                    }
+                }
+                9 => {
+                    let path: PathBuf = prompt(stream, move || read_input(Some("Path: ")))
+                        .await??
+                        .into();
+
+                    match make_request(stream, buffer, Request::Stat { id, path }).await? {
+                        Response::Stat(Ok(element)) => {
+                            println!("{}", format_elements(&[element]))
+                        }
+                        Response::Stat(Err(err)) => {
+                            println!("Error while attempting to stat: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                10 => {
+                    let path: PathBuf = prompt(stream, move || read_input(Some("Path: ")))
+                        .await??
+                        .into();
+                    let new_name: OsString = prompt(stream, move || read_input(Some("New Name: ")))
+                        .await??
+                        .into();
+
+                    match make_request(stream, buffer, Request::Rename { id, path: path.clone(), new_name: new_name.clone() }).await? {
+                        Response::Rename(Ok(())) => {
+                            println!("Renamed {path:?} to {new_name:?}\n");
+                        }
+                        Response::Rename(Err(err)) => {
+                            println!("Error while attempting to rename: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                11 => {
+                    let path: PathBuf = prompt(stream, move || read_input(Some("Path: ")))
+                        .await??
+                        .into();
+
+                    let destination: PathBuf = prompt(stream, move || read_input(Some("Save As: ")))
+                        .await??
+                        .into();
+
+                    match make_request(stream, buffer, Request::Download { id, path }).await? {
+                        Response::Download(Ok(download_id)) => {
+                            let data = receive_download(stream, buffer, download_id).await?;
+                            tokio::fs::write(&destination, &data).await?;
+                            println!("Saved {} bytes to {destination:?}\n", data.len());
+                        }
+                        Response::Download(Err(err)) => {
+                            println!("Error while attempting to download: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                12 => {
+                    let max_depth = prompt(stream, move || read_input(Some("Max Depth (empty for no limit): "))).await??;
+                    let max_depth = match max_depth.trim() {
+                        "" => None,
+                        max_depth => match max_depth.parse() {
+                            Ok(max_depth) => Some(max_depth),
+                            Err(_) => {
+                                println!("Invalid depth\n");
+                                continue;
+                            }
+                        }
+                    };
+
+                    match make_request(stream, buffer, Request::ReadRecursive { id, max_depth }).await? {
+                        Response::ReadRecursive(Ok(elements)) => {
+                            // Show each element by its path, as Glob does
+                            let elements: Vec<FSElement> = elements.into_iter()
+                                .map(|(path, mut element)| {
+                                    element.name = path.into_os_string();
+                                    element
+                                })
+                                .collect();
+                            println!("Elements:\n{}", format_elements(&elements))
+                        }
+                        Response::ReadRecursive(Err(err)) => {
+                            println!("Error while attempting to read cursor recursively: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                13 => {
+                    let order = match prompt(stream, move || read_input(Some("Sort By (name/size/modified/created/type): ")))
+                        .await??
+                        .trim()
+                    {
+                        "name" => SortOrder::Name,
+                        "size" => SortOrder::Size,
+                        "modified" => SortOrder::Modified,
+                        "created" => SortOrder::Created,
+                        "type" => SortOrder::FileType,
+                        _ => {
+                            println!("Unknown sort order\n");
+                            continue;
+                        }
+                    };
+
+                    let direction = match prompt(stream, move || read_input(Some("Direction (asc/desc): ")))
+                        .await??
+                        .trim()
+                    {
+                        "asc" => SortDirection::Ascending,
+                        "desc" => SortDirection::Descending,
+                        _ => {
+                            println!("Unknown direction\n");
+                            continue;
+                        }
+                    };
+
+                    let directories_first = prompt(stream, move || read_input(Some("Directories First (y/n): ")))
+                        .await??
+                        .trim()
+                        .eq_ignore_ascii_case("y");
+
+                    match make_request(stream, buffer, Request::SetSort { id, order, direction, directories_first }).await? {
+                        Response::SetSort(Ok(())) => {
+                            println!("Sort order changed\n");
+                        }
+                        Response::SetSort(Err(err)) => {
+                            println!("Error while attempting to change the sort order: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                14 => {
+                    let offset = prompt(stream, move || read_input(Some("Offset: "))).await??;
+                    let count = prompt(stream, move || read_input(Some("Count: "))).await??;
+                    let (Ok(offset), Ok(count)) = (offset.trim().parse(), count.trim().parse()) else {
+                        println!("Invalid offset or count\n");
+                        continue;
+                    };
+
+                    match make_request(stream, buffer, Request::ReadPage { id, offset, count }).await? {
+                        Response::ReadPage(Ok((elements, total))) => {
+                            println!("Elements {offset} to {} of {total}:\n{}", offset as usize + elements.len(), format_elements(&elements))
+                        }
+                        Response::ReadPage(Err(err)) => {
+                            println!("Error while attempting to read page: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                15 => {
+                    let source: PathBuf = prompt(stream, move || read_input(Some("Local File: ")))
+                        .await??
+                        .into();
+
+                    let dest_path: PathBuf = prompt(stream, move || read_input(Some("Upload To: ")))
+                        .await??
+                        .into();
+
+                    let data = match read_upload(&source).await {
+                        Ok(data) => data,
+                        Err(err) => {
+                            println!("Error while attempting to upload {source:?}: {err}\n");
+                            continue;
+                        }
+                    };
+
+                    let len = data.len();
+                    match make_request(stream, buffer, Request::Upload { id, dest_path: dest_path.clone(), data }).await? {
+                        Response::Upload(Ok(())) => {
+                            println!("Uploaded {len} bytes to {dest_path:?}\n");
+                        }
+                        Response::Upload(Err(err)) => {
+                            println!("Error while attempting to upload: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                16 => {
+                    match make_request(stream, buffer, Request::Back { id }).await? {
+                        Response::Back(Ok(path)) => {
+                            println!("Moved back to {path:?}\n");
+                        }
+                        Response::Back(Err(err)) => {
+                            println!("Error while attempting to move cursor back: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                17 => {
+                    match make_request(stream, buffer, Request::Forward { id }).await? {
+                        Response::Forward(Ok(path)) => {
+                            println!("Moved forward to {path:?}\n");
+                        }
+                        Response::Forward(Err(err)) => {
+                            println!("Error while attempting to move cursor forward: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                18 => {
+                    let pattern = prompt(stream, move || read_input(Some("Pattern (empty for everything): "))).await??;
+                    let case_insensitive = prompt(stream, move || read_input(Some("Ignore Case (y/n): ")))
+                        .await??
+                        .trim()
+                        .eq_ignore_ascii_case("y");
+
+                    match make_request(stream, buffer, Request::Search { id, pattern, case_insensitive }).await? {
+                        Response::Search(Ok(elements)) => {
+                            println!("Matches:\n{}", format_elements(&elements))
+                        }
+                        Response::Search(Err(err)) => {
+                            println!("Error while attempting to search: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                19 => {
+                    let pattern = prompt(stream, move || read_input(Some("Pattern (empty for everything): "))).await??;
+                    let case_insensitive = prompt(stream, move || read_input(Some("Ignore Case (y/n): ")))
+                        .await??
+                        .trim()
+                        .eq_ignore_ascii_case("y");
+                    let max_depth = prompt(stream, move || read_input(Some("Max Depth (empty for no limit): "))).await??;
+                    let max_depth = match max_depth.trim() {
+                        "" => None,
+                        max_depth => match max_depth.parse() {
+                            Ok(max_depth) => Some(max_depth),
+                            Err(_) => {
+                                println!("Invalid depth\n");
+                                continue;
+                            }
+                        }
+                    };
+
+                    match make_request(stream, buffer, Request::SearchRecursive { id, pattern, case_insensitive, max_depth }).await? {
+                        Response::SearchRecursive(Ok(elements)) => {
+                            // Show each element by its path, as Glob does
+                            let elements: Vec<FSElement> = elements.into_iter()
+                                .map(|(path, mut element)| {
+                                    element.name = path.into_os_string();
+                                    element
+                                })
+                                .collect();
+                            println!("Matches:\n{}", format_elements(&elements))
+                        }
+                        Response::SearchRecursive(Err(err)) => {
+                            println!("Error while attempting to search recursively: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                20 => {
+                    let path: PathBuf = prompt(stream, move || read_input(Some("Path: ")))
+                        .await??
+                        .into();
+
+                    let algorithm = match prompt(stream, move || read_input(Some("Algorithm (sha256/blake3): ")))
+                        .await??
+                        .trim()
+                    {
+                        "sha256" => ChecksumAlgorithm::Sha256,
+                        "blake3" => ChecksumAlgorithm::Blake3,
+                        _ => {
+                            println!("Unknown checksum algorithm\n");
+                            continue;
+                        }
+                    };
+
+                    let expected = prompt(stream, move || read_input(Some("Expected Checksum (empty to skip): "))).await??;
+
+                    match make_request(stream, buffer, Request::Checksum { id, path: path.clone(), algorithm }).await? {
+                        Response::Checksum(Ok(checksum)) => {
+                            let checksum = hex::encode(checksum);
+                            println!("{checksum}  {}", path.display());
+                            match expected.trim() {
+                                "" => println!(),
+                                expected if expected.eq_ignore_ascii_case(&checksum) => println!("The checksum matches\n"),
+                                _ => println!("The checksum does NOT match the expected value\n"),
+                            }
+                        }
+                        Response::Checksum(Err(err)) => {
+                            println!("Error while attempting to compute the checksum: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                21 => {
+                    let path: PathBuf = prompt(stream, move || read_input(Some("Path: ")))
+                        .await??
+                        .into();
+
+                    let destination: PathBuf = prompt(stream, move || read_input(Some("Save As: ")))
+                        .await??
+                        .into();
+
+                    let transfer_id = match make_request(stream, buffer, Request::StartStream { id, path, chunk_size: 0 }).await? {
+                        Response::StartStream(Ok((transfer_id, total_bytes))) => {
+                            println!("Downloading {total_bytes} bytes");
+                            transfer_id
+                        }
+                        Response::StartStream(Err(err)) => {
+                            println!("Error while attempting to start the download: {err}\n");
+                            continue;
+                        }
+                        _ => bail!("Unexpected response type")
+                    };
+
+                    // Each chunk is written out before the next one is requested, so the file is never held in memory
+                    let mut file = tokio::fs::File::create(&destination).await?;
+                    let mut saved = 0;
+                    loop {
+                        match make_request(stream, buffer, Request::NextChunk { transfer_id }).await? {
+                            Response::NextChunk(Ok((data, bytes_remaining))) => {
+                                file.write_all(&data).await?;
+                                saved += data.len();
+                                if bytes_remaining == 0 {
+                                    file.flush().await?;
+                                    println!("Saved {saved} bytes to {destination:?}\n");
+                                    break;
+                                }
+                            }
+                            Response::NextChunk(Err(err)) => {
+                                println!("Error while attempting to download: {err}\n");
+                                break;
+                            }
+                            _ => bail!("Unexpected response type")
+                        }
+                    }
+                }
+                22 => {
                     selected_cursor = None;
                 }
                 _ => unreachable!()
             }
         } else {
             let commands = root_commands.clone();
-            match tokio::task::spawn_blocking(move || ask_for_command_selection(&commands)).await?? {
+            match prompt(stream, move || ask_for_command_selection(&commands)).await?? {
                 1 => {
-                    match make_request(&mut stream, &mut buffer, Request::Create).await? {
+                    match make_request(stream, buffer, Request::Create).await? {
                         Response::Create(Ok(id)) => {
                             println!("Cursor {id} created!\n");
                             cursors.push(id);
@@ -140,11 +1062,11 @@ async fn main() -> Result<(), anyhow::Error> {
                         .map(|id| format!("Cursor {id}"))
                         .collect();
 
-                    let selection: usize = tokio::task::spawn_blocking(move || ask_for_command_selection(&commands)).await??
+                    let selection: usize = prompt(stream, move || ask_for_command_selection(&commands)).await??
                         .try_into()
                         .unwrap();
 
-                    match make_request(&mut stream, &mut buffer, Request::Destroy { id: cursors[selection - 1] }).await? {
+                    match make_request(stream, buffer, Request::Destroy { id: cursors[selection - 1] }).await? {
                         Response::Destroy(Ok(())) => {
                             println!("Cursor {selection} successfully destroyed\n");
                             cursors.remove(selection - 1);
@@ -177,7 +1099,7 @@ async fn main() -> Result<(), anyhow::Error> {
                         .map(|id| format!("Cursor {id}"))
                         .collect();
 
-                    let selection: usize = tokio::task::spawn_blocking(move || ask_for_command_selection(&commands)).await??
+                    let selection: usize = prompt(stream, move || ask_for_command_selection(&commands)).await??
                         .try_into()
                         .unwrap();
                     
@@ -185,7 +1107,45 @@ async fn main() -> Result<(), anyhow::Error> {
                     println!("Selected cursor {id}\n");
                     selected_cursor = Some(id);
                 }
-                5 => break,
+                5 => {
+                    let show = prompt(stream, move || read_input(Some("Show hidden files? (y/n): "))).await??
+                        .trim()
+                        .eq_ignore_ascii_case("y");
+
+                    match make_request(stream, buffer, Request::SetShowHidden { show }).await? {
+                        Response::SetShowHidden(Ok(())) => {
+                            println!("Hidden files will {}be shown\n", if show {""} else {"not "});
+                        }
+                        Response::SetShowHidden(Err(err)) => {
+                            println!("Error while attempting to change the setting: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                6 => {
+                    let follow = prompt(stream, move || read_input(Some("Follow symlinks? (y/n): "))).await??
+                        .trim()
+                        .eq_ignore_ascii_case("y");
+
+                    match make_request(stream, buffer, Request::SetFollowSymlinks { follow }).await? {
+                        Response::SetFollowSymlinks(Ok(())) => {
+                            println!("Symlinks will {}be followed\n", if follow {""} else {"not "});
+                        }
+                        Response::SetFollowSymlinks(Err(err)) => {
+                            println!("Error while attempting to change the setting: {err}\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                7 => {
+                    match make_request(stream, buffer, Request::HealthCheck).await? {
+                        Response::HealthCheck { uptime_secs } => {
+                            println!("The server has been running for {uptime_secs} seconds\n");
+                        }
+                        _ => bail!("Unexpected response type")
+                    }
+                }
+                8 => break,
                 _ => unreachable!()
             }
         }
@@ -0,0 +1,56 @@
+//! Settings for the server, read from a TOML file given with `--config`
+
+use std::{net::SocketAddr, path::{Path, PathBuf}, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// The server's settings. Keys left out of the file keep their default values
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address the server listens on
+    pub bind_addr: SocketAddr,
+    /// The most Cursors each connection may have at once
+    pub cursor_limit: u16,
+    /// Paths added to the file system before the server starts listening. Each must be absolute
+    pub initial_paths: Vec<PathBuf>,
+    /// Cursors which no request has named for this many seconds are destroyed. TOML has no null, so 0 is used to
+    /// turn this off
+    pub idle_cursor_timeout_secs: Option<u64>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addr: SocketAddr::from(([127, 0, 0, 1], 8000)),
+            cursor_limit: 16,
+            initial_paths: vec![],
+            idle_cursor_timeout_secs: Some(1800)
+        }
+    }
+}
+
+impl Config {
+    /// Read and validate the settings in a TOML file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+        Config::parse(&toml).with_context(|| format!("{} is not a valid configuration", path.display()))
+    }
+
+    /// Parse and validate settings written as TOML
+    pub fn parse(toml: &str) -> Result<Self, anyhow::Error> {
+        let config: Config = toml::from_str(toml)?;
+        if let Some(path) = config.initial_paths.iter().find(|path| !path.is_absolute()) {
+            anyhow::bail!("The initial path {} is not absolute", path.display());
+        }
+
+        Ok(config)
+    }
+
+    /// How long a Cursor may go unused before it is destroyed, or None if idle Cursors are kept
+    pub fn idle_cursor_timeout(&self) -> Option<Duration> {
+        self.idle_cursor_timeout_secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+    }
+}
@@ -0,0 +1,23 @@
+//! Helpers for displaying file system elements to people
+
+use std::sync::OnceLock;
+
+use time::{format_description::{self, OwnedFormatItem}, OffsetDateTime};
+
+/// How timestamps are displayed, such as 2023-04-25 13:05:09
+pub const TIMESTAMP_FORMAT: &str = "[year]-[month]-[day] [hour]:[minute]:[second]";
+
+/// Shown in place of a timestamp the file system could not provide
+pub const UNKNOWN_TIMESTAMP: &str = "unknown";
+
+/// Format a timestamp with [`TIMESTAMP_FORMAT`], or [`UNKNOWN_TIMESTAMP`] if there is none
+pub fn format_timestamp(time: Option<OffsetDateTime>) -> String {
+    // The description is parsed once, the first time a timestamp is formatted
+    static FORMAT: OnceLock<OwnedFormatItem> = OnceLock::new();
+    let format = FORMAT.get_or_init(|| {
+        format_description::parse_owned::<2>(TIMESTAMP_FORMAT).expect("TIMESTAMP_FORMAT is a valid format description")
+    });
+
+    time.and_then(|time| time.format(format).ok())
+        .unwrap_or_else(|| UNKNOWN_TIMESTAMP.into())
+}
@@ -1,11 +1,21 @@
-use std::{ffi::OsString, path::Path};
+use std::{borrow::Cow, cmp::Ordering, ffi::OsString, hash::{Hash, Hasher}, path::{Path, PathBuf}, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
+use thiserror::Error;
 use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
 
 pub mod mapped_fs;
+pub mod mem_fs;
+pub mod composite_fs;
 pub mod browser;
+pub mod archive;
+pub mod sort;
+pub mod checksum;
+pub mod open_files;
+pub mod cursor_quota;
+pub mod util;
 
 /// Represents a file/directory in a file system
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -15,13 +25,272 @@ pub struct FSElement {
     pub modified: Option<OffsetDateTime>,
     pub size: u64,
     /// True if the element is a file, false if it is a directory
-    pub is_file: bool
+    pub is_file: bool,
+    /// True if the element is a symbolic link. The other fields describe the link's target unless symbolic
+    /// links are not being followed
+    #[serde(default)]
+    pub is_symlink: bool,
+    /// A hash of the contents of a file, if the file system provides one. [`mapped_fs::MappedFS`] leaves it empty
+    #[serde(default)]
+    pub hash: Option<Vec<u8>>,
+    /// The Unix permission bits and file type, as in `st_mode`. None on other platforms
+    #[serde(default)]
+    pub mode: Option<u32>,
+    /// The user ID of the owner. None on other platforms
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// The group ID of the owner. None on other platforms
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// The inode number. None on other platforms
+    #[serde(default)]
+    pub inode: Option<u64>
 }
 
+impl FSElement {
+    /// How long ago the element was last modified, or None if the modification time is unknown. Modification
+    /// times in the future count as an age of zero
+    pub fn age(&self) -> Option<Duration> {
+        let age = OffsetDateTime::now_utc() - self.modified?;
+        Some(age.try_into().unwrap_or_default())
+    }
+
+    /// The name as a str, or None if it is not valid Unicode
+    pub fn name_str(&self) -> Option<&str> {
+        self.name.to_str()
+    }
+
+    /// The name with any invalid Unicode replaced by U+FFFD
+    pub fn name_lossy(&self) -> Cow<'_, str> {
+        self.name.to_string_lossy()
+    }
+
+    /// Identifies the contents of the element, so files with the same contents can be found regardless of
+    /// their paths. None if the element has no hash
+    pub fn content_id(&self) -> Option<&[u8]> {
+        self.hash.as_deref()
+    }
+
+    /// The size formatted with binary prefixes, such as "1.5 KiB"
+    pub fn size_human(&self) -> String {
+        format_size(self.size, 1024, &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"])
+    }
+
+    /// The size formatted with SI prefixes, such as "1.5 KB"
+    pub fn size_human_si(&self) -> String {
+        format_size(self.size, 1000, &["B", "KB", "MB", "GB", "TB", "PB", "EB"])
+    }
+}
+
+/// Elements are ordered by name, matching the default sort order of a Cursor. Elements with the same name are
+/// ordered by their remaining fields, so that only equal elements compare as equal
+impl Ord for FSElement {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let tie_breakers = |element: &FSElement| (
+            element.is_file, element.size, element.modified, element.created, element.is_symlink, element.mode,
+            element.uid, element.gid, element.inode
+        );
+        self.name.cmp(&other.name)
+            .then_with(|| tie_breakers(self).cmp(&tie_breakers(other)))
+            .then_with(|| self.hash.cmp(&other.hash))
+    }
+}
+
+impl PartialOrd for FSElement {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Only the name, size and type are hashed. Timestamps are left out because they vary between listings of the same
+/// file. Every hashed field is also compared by `==`, so equal elements always hash the same. Elements which differ
+/// only in their timestamps hash the same but are not equal, so a `HashSet` keeps both; group them with
+/// [`util::deduplicate`] or key a map by `(name, size, is_file)` to treat them as one
+impl Hash for FSElement {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.size.hash(state);
+        self.is_file.hash(state);
+    }
+}
+
+/// Whether [`FS::list_recursive`] lists the contents of `element`, which was found `depth` levels below the path
+/// being listed
+fn enters(element: &FSElement, depth: usize, max_depth: Option<usize>) -> bool {
+    !element.is_file && !element.is_symlink && !matches!(max_depth, Some(max_depth) if depth >= max_depth)
+}
+
+/// Format `size` using the largest unit it reaches, with one decimal place for everything but bytes
+fn format_size(size: u64, base: u64, units: &[&str]) -> String {
+    if size < base {
+        return format!("{size} {}", units[0]);
+    }
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= base as f64 && unit < units.len() - 1 {
+        value /= base as f64;
+        unit += 1;
+    }
+    format!("{value:.1} {}", units[unit])
+}
+
+#[cfg(feature = "json")]
+impl FSElement {
+    /// Serialize the element to JSON, for tools which consume JSON rather than the binary protocol
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Deserialize an element previously serialized with [`FSElement::to_json`]
+    pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+}
+
+/// Options which change how [`FS::list_with_options`] reads a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListOptions {
+    /// Describe the target of each symbolic link rather than the link itself
+    pub follow_symlinks: bool,
+    /// What to do with entries whose metadata cannot be read
+    pub mode: ListMode
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        ListOptions { follow_symlinks: true, mode: ListMode::SkipErrors }
+    }
+}
+
+/// How a listing treats entries which cannot be read, such as broken symbolic links or entries deleted while
+/// the directory is being listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMode {
+    /// Leave the entry out of the listing
+    SkipErrors,
+    /// Fail the whole listing with the first error
+    FailOnError
+}
+
+/// Returned by a file system which does not support an operation, such as writing to a read-only backend. Names the
+/// operation
+#[derive(Error, Debug)]
+#[error("The file system does not support {0}")]
+pub struct Unsupported(pub &'static str);
+
 #[async_trait]
-pub trait FS {
-    type Error: std::error::Error;
+pub trait FS: Send + Sync {
+    type Error: std::error::Error + From<Unsupported> + Send + Sync + 'static;
 
     /// List the elements at a specified path within the file system
     async fn list<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<Vec<FSElement>, Self::Error>;
+
+    /// List the elements at a specified path, honouring the given options where the file system supports them
+    async fn list_with_options<P: AsRef<Path> + Send + Sync>(&self, path: P, _options: ListOptions) -> Result<Vec<FSElement>, Self::Error> {
+        self.list(path).await
+    }
+
+    /// List the elements at a specified path, stopping early once `cancel` is cancelled, for example because
+    /// the client which asked for the listing has disconnected. File systems which cannot stop early list the
+    /// whole path
+    async fn list_cancellable<P: AsRef<Path> + Send + Sync>(&self, path: P, _cancel: CancellationToken) -> Result<Vec<FSElement>, Self::Error> {
+        self.list(path).await
+    }
+
+    /// List every element below a specified path, each paired with its path within the file system and ordered by
+    /// that path. `max_depth` limits how many levels of subdirectories are entered, so Some(0) lists the same
+    /// elements as [`FS::list`] and None lists the whole tree. Symbolic links are listed but never entered, so a
+    /// link to one of its own parents cannot make the listing endless. Fails if any directory cannot be listed
+    async fn list_recursive<P: AsRef<Path> + Send + Sync>(&self, path: P, max_depth: Option<usize>) -> Result<Vec<(PathBuf, FSElement)>, Self::Error> {
+        let mut output = vec![];
+        let mut pending = vec![(path.as_ref().to_owned(), 0)];
+        while let Some((directory, depth)) = pending.pop() {
+            for element in self.list(&directory).await? {
+                let element_path = directory.join(&element.name);
+                if enters(&element, depth, max_depth) {
+                    pending.push((element_path.clone(), depth + 1));
+                }
+                output.push((element_path, element));
+            }
+        }
+
+        output.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+        Ok(output)
+    }
+
+    /// Obtain the element at a specified path within the file system without listing its parent
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<FSElement, Self::Error>;
+
+    /// Replace the contents of the file at a specified path with `data`, creating the file if it does not exist.
+    /// File systems which cannot be written to keep this default, which fails with [`Unsupported`]
+    async fn write<P: AsRef<Path> + Send + Sync>(&self, _path: P, _data: &[u8]) -> Result<(), Self::Error> {
+        Err(Unsupported("writing").into())
+    }
+
+    /// Rename or move the element at `from` to `to`, replacing a file already at `to`. File systems which cannot
+    /// be modified keep this default, which fails with [`Unsupported`]
+    async fn rename<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, _from: P, _to: Q) -> Result<(), Self::Error> {
+        Err(Unsupported("renaming").into())
+    }
+
+    /// Rename like [`FS::rename`], but fail if anything is already at `to`, with an io::Error of kind `AlreadyExists`
+    /// among the sources of the error. Nothing created at `to` while the rename is under way may be replaced
+    async fn rename_no_replace<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, _from: P, _to: Q) -> Result<(), Self::Error> {
+        Err(Unsupported("renaming").into())
+    }
+
+    /// Resolve a path within the file system to the matching path within the real file system
+    fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::Error>;
+
+    /// Returns true if clients may modify the path. File systems without access control are always writable
+    fn is_writable<P: AsRef<Path>>(&self, _path: P) -> bool {
+        true
+    }
+}
+
+/// Lets several owners, such as one Browser per connection, share a single file system
+#[async_trait]
+impl<F: FS> FS for Arc<F> {
+    type Error = F::Error;
+
+    async fn list<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<Vec<FSElement>, Self::Error> {
+        F::list(self, path).await
+    }
+
+    async fn list_with_options<P: AsRef<Path> + Send + Sync>(&self, path: P, options: ListOptions) -> Result<Vec<FSElement>, Self::Error> {
+        F::list_with_options(self, path, options).await
+    }
+
+    async fn list_cancellable<P: AsRef<Path> + Send + Sync>(&self, path: P, cancel: CancellationToken) -> Result<Vec<FSElement>, Self::Error> {
+        F::list_cancellable(self, path, cancel).await
+    }
+
+    async fn list_recursive<P: AsRef<Path> + Send + Sync>(&self, path: P, max_depth: Option<usize>) -> Result<Vec<(PathBuf, FSElement)>, Self::Error> {
+        F::list_recursive(self, path, max_depth).await
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<FSElement, Self::Error> {
+        F::metadata(self, path).await
+    }
+
+    async fn write<P: AsRef<Path> + Send + Sync>(&self, path: P, data: &[u8]) -> Result<(), Self::Error> {
+        F::write(self, path, data).await
+    }
+
+    async fn rename<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), Self::Error> {
+        F::rename(self, from, to).await
+    }
+
+    async fn rename_no_replace<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), Self::Error> {
+        F::rename_no_replace(self, from, to).await
+    }
+
+    fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::Error> {
+        F::unmap(self, path)
+    }
+
+    fn is_writable<P: AsRef<Path>>(&self, path: P) -> bool {
+        F::is_writable(self, path)
+    }
 }
@@ -0,0 +1,140 @@
+use std::{path::{Path, PathBuf}, io::{self, Cursor, Read, Write}, fs::{File, Metadata}};
+
+use flate2::{write::GzEncoder, Compression};
+use serde::{Serialize, Deserialize};
+use zip::{ZipWriter, write::FileOptions};
+
+/// The formats which the server is able to create archives in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz
+}
+
+/// Bounds on the work done to build a single archive
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    /// Directories this many levels below an entry are added, but their contents are not
+    pub max_depth: u32,
+    /// The most bytes of file contents the archive may hold before compression
+    pub max_size: u64
+}
+
+/// Something found while walking the entries of an archive
+enum ArchiveItem {
+    Dir { name: PathBuf, metadata: Metadata },
+    File { name: PathBuf, real_path: PathBuf }
+}
+
+/// Build an in-memory archive. Each entry is a pair of the name to use within the archive and the path of the
+/// file or directory in the real file system. Directories are added recursively. Symbolic links are never
+/// followed or added, so an archive cannot loop forever or reach outside of the entries it was given. Fails if
+/// the files add up to more than `limits.max_size`. This performs blocking I/O
+pub fn build_archive(format: ArchiveFormat, entries: &[(PathBuf, PathBuf)], limits: ArchiveLimits) -> Result<Vec<u8>, io::Error> {
+    let items = walk_entries(entries, limits)?;
+    match format {
+        ArchiveFormat::Zip => build_zip(&items, limits.max_size),
+        ArchiveFormat::TarGz => build_tar_gz(&items, limits.max_size),
+    }
+}
+
+/// List everything which goes into the archive, so the size limit is checked before anything is read
+fn walk_entries(entries: &[(PathBuf, PathBuf)], limits: ArchiveLimits) -> Result<Vec<ArchiveItem>, io::Error> {
+    let mut items = vec![];
+    let mut total_size = 0;
+
+    for (name, real_path) in entries {
+        // Walk the tree with an explicit stack rather than recursing
+        let mut pending = vec![(name.to_owned(), real_path.to_owned(), 0)];
+        while let Some((name, real_path, depth)) = pending.pop() {
+            // Symbolic links are skipped, as are sockets and pipes, which could block forever when read
+            let metadata = std::fs::symlink_metadata(&real_path)?;
+            if metadata.is_dir() {
+                if depth < limits.max_depth {
+                    for entry in std::fs::read_dir(&real_path)? {
+                        let entry = entry?;
+                        pending.push((name.join(entry.file_name()), entry.path(), depth + 1));
+                    }
+                }
+                items.push(ArchiveItem::Dir { name, metadata });
+            } else if metadata.is_file() {
+                total_size += metadata.len();
+                check_size(total_size, limits.max_size)?;
+                items.push(ArchiveItem::File { name, real_path });
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+fn check_size(size: u64, max_size: u64) -> Result<(), io::Error> {
+    if size > max_size {
+        return Err(io::Error::other(format!("The files are larger than the limit of {max_size} bytes")));
+    }
+    Ok(())
+}
+
+/// Open a file for the archive, counting its size towards `total_size` again, since it may have grown since the
+/// walk. Only as many bytes as the file held when it was opened are read
+fn open_file(real_path: &Path, total_size: &mut u64, max_size: u64) -> Result<(Metadata, impl Read), io::Error> {
+    let file = File::open(real_path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    *total_size += len;
+    check_size(*total_size, max_size)?;
+    Ok((metadata, file.take(len)))
+}
+
+fn build_zip(items: &[ArchiveItem], max_size: u64) -> Result<Vec<u8>, io::Error> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default();
+    let mut total_size = 0;
+
+    for item in items {
+        match item {
+            ArchiveItem::Dir { name, .. } => writer.add_directory(zip_entry_name(name), options)?,
+            ArchiveItem::File { name, real_path } => {
+                let (_, mut file) = open_file(real_path, &mut total_size, max_size)?;
+                writer.start_file(zip_entry_name(name), options)?;
+                io::copy(&mut file, &mut writer)?;
+            }
+        }
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+fn zip_entry_name(name: &Path) -> String {
+    // Zip entry names always use '/' as the separator
+    name.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn build_tar_gz(items: &[ArchiveItem], max_size: u64) -> Result<Vec<u8>, io::Error> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    builder.follow_symlinks(false);
+    let mut total_size = 0;
+
+    for item in items {
+        match item {
+            ArchiveItem::Dir { name, metadata } => {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(metadata);
+                builder.append_data(&mut header, name, io::empty())?;
+            }
+            ArchiveItem::File { name, real_path } => {
+                let (metadata, file) = open_file(real_path, &mut total_size, max_size)?;
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&metadata);
+                builder.append_data(&mut header, name, file)?;
+            }
+        }
+    }
+
+    let mut encoder = builder.into_inner()?;
+    encoder.flush()?;
+    encoder.finish()
+}
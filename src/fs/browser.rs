@@ -1,16 +1,34 @@
 use std::{
-    collections::{HashMap, hash_map::Entry},
-    path::{Path, PathBuf}, cmp::Ordering,
+    borrow::Cow, collections::{HashMap, BTreeSet}, error::Error, ffi::{OsStr, OsString}, future::Future, panic::AssertUnwindSafe,
+    net::SocketAddr, path::{Path, PathBuf, Component}, io, sync::{atomic::AtomicU32, Arc}, time::{Duration, Instant, SystemTime},
 };
 
-use rand::{distributions::Uniform, prelude::Distribution, rngs::SmallRng, SeedableRng};
+use futures::{FutureExt, Stream, stream};
+use rand::{distributions::Uniform, prelude::Distribution, rngs::{OsRng, SmallRng}, SeedableRng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::Instrument;
+use tokio::{io::AsyncReadExt, task::{JoinSet, JoinError}};
 
-use super::FSElement;
+use super::{FSElement, ListOptions, ListMode};
+use super::mapped_fs::get_element;
+use super::archive::{ArchiveFormat, ArchiveLimits, build_archive};
+use super::sort::{SortKey, SortOrder, SortDirection, CursorSort};
+use super::checksum::{ChecksumAlgorithm, checksum_file};
+use super::open_files::{OpenFileLimit, OpenFilePermit};
+use super::cursor_quota::CursorQuota;
+use crate::{acl::Acl, protocol::{Capability, crate_version, server_capabilities}};
 
 use super::FS;
 
+use self::cursor_store::CursorStore;
+
+pub mod cursor_store;
+pub mod handle;
+pub mod request_builder;
+
 #[derive(Deserialize, Serialize)]
 pub enum Request {
     // Create a new Cursor and return it's ID
@@ -20,147 +38,1930 @@ pub enum Request {
 
     // Read the file data from the Cursor's current position
     Read { id: u16 },
+    // Read the Cursor's current position one element per frame, unsorted
+    ReadStreaming { id: u16 },
+    // Read up to count elements at the Cursor's current position, starting at offset
+    ReadPage { id: u16, offset: u32, count: u32 },
+    // Read every element below the Cursor's location, entering at most max_depth levels of subdirectories
+    ReadRecursive { id: u16, max_depth: Option<u32> },
+
+    // Get the metadata of a single element. Relative paths are resolved against the Cursor's location
+    Stat { id: u16, path: PathBuf },
 
     // Get the current location (path) of the Cursor
     GetLocation { id: u16 },
-    // Move the cursor to a new location
-    Move { id: u16, path: PathBuf },
+    // Move the cursor to a new location. Called Move before protocol changes in MIGRATION.md
+    Navigate { id: u16, path: PathBuf },
+    // Move the cursor to the location it was at before the last Navigate, like a web browser's back button
+    Back { id: u16 },
+    // Undo a Back, returning the cursor to the location it left
+    Forward { id: u16 },
+
+    // Copy a file or directory. Relative paths are resolved against the Cursor's location
+    Copy { id: u16, from: PathBuf, to: PathBuf },
+    // Give a file or directory a new name within the same directory. The path is resolved against the Cursor's
+    // location, and new_name must be a single path component
+    Rename { id: u16, path: PathBuf, new_name: OsString },
+
+    // Create an archive of the given paths. Relative paths are resolved against the Cursor's location
+    Archive { id: u16, paths: Vec<PathBuf>, format: ArchiveFormat },
+    // Download the contents of a file. Relative paths are resolved against the Cursor's location
+    Download { id: u16, path: PathBuf },
+    // Write a file, replacing it if it exists. Relative paths are resolved against the Cursor's location
+    Upload {
+        id: u16,
+        dest_path: PathBuf,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>
+    },
+    // Open a file to be read in pieces of chunk_size bytes, each requested with NextChunk. A chunk_size of 0 uses the
+    // server's default. Relative paths are resolved against the Cursor's location
+    StartStream { id: u16, path: PathBuf, chunk_size: u32 },
+    // Read the next piece of a file opened with StartStream
+    NextChunk { transfer_id: u32 },
+    // Close a file opened with StartStream before all of it has been read
+    CancelStream { transfer_id: u32 },
+
+    // Compute the checksums of several files at once. Relative paths are resolved against the Cursor's location
+    ChecksumMany { id: u16, paths: Vec<PathBuf>, algorithm: ChecksumAlgorithm },
+    // Compute the checksum of a single file, for verifying a download. Relative paths are resolved against the
+    // Cursor's location
+    Checksum { id: u16, path: PathBuf, algorithm: ChecksumAlgorithm },
+
+    // Choose how a Cursor orders the elements it reads
+    SetSort { id: u16, order: SortOrder, direction: SortDirection, directories_first: bool },
+
+    // Choose whether elements with names starting with '.' are included when reading any Cursor
+    SetShowHidden { show: bool },
+    // Choose whether symbolic links are described by their target when reading any Cursor
+    SetFollowSymlinks { follow: bool },
+
+    // Find the elements matching a glob pattern relative to the Cursor's location
+    Glob { id: u16, pattern: String },
+    // Read the elements at the Cursor's location whose names match a glob pattern. An empty pattern matches everything
+    Search { id: u16, pattern: String, case_insensitive: bool },
+    // Find the elements below the Cursor's location whose names match a glob pattern, entering at most max_depth
+    // levels of subdirectories
+    SearchRecursive { id: u16, pattern: String, case_insensitive: bool, max_depth: Option<u32> },
+
+    // A custom request handled by the server plugin registered for type_id
+    Plugin {
+        type_id: u16,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>
+    },
+
+    // Sent periodically to show the client is still connected. The server does not respond
+    Heartbeat,
+
+    // Check that the server is running and obtain its status
+    HealthCheck,
+
+    // Obtain the server's version and the optional features it supports
+    GetServerInfo,
+
+    // Sent by a client which is done. The server answers and then closes the connection
+    Goodbye,
+}
+
+impl Request {
+    /// The ID of the Cursor the request is made through, if it is made through one
+    pub fn cursor_id(&self) -> Option<u16> {
+        match self {
+            Request::Destroy { id }
+            | Request::Read { id }
+            | Request::ReadStreaming { id }
+            | Request::ReadPage { id, .. }
+            | Request::ReadRecursive { id, .. }
+            | Request::Stat { id, .. }
+            | Request::GetLocation { id }
+            | Request::Navigate { id, .. }
+            | Request::Back { id }
+            | Request::Forward { id }
+            | Request::Copy { id, .. }
+            | Request::Rename { id, .. }
+            | Request::Archive { id, .. }
+            | Request::Download { id, .. }
+            | Request::Upload { id, .. }
+            | Request::StartStream { id, .. }
+            | Request::ChecksumMany { id, .. }
+            | Request::Checksum { id, .. }
+            | Request::SetSort { id, .. }
+            | Request::Glob { id, .. }
+            | Request::Search { id, .. }
+            | Request::SearchRecursive { id, .. } => Some(*id),
+            Request::Create
+            | Request::NextChunk { .. }
+            | Request::CancelStream { .. }
+            | Request::SetShowHidden { .. }
+            | Request::SetFollowSymlinks { .. }
+            | Request::Plugin { .. }
+            | Request::Heartbeat
+            | Request::HealthCheck
+            | Request::GetServerInfo
+            | Request::Goodbye => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
-pub enum Response {
+pub enum Response<'a> {
     // On success, returns the ID of the cursor
     Create(Result<u16, CursorError>),
 
     // The Ok(()) value means the cursor was destroyed successfully
     Destroy(Result<(), CursorError>),
 
-    // Returns a list of the file system elements that were read
-    Read(Result<Vec<FSElement>, CursorError>),
+    // Returns the Cursor's location and the file system elements that were read there. The Browser lends its
+    // cached listing, so it is not copied before it is serialized
+    Read(Result<(PathBuf, Cow<'a, Vec<FSElement>>), CursorError>),
+    // Returns the elements of the requested page and the total number of elements at the Cursor's location. A page
+    // past the end is empty
+    ReadPage(Result<(Vec<FSElement>, u32), CursorError>),
+    // Returns every element below the Cursor's location with its path within the file system, ordered by path
+    ReadRecursive(Result<Vec<(PathBuf, FSElement)>, CursorError>),
+    // Returns the element at the requested path
+    Stat(Result<FSElement, CursorError>),
+    // A single element of a streaming read
+    Element(FSElement),
+    // Sent after the last Element of a streaming read. Returns an error if the read stopped early
+    EndOfStream(Result<(), CursorError>),
 
     // Only fails if the cursor ID is wrong
     GetLocation(Result<PathBuf, CursorError>),
     // Only fails if the cursor ID is wrong
-    Move(Result<(), CursorError>)
+    Navigate(Result<(), CursorError>),
+    // Returns the Cursor's new location
+    Back(Result<PathBuf, CursorError>),
+    // Returns the Cursor's new location
+    Forward(Result<PathBuf, CursorError>),
+
+    // The Ok(()) value means the copy completed successfully
+    Copy(Result<(), CursorError>),
+    // The Ok(()) value means the rename completed successfully
+    Rename(Result<(), CursorError>),
+
+    // On success, returns the ID of the download. The archive data follows as DownloadChunk frames
+    Archive(Result<u32, CursorError>),
+    // On success, returns the ID of the download. The file's contents follow as DownloadChunk frames
+    Download(Result<u32, CursorError>),
+    // The Ok(()) value means the file was written completely
+    Upload(Result<(), CursorError>),
+    // On success, returns the ID of the transfer and the size of the file in bytes
+    StartStream(Result<(u32, u64), CursorError>),
+    // Returns the next piece of the file and the number of bytes left after it. The transfer ends once no bytes are
+    // left, or when reading fails
+    NextChunk(Result<(ByteBuf, u64), CursorError>),
+    // Only fails if the transfer ID is wrong
+    CancelStream(Result<(), CursorError>),
+
+    // A piece of a download sent by the server without a matching request
+    DownloadChunk {
+        download_id: u32,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        bytes_remaining: u64
+    },
+
+    // Returns each requested path with its hex encoded checksum, or with "ERROR: <reason>" if it failed
+    ChecksumMany(Result<Vec<(PathBuf, String)>, CursorError>),
+    // Returns the raw bytes of the file's checksum
+    Checksum(Result<Vec<u8>, CursorError>),
+
+    // Only fails if the cursor ID is wrong
+    SetSort(Result<(), CursorError>),
+
+    // The Ok(()) value means the setting was changed
+    SetShowHidden(Result<(), CursorError>),
+    // The Ok(()) value means the setting was changed
+    SetFollowSymlinks(Result<(), CursorError>),
+
+    // Returns the matching elements. Each name is the path of the element relative to the Cursor's location
+    Glob(Result<Vec<FSElement>, CursorError>),
+    // Returns the matching elements in the order of a Read
+    Search(Result<Vec<FSElement>, CursorError>),
+    // Returns each matching element with its path within the file system, ordered by path
+    SearchRecursive(Result<Vec<(PathBuf, FSElement)>, CursorError>),
+
+    // Returns the data produced by the plugin
+    Plugin(Result<ByteBuf, CursorError>),
+
+    // Sent periodically to show the server is still connected. It does not answer any request
+    Heartbeat,
+
+    // Returns how long the server has been running
+    HealthCheck { uptime_secs: u64 },
+
+    // Returns the server's version as [major, minor, patch] and the optional features it supports
+    GetServerInfo { version: [u16; 3], capabilities: Vec<Capability> },
+
+    // The last frame before the server closes the connection at the client's request
+    Goodbye,
+
+    // Sent without a request when the server is shutting down, as the last frame before it closes the connection
+    Shutdown { reason: String },
+
+    // The server did not recognise the request, for example because the client is newer than the server.
+    // Contains the name of the request type
+    UnknownRequest { name: String }
+}
+
+impl Response<'_> {
+    /// The error carried by a failed response, if any
+    pub fn error_mut(&mut self) -> Option<&mut CursorError> {
+        match self {
+            Response::Create(Err(err)) => Some(err),
+            Response::Destroy(Err(err))
+            | Response::EndOfStream(Err(err))
+            | Response::Navigate(Err(err))
+            | Response::Copy(Err(err))
+            | Response::Rename(Err(err))
+            | Response::Upload(Err(err))
+            | Response::CancelStream(Err(err))
+            | Response::SetSort(Err(err))
+            | Response::SetShowHidden(Err(err))
+            | Response::SetFollowSymlinks(Err(err)) => Some(err),
+            Response::Read(Err(err))
+            | Response::ReadPage(Err(err))
+            | Response::ReadRecursive(Err(err))
+            | Response::Glob(Err(err))
+            | Response::Search(Err(err))
+            | Response::SearchRecursive(Err(err)) => Some(err),
+            Response::Stat(Err(err)) => Some(err),
+            Response::GetLocation(Err(err))
+            | Response::Back(Err(err))
+            | Response::Forward(Err(err)) => Some(err),
+            Response::Archive(Err(err)) | Response::Download(Err(err)) => Some(err),
+            Response::StartStream(Err(err)) => Some(err),
+            Response::NextChunk(Err(err)) => Some(err),
+            Response::ChecksumMany(Err(err)) | Response::Checksum(Err(err)) => Some(err),
+            Response::Plugin(Err(err)) => Some(err),
+            _ => None
+        }
+    }
+
+    /// Copy any borrowed data, so the response no longer borrows the Browser which produced it
+    pub fn into_owned(self) -> Response<'static> {
+        match self {
+            Response::Read(result) => Response::Read(result.map(|(path, elements)| (path, Cow::Owned(elements.into_owned())))),
+            Response::Create(result) => Response::Create(result),
+            Response::Destroy(result) => Response::Destroy(result),
+            Response::ReadPage(result) => Response::ReadPage(result),
+            Response::ReadRecursive(result) => Response::ReadRecursive(result),
+            Response::Stat(result) => Response::Stat(result),
+            Response::Element(element) => Response::Element(element),
+            Response::EndOfStream(result) => Response::EndOfStream(result),
+            Response::GetLocation(result) => Response::GetLocation(result),
+            Response::Navigate(result) => Response::Navigate(result),
+            Response::Back(result) => Response::Back(result),
+            Response::Forward(result) => Response::Forward(result),
+            Response::Copy(result) => Response::Copy(result),
+            Response::Rename(result) => Response::Rename(result),
+            Response::Archive(result) => Response::Archive(result),
+            Response::Download(result) => Response::Download(result),
+            Response::Upload(result) => Response::Upload(result),
+            Response::StartStream(result) => Response::StartStream(result),
+            Response::NextChunk(result) => Response::NextChunk(result),
+            Response::CancelStream(result) => Response::CancelStream(result),
+            Response::DownloadChunk { download_id, data, bytes_remaining } =>
+                Response::DownloadChunk { download_id, data, bytes_remaining },
+            Response::ChecksumMany(result) => Response::ChecksumMany(result),
+            Response::Checksum(result) => Response::Checksum(result),
+            Response::SetSort(result) => Response::SetSort(result),
+            Response::SetShowHidden(result) => Response::SetShowHidden(result),
+            Response::SetFollowSymlinks(result) => Response::SetFollowSymlinks(result),
+            Response::Glob(result) => Response::Glob(result),
+            Response::Search(result) => Response::Search(result),
+            Response::SearchRecursive(result) => Response::SearchRecursive(result),
+            Response::Plugin(result) => Response::Plugin(result),
+            Response::Heartbeat => Response::Heartbeat,
+            Response::HealthCheck { uptime_secs } => Response::HealthCheck { uptime_secs },
+            Response::GetServerInfo { version, capabilities } => Response::GetServerInfo { version, capabilities },
+            Response::Goodbye => Response::Goodbye,
+            Response::Shutdown { reason } => Response::Shutdown { reason },
+            Response::UnknownRequest { name } => Response::UnknownRequest { name },
+        }
+    }
 }
 
 #[derive(Error, Debug, Deserialize, Serialize)]
 pub enum CursorError {
-    #[error("A new cursor cannot be created, since the limit of {limit} cursors has already been reached")]
-    CursorLimitReached { limit: u16 },
+    #[error("A new cursor cannot be created, since the limit of {limit} cursors has already been reached{}", span_suffix(.span_id))]
+    CursorLimitReached { limit: u16, span_id: Option<u64> },
+
+    #[error("A new cursor cannot be created, since the server-wide limit of {limit} cursors has already been reached{}", span_suffix(.span_id))]
+    GlobalLimitReached { limit: u32, span_id: Option<u64> },
+
+    #[error("The specified cursor does not exist{}", span_suffix(.span_id))]
+    UnknownCursor { span_id: Option<u64> },
+
+    #[error("The path {path} is not readable ({kind}){}", span_suffix(.span_id))]
+    ReadError {
+        path: PathBuf,
+        kind: String,
+        /// The error reported by the file system. A client receives it as a [`RemoteError`]
+        #[source]
+        #[serde(with = "remote_error")]
+        source: Box<dyn Error + Send + Sync>,
+        span_id: Option<u64>
+    },
+
+    #[error("The path {path} is not a directory{}", span_suffix(.span_id))]
+    NotADirectory { path: PathBuf, span_id: Option<u64> },
+
+    #[error("Permission to read the path {path} was denied{}", span_suffix(.span_id))]
+    PermissionDenied { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The path {path} does not exist{}", span_suffix(.span_id))]
+    NotFound { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The path {from} could not be copied to {to}{}", span_suffix(.span_id))]
+    CopyError { from: PathBuf, to: PathBuf, span_id: Option<u64> },
+
+    #[error("The path {from} could not be renamed to {to}{}", span_suffix(.span_id))]
+    RenameError {
+        from: PathBuf,
+        to: PathBuf,
+        /// The error reported by the file system. A client receives it as a [`RemoteError`]
+        #[source]
+        #[serde(with = "remote_error")]
+        source: Box<dyn Error + Send + Sync>,
+        span_id: Option<u64>
+    },
+
+    #[error("The name {name:?} is not a single path component{}", span_suffix(.span_id))]
+    InvalidName { name: OsString, span_id: Option<u64> },
+
+    #[error("The path {path} already exists{}", span_suffix(.span_id))]
+    AlreadyExists { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The archive could not be created: {reason}{}", span_suffix(.span_id))]
+    ArchiveError { reason: String, span_id: Option<u64> },
+
+    #[error("Access to the path {path} is denied{}", span_suffix(.span_id))]
+    AccessDenied { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The glob pattern {pattern} is invalid{}", span_suffix(.span_id))]
+    InvalidPattern { pattern: String, span_id: Option<u64> },
+
+    #[error("More than {limit} elements matched{}", span_suffix(.span_id))]
+    TooManyResults { limit: usize, span_id: Option<u64> },
+
+    #[error("No plugin is registered for the request type {type_id}{}", span_suffix(.span_id))]
+    UnknownPlugin { type_id: u16, span_id: Option<u64> },
+
+    #[error("The server is too busy to open more files, please try again later{}", span_suffix(.span_id))]
+    ServerBusy { span_id: Option<u64> },
 
-    #[error("The specified cursor does not exist")]
-    UnknownCursor,
+    #[error("The path has {depth} components, more than the limit of {max}{}", span_suffix(.span_id))]
+    PathTooDeep { depth: u32, max: u32, span_id: Option<u64> },
 
-    #[error("The path {path} is not readable")]
-    ReadError { path: PathBuf },
+    #[error("The directory {path} does not exist{}", span_suffix(.span_id))]
+    DestinationNotFound { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The file {path} could not be written{}", span_suffix(.span_id))]
+    UploadError {
+        path: PathBuf,
+        /// The error reported by the file system. A client receives it as a [`RemoteError`]
+        #[source]
+        #[serde(with = "remote_error")]
+        source: Box<dyn Error + Send + Sync>,
+        span_id: Option<u64>
+    },
+
+    #[error("The transfer {transfer_id} does not exist{}", span_suffix(.span_id))]
+    UnknownTransfer { transfer_id: u32, span_id: Option<u64> },
+
+    #[error("The file {path} is {size} bytes, more than the limit of {limit} bytes for a download, so it must be streamed{}", span_suffix(.span_id))]
+    FileTooLarge { path: PathBuf, size: u64, limit: u64, span_id: Option<u64> },
+
+    #[error("The path {path} is not a file{}", span_suffix(.span_id))]
+    NotAFile { path: PathBuf, span_id: Option<u64> },
+
+    #[error("The cursor has no previous location to go back to{}", span_suffix(.span_id))]
+    NoPreviousLocation { span_id: Option<u64> },
+
+    #[error("The cursor has no next location to go forward to{}", span_suffix(.span_id))]
+    NoNextLocation { span_id: Option<u64> },
+}
+
+impl CursorError {
+    /// Record the ID of the server's tracing span for the request which failed. The ID is shown with the error
+    /// and in the server's log, so the two can be matched up. None if the server was not tracing the request
+    pub fn set_span_id(&mut self, id: Option<u64>) {
+        let span_id = match self {
+            CursorError::CursorLimitReached { span_id, .. }
+            | CursorError::GlobalLimitReached { span_id, .. }
+            | CursorError::UnknownCursor { span_id }
+            | CursorError::ReadError { span_id, .. }
+            | CursorError::NotADirectory { span_id, .. }
+            | CursorError::PermissionDenied { span_id, .. }
+            | CursorError::NotFound { span_id, .. }
+            | CursorError::CopyError { span_id, .. }
+            | CursorError::RenameError { span_id, .. }
+            | CursorError::InvalidName { span_id, .. }
+            | CursorError::AlreadyExists { span_id, .. }
+            | CursorError::ArchiveError { span_id, .. }
+            | CursorError::AccessDenied { span_id, .. }
+            | CursorError::InvalidPattern { span_id, .. }
+            | CursorError::TooManyResults { span_id, .. }
+            | CursorError::UnknownPlugin { span_id, .. }
+            | CursorError::ServerBusy { span_id }
+            | CursorError::PathTooDeep { span_id, .. }
+            | CursorError::DestinationNotFound { span_id, .. }
+            | CursorError::UploadError { span_id, .. }
+            | CursorError::UnknownTransfer { span_id, .. }
+            | CursorError::FileTooLarge { span_id, .. }
+            | CursorError::NotAFile { span_id, .. }
+            | CursorError::NoPreviousLocation { span_id }
+            | CursorError::NoNextLocation { span_id } => span_id,
+        };
+        *span_id = id;
+    }
+}
+
+/// An error which happened on the other side of the connection. Only its message survives the trip, including
+/// the messages of the errors which caused it
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct RemoteError(pub String);
+
+/// Sends a boxed error as its message and receives it as a [`RemoteError`]
+mod remote_error {
+    use std::error::Error;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::RemoteError;
+
+    // serde passes the field itself, which is a Box
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(error: &Box<dyn Error + Send + Sync>, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(err) = source {
+            message.push_str(": ");
+            message.push_str(&err.to_string());
+            source = err.source();
+        }
+        serializer.serialize_str(&message)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<dyn Error + Send + Sync>, D::Error> {
+        Ok(Box::new(RemoteError(String::deserialize(deserializer)?)))
+    }
+}
+
+fn span_suffix(span_id: &Option<u64>) -> String {
+    match span_id {
+        Some(span_id) => format!(" (span {span_id})"),
+        None => String::new(),
+    }
 }
 
-struct Cursor {
+/// The position and cached listing of a single Cursor. Only the [`Browser`] can inspect or create one
+pub struct Cursor {
     path: PathBuf,
-    state: Option<Vec<FSElement>>
+    // Shared so a listing can outlive a borrow of the Browser without being copied
+    state: Option<Arc<Vec<FSElement>>>,
+    cached_at: Option<Instant>,
+    // The modification time of the directory when the state was read
+    dir_mtime: Option<OffsetDateTime>,
+    // The order set with Request::SetSort, or None to use the Browser's sort key
+    sort: Option<CursorSort>,
+    // The locations the Cursor has been moved to, oldest first. history[history_index] is always the current path
+    history: Vec<PathBuf>,
+    history_index: usize,
+    // When a request last named the Cursor, see Browser::gc_idle_cursors
+    last_used: Instant
 }
 
-pub struct Browser<F> {
-    cursors: HashMap<u16, Cursor>,
+impl Cursor {
+    /// Move to `path` and forget the listing of the previous location
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+        self.invalidate();
+    }
+
+    /// Forget the cached listing, so the next read lists the location again
+    fn invalidate(&mut self) {
+        self.state = None;
+        self.cached_at = None;
+        self.dir_mtime = None;
+    }
+
+    /// True if the cached state exists and has not outlived `ttl`. A `ttl` of None never expires
+    fn is_fresh(&self, ttl: Option<Duration>) -> bool {
+        match (&self.state, self.cached_at, ttl) {
+            (Some(_), Some(_), None) => true,
+            (Some(_), Some(cached_at), Some(ttl)) => cached_at.elapsed() < ttl,
+            _ => false
+        }
+    }
+}
+
+/// The maximum number of checksums computed at the same time by a single request
+const CHECKSUM_CONCURRENCY: usize = 8;
+
+/// The number of random cursor IDs generated each time the free list runs out
+const ID_BATCH_SIZE: usize = 64;
+
+/// The most locations a Cursor remembers for [`Request::Back`]. The oldest are forgotten first
+const MAX_HISTORY_LEN: usize = 64;
+
+/// The default maximum number of components in a path a Cursor can be moved to
+const DEFAULT_MAX_PATH_DEPTH: u32 = 64;
+
+/// The most bytes of file contents a [`Request::Archive`] may collect. The archive is built in memory, so this
+/// bounds the memory a single request can use
+const MAX_ARCHIVE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The most elements a [`Request::Glob`] may match. Every match is held in memory until the response is sent
+pub const MAX_GLOB_RESULTS: usize = 10_000;
+
+/// The most bytes a [`Request::Download`] may send. The file is read into memory whole, so larger files must be
+/// fetched with [`Request::StartStream`] instead
+pub const MAX_DOWNLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The largest file a single [`Request::Upload`] can carry, leaving room in the frame for the rest of the request
+pub const MAX_UPLOAD_SIZE: usize = crate::protocol::MAX_FRAME_LEN - 64 * 1024;
+
+/// The size of the DownloadChunk frames that downloads are split into, well within
+/// [`crate::protocol::MAX_FRAME_LEN`]
+const DOWNLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+struct PendingDownload {
+    id: u32,
+    data: Vec<u8>,
+    position: usize
+}
+
+/// The largest chunk a [`Request::StartStream`] may ask for. Larger sizes are reduced to this, which keeps each chunk
+/// well within a frame
+const MAX_STREAM_CHUNK_SIZE: u32 = 4 * 1024 * 1024;
+
+/// The most files a single Browser may have open for [`Request::StartStream`] at once
+const MAX_OPEN_STREAMS: usize = 16;
+
+/// A file opened by [`Request::StartStream`] which is read one chunk per [`Request::NextChunk`]
+struct StreamState {
+    // The path within the file system, for error messages
+    path: PathBuf,
+    file: tokio::fs::File,
+    chunk_size: u32,
+    bytes_remaining: u64,
+    // Released when the transfer ends, which closes the file
+    _permit: Option<OpenFilePermit>
+}
+
+pub struct Browser<F, S: CursorStore = HashMap<u16, Cursor>, R: RngCore = SmallRng> {
+    cursors: S,
     cursor_limit: u16,
+    cursor_quota: Option<CursorQuota>,
 
-    cursor_id_rng: SmallRng,
+    cursor_id_rng: R,
     cursor_id_uniform: Uniform<u16>,
+    available_ids: BTreeSet<u16>,
+
+    pending_download: Option<PendingDownload>,
+    streams: HashMap<u32, StreamState>,
+
+    default_sort: Option<CursorSort>,
+    cache_ttl: Option<Duration>,
+    show_hidden: bool,
+    list_options: ListOptions,
+    open_files: Option<OpenFileLimit>,
+    max_path_depth: u32,
+    connection_id: u64,
+    acl: Option<Acl>,
+    client_addr: Option<SocketAddr>,
 
     fs: F
 }
 
 impl<F: FS> Browser<F> {
+    /// Create a Browser without touching the file system. A file system which cannot be read is only noticed
+    /// when a Cursor is first read, see [`Browser::new_checked`]
     pub fn new(cursor_limit: u16, fs: F) -> Self {
+        Browser::with_store(cursor_limit, fs, HashMap::new())
+    }
+
+    /// Create a Browser whose Cursors also count towards a quota shared with other Browsers, such as those of every
+    /// other connection to a server. `global_quota` counts the open Cursors, of which there may be at most
+    /// `max_global`. See [`Browser::set_cursor_quota`]
+    pub fn new_with_global_quota(per_connection_limit: u16, global_quota: Arc<AtomicU32>, max_global: u32, fs: F) -> Self {
+        let mut browser = Browser::new(per_connection_limit, fs);
+        browser.set_cursor_quota(CursorQuota::new(global_quota, max_global));
+        browser
+    }
+
+    /// Create a Browser after checking that the root of the file system can be listed. Unlike
+    /// [`FS::list`], the check fails if any root element cannot be read, so a broken mapping is reported here
+    /// rather than silently left out of every listing
+    pub async fn new_checked(cursor_limit: u16, fs: F) -> Result<Self, F::Error> {
+        let options = ListOptions { mode: ListMode::FailOnError, ..ListOptions::default() };
+        fs.list_with_options(Path::new(""), options).await?;
+        Ok(Browser::new(cursor_limit, fs))
+    }
+}
+
+impl<F: FS, R: RngCore> Browser<F, HashMap<u16, Cursor>, R> {
+    /// Create a Browser which generates Cursor and download IDs with `rng`. A seeded RNG makes the IDs repeatable
+    pub fn new_with_rng(cursor_limit: u16, fs: F, rng: R) -> Self {
+        Browser::with_store_and_rng(cursor_limit, fs, HashMap::new(), rng)
+    }
+}
+
+impl<F: FS, S: CursorStore> Browser<F, S> {
+    /// Create a Browser which keeps its Cursors in `store` rather than a `HashMap`
+    pub fn with_store(cursor_limit: u16, fs: F, store: S) -> Self {
+        Browser::with_store_and_rng(cursor_limit, fs, store, default_rng())
+    }
+}
+
+impl<F: FS, S: CursorStore, R: RngCore> Browser<F, S, R> {
+    /// Create a Browser which keeps its Cursors in `store` and generates IDs with `rng`
+    pub fn with_store_and_rng(cursor_limit: u16, fs: F, store: S, rng: R) -> Self {
         Browser {
-            cursors: HashMap::new(),
+            cursors: store,
             cursor_limit,
-            cursor_id_rng: SmallRng::from_entropy(),
+            cursor_quota: None,
+            cursor_id_rng: rng,
             cursor_id_uniform: Uniform::new_inclusive(0, u16::MAX),
+            available_ids: BTreeSet::new(),
+            pending_download: None,
+            streams: HashMap::new(),
+            default_sort: None,
+            cache_ttl: Some(Duration::ZERO),
+            show_hidden: true,
+            list_options: ListOptions::default(),
+            open_files: None,
+            max_path_depth: DEFAULT_MAX_PATH_DEPTH,
+            connection_id: 0,
+            acl: None,
+            client_addr: None,
             fs,
         }
     }
 
+    /// Identify the connection this Browser serves. The ID is recorded on every tracing span the Browser opens.
+    /// It is 0 by default
+    pub fn set_connection_id(&mut self, id: u64) {
+        self.connection_id = id;
+    }
+
+    /// The ID of the connection this Browser serves, see [`Browser::set_connection_id`]
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    /// Record the address of the client this Browser serves, which is checked against the access control list
+    pub fn set_client_addr(&mut self, addr: SocketAddr) {
+        self.client_addr = Some(addr);
+    }
+
+    /// Only list virtual roots which the client's address is permitted to list, see [`Browser::set_client_addr`].
+    /// Every operation on a path inside a root which is not permitted, such as reading, downloading or renaming it,
+    /// fails with [`CursorError::PermissionDenied`], and recursive listings leave those roots out. Every root may be
+    /// listed by default
+    pub fn set_acl(&mut self, acl: Acl) {
+        self.acl = Some(acl);
+        self.invalidate_all();
+    }
+
+    /// The file system this Browser reads from
+    pub fn fs(&self) -> &F {
+        &self.fs
+    }
+
+    /// Mutable access to the file system, for changes such as adding or removing mapped paths. Every Cursor's
+    /// cached listing is discarded, so the changes are seen by the next read
+    pub fn fs_mut(&mut self) -> &mut F {
+        self.invalidate_all();
+        &mut self.fs
+    }
+
+    /// Select the key used to sort the elements returned by [`Browser::read_cursor`]. Elements are sorted by
+    /// name by default
+    pub fn set_sort_key<K: SortKey>(&mut self) {
+        self.default_sort = Some(CursorSort::by_key::<K>());
+    }
+
+    /// Choose how a single Cursor orders the elements it reads, overriding the key chosen with
+    /// [`Browser::set_sort_key`]. A cached listing is sorted again, so it does not have to be read again
+    pub fn set_cursor_sort(&mut self, id: u16, sort: CursorSort) -> Result<(), CursorError> {
+        let cursor = get_cursor_mut(&mut self.cursors, id)?;
+        cursor.sort = Some(sort);
+        if let Some(state) = &mut cursor.state {
+            sort.sort(Arc::make_mut(state).as_mut_slice());
+        }
+        Ok(())
+    }
+
+    /// Set how long the listing read by [`Browser::read_cursor`] stays cached. A TTL of zero re-reads the file
+    /// system on every call, which is the default. A TTL of None caches the listing until the cursor is moved
+    pub fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Choose whether elements with names starting with '.' are included when reading. They are included by
+    /// default
+    pub fn set_show_hidden(&mut self, show: bool) {
+        self.show_hidden = show;
+        self.invalidate_all();
+    }
+
+    /// Choose whether symbolic links are described by their target when reading. Links are followed by default
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        self.list_options.follow_symlinks = follow;
+        self.invalidate_all();
+    }
+
+    /// Choose whether reading a Cursor fails when an entry cannot be read, or leaves the entry out. Entries are
+    /// left out by default
+    pub fn set_list_mode(&mut self, mode: ListMode) {
+        self.list_options.mode = mode;
+        self.invalidate_all();
+    }
+
+    /// Count this Browser's Cursors towards `quota`, which is shared with other Browsers. Creating a Cursor fails once
+    /// the quota is used up, and the Browser's Cursors stop counting when it is dropped. There is no quota by default
+    pub fn set_cursor_quota(&mut self, quota: CursorQuota) {
+        let open = self.cursors.len().try_into().unwrap_or(u32::MAX);
+        if let Some(previous) = self.cursor_quota.take() {
+            previous.release(open);
+        }
+        quota.add(open);
+        self.cursor_quota = Some(quota);
+    }
+
+    /// Require a permit from `limit` before opening files for archives and checksums. There is no limit by default
+    pub fn set_open_file_limit(&mut self, limit: OpenFileLimit) {
+        self.open_files = Some(limit);
+    }
+
+    /// Set the maximum number of components in a path a Cursor can be moved to. The default is 64
+    pub fn set_max_path_depth(&mut self, max_path_depth: u32) {
+        self.max_path_depth = max_path_depth;
+    }
+
+    /// Wait for permission to open a file, if the number of open files is limited
+    async fn acquire_open_file(&self) -> Result<Option<OpenFilePermit>, CursorError> {
+        match &self.open_files {
+            Some(limit) => limit.acquire().await.map(Some).ok_or(CursorError::ServerBusy { span_id: None }),
+            None => Ok(None),
+        }
+    }
+
+    /// Discard the cached state of every cursor
+    fn invalidate_all(&mut self) {
+        self.invalidate_where(|_| true);
+    }
+
+    /// Discard the cached listing of every Cursor for which `f` returns true
+    fn invalidate_where(&mut self, f: impl Fn(&Cursor) -> bool) {
+        let ids: Vec<u16> = self.cursors.iter().filter(|(_, cursor)| f(cursor)).map(|(id, _)| id).collect();
+        for id in ids {
+            if let Some(cursor) = self.cursors.get_mut(id) {
+                cursor.invalidate();
+            }
+        }
+    }
+
     pub fn create_cursor(&mut self) -> Result<u16, CursorError> {
+        let limit_reached = CursorError::CursorLimitReached { limit: self.cursor_limit, span_id: None };
+
+        // The limit is at most u16::MAX, so this also guarantees that at least one ID is free
         if self.cursors.len() >= self.cursor_limit.into() {
-            return Err(CursorError::CursorLimitReached {
-                limit: self.cursor_limit,
-            });
+            return Err(limit_reached);
+        }
+
+        if self.available_ids.is_empty() {
+            self.replenish_ids();
+        }
+
+        // Every ID in the free list is unused, so no collision checking is needed. The list can only be empty
+        // if a custom store is already holding every possible ID
+        let id = self.available_ids.pop_first().ok_or(limit_reached)?;
+        if let Some(quota) = &self.cursor_quota {
+            if !quota.try_acquire() {
+                self.available_ids.insert(id);
+                return Err(CursorError::GlobalLimitReached { limit: quota.max(), span_id: None });
+            }
         }
+        self.cursors.insert(
+            id,
+            Cursor {
+                path: PathBuf::new(),
+                state: None,
+                cached_at: None,
+                dir_mtime: None,
+                sort: None,
+                history: vec![PathBuf::new()],
+                history_index: 0,
+                last_used: Instant::now()
+            },
+        );
+        Ok(id)
+    }
 
-        loop {
+    /// Refill the free list with a batch of random unused IDs
+    fn replenish_ids(&mut self) {
+        for _ in 0..ID_BATCH_SIZE {
             let id = self.cursor_id_uniform.sample(&mut self.cursor_id_rng);
+            if self.cursors.get(id).is_none() {
+                self.available_ids.insert(id);
+            }
+        }
 
-            if let Entry::Vacant(entry) = self.cursors.entry(id) {
-                entry.insert(
-                    Cursor {
-                        path: PathBuf::new(),
-                        state: None
-                    },
-                );
-                return Ok(id);
+        // Random sampling can miss when almost every ID is taken, so fall back to searching for a free one
+        if self.available_ids.is_empty() {
+            if let Some(id) = (0..=u16::MAX).find(|id| self.cursors.get(*id).is_none()) {
+                self.available_ids.insert(id);
             }
         }
     }
 
+    /// Destroy every Cursor which no request has named for longer than `timeout`, such as those left behind by a client
+    /// which has stopped using them. Returns the number of Cursors destroyed
+    pub fn gc_idle_cursors(&mut self, timeout: Duration) -> usize {
+        let idle: Vec<u16> = self.cursors
+            .iter()
+            .filter(|(_, cursor)| cursor.last_used.elapsed() > timeout)
+            .map(|(id, _)| id)
+            .collect();
+
+        for &id in &idle {
+            tracing::debug!(cursor_id = id, connection_id = self.connection_id, "Destroying idle cursor");
+            _ = self.destroy_cursor(id);
+        }
+        idle.len()
+    }
+
     pub fn destroy_cursor(&mut self, id: u16) -> Result<(), CursorError> {
         self.cursors
-            .remove(&id)
-            .map(|_| ())
-            .ok_or(CursorError::UnknownCursor)
+            .remove(id)
+            .map(|_| {
+                self.available_ids.insert(id);
+                if let Some(quota) = &self.cursor_quota {
+                    quota.release(1);
+                }
+            })
+            .ok_or(CursorError::UnknownCursor { span_id: None })
     }
 
-    pub async fn read_cursor(&mut self, id: u16) -> Result<&Vec<FSElement>, CursorError> {
+    /// Read the elements at the Cursor's location, listing them again if the cached listing is stale. The
+    /// listing is shared with the Cursor, so returning it is cheap
+    pub async fn read_cursor(&mut self, id: u16) -> Result<Arc<Vec<FSElement>>, CursorError> {
+        self.refresh_cursor(id).await.map(|(_, elements)| Arc::clone(elements))
+    }
+
+    /// Bring the cached listing of a Cursor up to date and lend it along with the Cursor's location
+    async fn refresh_cursor(&mut self, id: u16) -> Result<(&Path, &Arc<Vec<FSElement>>), CursorError> {
         let cursor = get_cursor_mut(&mut self.cursors, id)?;
+        check_acl(self.acl.as_ref(), self.client_addr, &cursor.path)?;
+
+        // A change to the directory's modification time means entries were added, removed or renamed. It is only
+        // read to check a listing which would otherwise be used, or to record it with a listing which will be kept
+        let fresh = cursor.is_fresh(self.cache_ttl);
+        let dir_mtime = if fresh || self.cache_ttl != Some(Duration::ZERO) {
+            self.fs
+                .metadata(&cursor.path)
+                .await
+                .ok()
+                .and_then(|element| element.modified)
+        } else {
+            None
+        };
+
+        if !fresh || cursor.dir_mtime != dir_mtime {
+            let mut elements = catch_fs_panic(&cursor.path, self.fs.list_with_options(&cursor.path, self.list_options))
+                .await?
+                .map_err(|err| read_error(cursor.path.clone(), err))?;
+            retain_permitted(self.acl.as_ref(), self.client_addr, &cursor.path, &mut elements);
+
+            if !self.show_hidden {
+                elements.retain(|element| !is_hidden(element));
+            }
+            sort_elements(cursor.sort.or(self.default_sort), &mut elements);
+            cursor.state = Some(Arc::new(elements));
+            cursor.cached_at = Some(Instant::now());
+            cursor.dir_mtime = dir_mtime;
+        }
+
+        Ok((&cursor.path, cursor.state.as_ref().unwrap()))
+    }
+
+    /// The result of a [`Request::Read`], lending the cached listing
+    async fn read_response(&mut self, id: u16) -> Result<(PathBuf, Cow<'_, Vec<FSElement>>), CursorError> {
+        let (path, elements) = self.refresh_cursor(id).await?;
+        Ok((path.to_owned(), Cow::Borrowed(&**elements)))
+    }
+
+    /// Read `count` elements at the Cursor's location, starting at `offset`, along with the total number of elements.
+    /// Pages are cut from the cached listing, which is read again under the same rules as [`Browser::read_cursor`].
+    /// With the default cache TTL of zero every page is read again, so set a TTL if the pages of a directory that
+    /// is being changed must line up
+    pub async fn read_cursor_page(&mut self, id: u16, offset: u32, count: u32) -> Result<(Vec<FSElement>, u32), CursorError> {
+        let (_, elements) = self.refresh_cursor(id).await?;
+        let total = elements.len().try_into().unwrap_or(u32::MAX);
+
+        let page = elements.iter()
+            .skip(offset as usize)
+            .take(count as usize)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    /// List every element below the Cursor's location, see [`FS::list_recursive`]. The depth is capped at the maximum
+    /// path depth, which None also stands for. Unless hidden elements are shown, they are left out together with
+    /// everything inside hidden directories. The listing is not cached
+    pub async fn read_cursor_recursive(&self, id: u16, max_depth: Option<u32>) -> Result<Vec<(PathBuf, FSElement)>, CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        let max_depth = max_depth.map_or(self.max_path_depth, |max_depth| max_depth.min(self.max_path_depth));
+        check_acl(self.acl.as_ref(), self.client_addr, &cursor.path)?;
+
+        let mut elements = catch_fs_panic(&cursor.path, self.fs.list_recursive(&cursor.path, Some(max_depth as usize)))
+            .await?
+            .map_err(|err| read_error(cursor.path.clone(), err))?;
+        elements.retain(|(path, _)| check_acl(self.acl.as_ref(), self.client_addr, path).is_ok());
+
+        if !self.show_hidden {
+            elements.retain(|(path, _)| !path
+                .strip_prefix(&cursor.path)
+                .unwrap_or(path)
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.')));
+        }
+        Ok(elements)
+    }
+
+    /// Read the elements at the Cursor's location one at a time as they are read from the file system. The
+    /// elements are not sorted or cached; they arrive in the order the file system returns them
+    pub fn read_cursor_streaming<'a>(&'a mut self, id: u16) -> impl Stream<Item = Result<FSElement, CursorError>> + 'a {
+        // The server answers streaming reads without going through Browser::process
+        if let Some(cursor) = self.cursors.get_mut(id) {
+            cursor.last_used = Instant::now();
+        }
+        let path = get_cursor(&self.cursors, id).and_then(|cursor| {
+            check_acl(self.acl.as_ref(), self.client_addr, &cursor.path)?;
+            Ok(cursor.path.clone())
+        });
+        let browser = &*self;
+
+        stream::unfold(StreamingRead::Start(path), move |state| async move {
+            let mut state = match state {
+                StreamingRead::Start(Err(err)) => return Some((Err(err), StreamingRead::Done)),
+                StreamingRead::Start(Ok(path)) => match browser.fs.unmap(&path) {
+                    Ok(real_path) => match tokio::fs::read_dir(&real_path).await {
+                        Ok(read_dir) => StreamingRead::Directory { path, read_dir },
+                        Err(err) => return Some((Err(read_error(path, err)), StreamingRead::Done)),
+                    },
+                    // Paths without a real counterpart, such as the root, are listed up front
+                    Err(_) => match catch_fs_panic(&path, browser.fs.list_with_options(&path, browser.list_options)).await {
+                        Ok(Ok(mut elements)) => {
+                            retain_permitted(browser.acl.as_ref(), browser.client_addr, &path, &mut elements);
+                            StreamingRead::Listed(elements.into_iter())
+                        }
+                        Ok(Err(err)) => return Some((Err(read_error(path, err)), StreamingRead::Done)),
+                        Err(err) => return Some((Err(err), StreamingRead::Done)),
+                    },
+                },
+                state => state,
+            };
+
+            loop {
+                let element = match &mut state {
+                    StreamingRead::Start(_) | StreamingRead::Done => return None,
+                    StreamingRead::Listed(elements) => elements.next()?,
+                    StreamingRead::Directory { path, read_dir } => match read_dir.next_entry().await {
+                        Ok(Some(entry)) => {
+                            // Elements which disappear or cannot be read are handled the same way as by FS::list
+                            match get_element(entry.file_name(), entry.path(), browser.list_options.follow_symlinks).await {
+                                Ok(element) => element,
+                                Err(err) if browser.list_options.mode == ListMode::FailOnError => {
+                                    return Some((Err(read_error(path.join(entry.file_name()), err)), StreamingRead::Done));
+                                }
+                                Err(_) => continue,
+                            }
+                        }
+                        Ok(None) => return None,
+                        Err(err) => return Some((Err(read_error(path.clone(), err)), StreamingRead::Done)),
+                    },
+                };
+
+                if browser.show_hidden || !is_hidden(&element) {
+                    return Some((Ok(element), state));
+                }
+            }
+        })
+    }
+
+    /// Obtain the element at a path without listing its parent directory. Relative paths are resolved
+    /// against the Cursor's location
+    pub async fn stat<P: AsRef<Path>>(&self, id: u16, path: P) -> Result<FSElement, CursorError> {
+        let path = get_cursor(&self.cursors, id)?.path.join(path);
+        check_acl(self.acl.as_ref(), self.client_addr, &path)?;
         self.fs
-            .list(&cursor.path)
+            .metadata(&path)
             .await
-            .map_err(|_| CursorError::ReadError { path: cursor.path.clone() })
-            .map(|mut elements| {
-                elements.sort_unstable_by(cmp_fs_elements);
-                cursor.state = Some(elements);
-                cursor.state.as_ref().unwrap()
-            })
+            .map_err(|err| read_error(path, err))
     }
 
     pub fn get_location_cursor(&self, id: u16) -> Result<&Path, CursorError> {
         Ok(&get_cursor(&self.cursors, id)?.path)
     }
 
+    /// The Cursor's location, or None if the Cursor does not exist
+    pub fn cursor_path(&self, id: u16) -> Option<&Path> {
+        self.cursors.get(id).map(|cursor| cursor.path.as_path())
+    }
+
+    /// The modification time of the Cursor's directory when its listing was cached, for checking whether the
+    /// cached listing may be stale. None if the Cursor does not exist. Some(None) if nothing is cached or the
+    /// time is unknown
+    pub fn cursor_modified(&self, id: u16) -> Option<Option<OffsetDateTime>> {
+        self.cursors.get(id).map(|cursor| cursor.dir_mtime)
+    }
+
+    /// Every Cursor as (ID, location, whether a listing is cached), in order of ID
+    pub fn cursors(&self) -> impl Iterator<Item = (u16, &Path, bool)> + '_ {
+        let mut cursors: Vec<_> = self.cursors
+            .iter()
+            .map(|(id, cursor)| (id, cursor.path.as_path(), cursor.state.is_some()))
+            .collect();
+        cursors.sort_unstable_by_key(|(id, ..)| *id);
+        cursors.into_iter()
+    }
+
     pub fn move_cursor<P: AsRef<Path>>(&mut self, id: u16, path: P) -> Result<(), CursorError> {
         let cursor = get_cursor_mut(&mut self.cursors, id)?;
-        if &cursor.path != path.as_ref() {
-            cursor.path = path.as_ref().to_owned();
-            cursor.state = None;
+
+        // Reject absurdly deep paths before they are stored and joined onto real paths
+        let depth = path.as_ref().components().count().try_into().unwrap_or(u32::MAX);
+        if depth > self.max_path_depth {
+            return Err(CursorError::PathTooDeep { depth, max: self.max_path_depth, span_id: None });
+        }
+
+        if cursor.path != path.as_ref() {
+            // Moving somewhere new discards the locations a Forward would have returned to
+            cursor.history.truncate(cursor.history_index + 1);
+            cursor.history.push(path.as_ref().to_owned());
+            if cursor.history.len() > MAX_HISTORY_LEN {
+                cursor.history.remove(0);
+            }
+            cursor.history_index = cursor.history.len() - 1;
+            cursor.set_path(path.as_ref().to_owned());
         }
         Ok(())
     }
 
-    pub async fn process(&mut self, request: Request) -> Response {
+    /// Move the Cursor back to the location it was at before it was last moved, returning that location
+    pub fn back_cursor(&mut self, id: u16) -> Result<PathBuf, CursorError> {
+        let cursor = get_cursor_mut(&mut self.cursors, id)?;
+        cursor.history_index = cursor.history_index.checked_sub(1).ok_or(CursorError::NoPreviousLocation { span_id: None })?;
+        cursor.set_path(cursor.history[cursor.history_index].clone());
+        Ok(cursor.path.clone())
+    }
+
+    /// Move the Cursor forward to the location it left with the last [`Browser::back_cursor`], returning that location
+    pub fn forward_cursor(&mut self, id: u16) -> Result<PathBuf, CursorError> {
+        let cursor = get_cursor_mut(&mut self.cursors, id)?;
+        if cursor.history_index + 1 >= cursor.history.len() {
+            return Err(CursorError::NoNextLocation { span_id: None });
+        }
+
+        cursor.history_index += 1;
+        cursor.set_path(cursor.history[cursor.history_index].clone());
+        Ok(cursor.path.clone())
+    }
+
+    /// Copy a file or directory (recursively) to a new location. Both paths must resolve to locations within
+    /// the file system, so a copy can never write outside of it, even through a symbolic link. Nothing may already
+    /// exist at `to`. The cached listings of the directory holding `to` are discarded
+    pub async fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, id: u16, from: P, to: Q) -> Result<(), CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        let from = cursor.path.join(from);
+        let to = cursor.path.join(to);
+        let copy_err = || CursorError::CopyError { from: from.clone(), to: to.clone(), span_id: None };
+        check_acl(self.acl.as_ref(), self.client_addr, &from)?;
+        check_acl(self.acl.as_ref(), self.client_addr, &to)?;
+
+        if !self.fs.is_writable(&to) {
+            return Err(CursorError::AccessDenied { path: to, span_id: None });
+        }
+
+        let real_from = self.fs.unmap(&from).map_err(|_| copy_err())?;
+        let real_to = self.fs.unmap(&to).map_err(|_| copy_err())?;
+
+        // Copying a directory into itself would never terminate
+        if real_to.starts_with(&real_from) {
+            return Err(copy_err());
+        }
+
+        if tokio::fs::symlink_metadata(&real_to).await.is_ok() {
+            return Err(CursorError::AlreadyExists { path: to, span_id: None });
+        }
+        self.check_contained(&to).await?;
+
+        copy_recursive(&real_from, &real_to)
+            .await
+            .map_err(|_| copy_err())?;
+
+        self.invalidate_where(|cursor| Some(cursor.path.as_path()) == to.parent());
+        Ok(())
+    }
+
+    /// Refuse a path to be written unless the real directory it is in lies within the path's virtual root once
+    /// symbolic links are resolved. Otherwise a link inside of a writable root, such as `link -> /etc`, would let
+    /// a client write anywhere through `link/file`. File systems without real paths have no links to follow
+    async fn check_contained(&self, path: &Path) -> Result<(), CursorError> {
+        let root = path.components().find_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+        let (Some(root), Ok(real_path)) = (root, self.fs.unmap(path)) else {
+            return Ok(());
+        };
+        let denied = || CursorError::AccessDenied { path: path.to_owned(), span_id: None };
+
+        let real_root = self.fs.unmap(Path::new("/").join(root)).map_err(|_| denied())?;
+        let real_parent = real_path.parent().ok_or_else(denied)?;
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let real_root = tokio::fs::canonicalize(&real_root).await.map_err(|err| read_error(parent.to_owned(), err))?;
+        let real_parent = tokio::fs::canonicalize(real_parent).await.map_err(|err| read_error(parent.to_owned(), err))?;
+
+        if !real_parent.starts_with(&real_root) {
+            return Err(denied());
+        }
+        Ok(())
+    }
+
+    /// Give a file or directory a new name within its current directory. `new_name` must be a single path
+    /// component, and nothing may already exist under it. The cached listing of the parent directory, and of
+    /// anything below the element, are discarded
+    pub async fn rename_file<P: AsRef<Path>>(&mut self, id: u16, path: P, new_name: &OsStr) -> Result<(), CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        let from = cursor.path.join(path);
+
+        // Separators, '.' and '..' would let the element leave its directory
+        let mut components = Path::new(new_name).components();
+        let is_single_component = matches!(components.next(), Some(Component::Normal(name)) if name == new_name)
+            && components.next().is_none();
+        if !is_single_component {
+            return Err(CursorError::InvalidName { name: new_name.to_owned(), span_id: None });
+        }
+
+        // The root, or a path ending in '..', has no name of its own to replace
+        let (Some(parent), Some(_)) = (from.parent(), from.file_name()) else {
+            return Err(CursorError::AccessDenied { path: from, span_id: None });
+        };
+        let to = parent.join(new_name);
+
+        check_acl(self.acl.as_ref(), self.client_addr, &from)?;
+        if !self.fs.is_writable(&from) {
+            return Err(CursorError::AccessDenied { path: from, span_id: None });
+        }
+
+        // A rename requested by a client must never replace anything, even something created after this check
+        if self.fs.metadata(&to).await.is_ok() {
+            return Err(CursorError::AlreadyExists { path: to, span_id: None });
+        }
+
+        if let Err(err) = self.fs.rename_no_replace(&from, &to).await {
+            if io_error_kind(&err) == io::ErrorKind::AlreadyExists {
+                return Err(CursorError::AlreadyExists { path: to, span_id: None });
+            }
+            return Err(CursorError::RenameError { from, to, source: Box::new(err), span_id: None });
+        }
+
+        self.invalidate_where(|cursor| Some(cursor.path.as_path()) == from.parent() || cursor.path.starts_with(&from));
+        Ok(())
+    }
+
+    /// Build an archive from a list of paths. Names within the archive are relative to the Cursor's location.
+    /// The archive is sent to the client afterwards in chunks, see [`Browser::next_frame`]
+    pub async fn archive(&mut self, id: u16, paths: Vec<PathBuf>, format: ArchiveFormat) -> Result<u32, CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = cursor.path.join(path);
+            check_acl(self.acl.as_ref(), self.client_addr, &path)?;
+            let real_path = self.fs
+                .unmap(&path)
+                .map_err(|err| read_error(path.clone(), err))?;
+
+            entries.push((archive_name(&cursor.path, &path), real_path));
+        }
+
+        // Files are added to the archive one at a time, so a single permit covers the whole archive
+        let permit = self.acquire_open_file().await?;
+        let limits = ArchiveLimits { max_depth: self.max_path_depth, max_size: MAX_ARCHIVE_SIZE };
+        let archive_err = |reason: String| CursorError::ArchiveError { reason, span_id: None };
+        let data = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                build_archive(format, &entries, limits)
+            })
+            .await
+            .map_err(|err| archive_err(err.to_string()))?
+            .map_err(|err| archive_err(err.to_string()))?;
+
+        Ok(self.start_download(data))
+    }
+
+    /// Read the contents of a file. The contents are sent to the client afterwards in chunks, see
+    /// [`Browser::next_frame`]. Files larger than [`MAX_DOWNLOAD_SIZE`] are refused, since they would be held in
+    /// memory whole, and must be fetched with [`Browser::start_stream`] instead
+    pub async fn download_file<P: AsRef<Path>>(&mut self, id: u16, path: P) -> Result<u32, CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        let path = cursor.path.join(path);
+        check_acl(self.acl.as_ref(), self.client_addr, &path)?;
+        let real_path = self.fs
+            .unmap(&path)
+            .map_err(|err| read_error(path.clone(), err))?;
+
+        let data = {
+            let _permit = self.acquire_open_file().await?;
+            let file = tokio::fs::File::open(&real_path)
+                .await
+                .map_err(|err| read_error(path.clone(), err))?;
+            let size = file.metadata()
+                .await
+                .map_err(|err| read_error(path.clone(), err))?
+                .len();
+            if size > MAX_DOWNLOAD_SIZE {
+                return Err(CursorError::FileTooLarge { path, size, limit: MAX_DOWNLOAD_SIZE, span_id: None });
+            }
+
+            // A file which grew since its size was checked is cut off at the limit
+            let mut data = Vec::with_capacity(size as usize);
+            file.take(MAX_DOWNLOAD_SIZE)
+                .read_to_end(&mut data)
+                .await
+                .map_err(|err| read_error(path, err))?;
+            data
+        };
+
+        Ok(self.start_download(data))
+    }
+
+    /// Write `data` to a file, replacing the file if it exists. The directory it is written to must already exist,
+    /// and must lie within its virtual root once symbolic links are resolved. The whole file arrives in a single
+    /// request, so it is limited to [`MAX_UPLOAD_SIZE`]. The cached listings of that directory are discarded
+    pub async fn upload_file<P: AsRef<Path>>(&mut self, id: u16, dest_path: P, data: &[u8]) -> Result<(), CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        let path = cursor.path.join(dest_path);
+        check_acl(self.acl.as_ref(), self.client_addr, &path)?;
+
+        if !self.fs.is_writable(&path) {
+            return Err(CursorError::AccessDenied { path, span_id: None });
+        }
+
+        let parent = path.parent().unwrap_or(Path::new(""));
+        if !self.fs.metadata(parent).await.is_ok_and(|element| !element.is_file) {
+            return Err(CursorError::DestinationNotFound { path: parent.to_owned(), span_id: None });
+        }
+        self.check_contained(&path).await?;
+
+        let permit = self.acquire_open_file().await?;
+        let result = self.fs.write(&path, data).await;
+        drop(permit);
+        if let Err(err) = result {
+            return Err(CursorError::UploadError { path, source: Box::new(err), span_id: None });
+        }
+
+        self.invalidate_where(|cursor| Some(cursor.path.as_path()) == path.parent());
+        Ok(())
+    }
+
+    /// Open a file to be sent in chunks which the client requests one at a time with [`Browser::next_chunk`], so
+    /// the file is never held in memory whole and the client decides how fast it arrives. Returns the ID of the
+    /// transfer and the size of the file
+    pub async fn start_stream<P: AsRef<Path>>(&mut self, id: u16, path: P, chunk_size: u32) -> Result<(u32, u64), CursorError> {
+        let path = get_cursor(&self.cursors, id)?.path.join(path);
+        check_acl(self.acl.as_ref(), self.client_addr, &path)?;
+        if self.streams.len() >= MAX_OPEN_STREAMS {
+            return Err(CursorError::ServerBusy { span_id: None });
+        }
+
+        let real_path = self.fs
+            .unmap(&path)
+            .map_err(|err| read_error(path.clone(), err))?;
+
+        let permit = self.acquire_open_file().await?;
+        let file = tokio::fs::File::open(&real_path)
+            .await
+            .map_err(|err| read_error(path.clone(), err))?;
+        let metadata = file.metadata()
+            .await
+            .map_err(|err| read_error(path.clone(), err))?;
+        if !metadata.is_file() {
+            return Err(CursorError::NotAFile { path, span_id: None });
+        }
+
+        let chunk_size = match chunk_size {
+            0 => DOWNLOAD_CHUNK_SIZE as u32,
+            chunk_size => chunk_size.min(MAX_STREAM_CHUNK_SIZE),
+        };
+        let transfer_id = loop {
+            let transfer_id = self.cursor_id_rng.gen();
+            if !self.streams.contains_key(&transfer_id) {
+                break transfer_id;
+            }
+        };
+
+        let total_bytes = metadata.len();
+        self.streams.insert(transfer_id, StreamState { path, file, chunk_size, bytes_remaining: total_bytes, _permit: permit });
+        Ok((transfer_id, total_bytes))
+    }
+
+    /// Read the next chunk of a transfer started with [`Browser::start_stream`], along with the number of bytes left
+    /// after it. The file is closed once the last chunk has been read, or if reading fails
+    pub async fn next_chunk(&mut self, transfer_id: u32) -> Result<(Vec<u8>, u64), CursorError> {
+        let stream = self.streams
+            .get_mut(&transfer_id)
+            .ok_or(CursorError::UnknownTransfer { transfer_id, span_id: None })?;
+
+        let len = stream.bytes_remaining.min(stream.chunk_size.into());
+        let mut data = Vec::with_capacity(len as usize);
+        let result = match (&mut stream.file).take(len).read_to_end(&mut data).await {
+            // The file was shortened after the transfer started
+            Ok(read) if (read as u64) < len => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {
+                stream.bytes_remaining -= len;
+                let bytes_remaining = stream.bytes_remaining;
+                if bytes_remaining == 0 {
+                    self.streams.remove(&transfer_id);
+                }
+                Ok((data, bytes_remaining))
+            }
+            Err(err) => {
+                let stream = self.streams.remove(&transfer_id).expect("The transfer was just found");
+                Err(read_error(stream.path, err))
+            }
+        }
+    }
+
+    /// Stop a transfer started with [`Browser::start_stream`] and close its file
+    pub fn cancel_stream(&mut self, transfer_id: u32) -> Result<(), CursorError> {
+        self.streams
+            .remove(&transfer_id)
+            .map(|_| ())
+            .ok_or(CursorError::UnknownTransfer { transfer_id, span_id: None })
+    }
+
+    /// Queue `data` to be sent in DownloadChunk frames, replacing any download which has not been sent yet
+    fn start_download(&mut self, data: Vec<u8>) -> u32 {
+        let download_id = self.cursor_id_rng.gen();
+        self.pending_download = Some(PendingDownload { id: download_id, data, position: 0 });
+        download_id
+    }
+
+    /// Compute the checksum of each path concurrently. Failures are reported per path rather than failing the
+    /// entire request. The results are in the same order as `paths`
+    pub async fn checksum_many(&self, id: u16, paths: Vec<PathBuf>, algorithm: ChecksumAlgorithm) -> Result<Vec<(PathBuf, String)>, CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+
+        let mut results: Vec<Option<String>> = vec![None; paths.len()];
+        let mut tasks = JoinSet::new();
+        for (index, path) in paths.iter().enumerate() {
+            let path = cursor.path.join(path);
+            if let Err(err) = check_acl(self.acl.as_ref(), self.client_addr, &path) {
+                results[index] = Some(format!("ERROR: {err}"));
+                continue;
+            }
+            let real_path = match self.fs.unmap(&path) {
+                Ok(real_path) => real_path,
+                Err(err) => {
+                    results[index] = Some(format!("ERROR: {err}"));
+                    continue;
+                }
+            };
+
+            // Bound the number of files being read at once
+            if tasks.len() >= CHECKSUM_CONCURRENCY {
+                store_checksum(&mut results, tasks.join_next().await);
+            }
+            // A path which waits too long for a file is reported as busy, and the others carry on
+            let permit = match self.acquire_open_file().await {
+                Ok(permit) => permit,
+                Err(err) => {
+                    results[index] = Some(format!("ERROR: {err}"));
+                    continue;
+                }
+            };
+            tasks.spawn(async move {
+                // The permit is released once the file has been read and closed
+                let _permit = permit;
+                (index, checksum_file(real_path, algorithm).await)
+            });
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            store_checksum(&mut results, Some(result));
+        }
+
+        Ok(paths.into_iter()
+            .zip(results)
+            .map(|(path, result)| (path, result.unwrap_or_else(|| "ERROR: The checksum task failed".to_owned())))
+            .collect())
+    }
+
+    /// Compute the checksum of a single file, reading it in pieces rather than all at once
+    pub async fn compute_checksum<P: AsRef<Path>>(&self, id: u16, path: P, algorithm: ChecksumAlgorithm) -> Result<Vec<u8>, CursorError> {
+        let path = get_cursor(&self.cursors, id)?.path.join(path);
+        check_acl(self.acl.as_ref(), self.client_addr, &path)?;
+        let real_path = self.fs
+            .unmap(&path)
+            .map_err(|err| read_error(path.clone(), err))?;
+
+        let element = self.fs
+            .metadata(&path)
+            .await
+            .map_err(|err| read_error(path.clone(), err))?;
+        if !element.is_file {
+            return Err(CursorError::NotAFile { path, span_id: None });
+        }
+
+        let _permit = self.acquire_open_file().await?;
+        checksum_file(real_path, algorithm)
+            .await
+            .map_err(|err| read_error(path, err))
+    }
+
+    /// Read the elements at the Cursor's location whose names match a glob pattern such as `*.rs`, `doc?` or `[abc]*`.
+    /// The cached listing is used if it is still fresh
+    pub async fn search_cursor(&mut self, id: u16, pattern: &str, case_insensitive: bool) -> Result<Vec<FSElement>, CursorError> {
+        let matcher = NameMatcher::new(pattern, case_insensitive)?;
+        let (_, elements) = self.refresh_cursor(id).await?;
+        Ok(elements.iter().filter(|element| matcher.matches(element)).cloned().collect())
+    }
+
+    /// Find the elements below the Cursor's location whose names match a glob pattern, as in
+    /// [`Browser::search_cursor`]. Subdirectories are entered as in [`Browser::read_cursor_recursive`]
+    pub async fn search_cursor_recursive(&self, id: u16, pattern: &str, case_insensitive: bool, max_depth: Option<u32>) -> Result<Vec<(PathBuf, FSElement)>, CursorError> {
+        let matcher = NameMatcher::new(pattern, case_insensitive)?;
+        let mut elements = self.read_cursor_recursive(id, max_depth).await?;
+        elements.retain(|(_, element)| matcher.matches(element));
+        Ok(elements)
+    }
+
+    /// Find every element matching a glob pattern such as `**/*.toml`, relative to the Cursor's location. Patterns
+    /// which could reach outside of the Cursor's location are rejected. Symbolic links are neither matched nor
+    /// entered, the search stops at the maximum path depth, see [`Browser::set_max_path_depth`], and it fails once
+    /// more than [`MAX_GLOB_RESULTS`] elements match
+    pub async fn glob(&self, id: u16, pattern: &str) -> Result<Vec<FSElement>, CursorError> {
+        let cursor = get_cursor(&self.cursors, id)?;
+        check_acl(self.acl.as_ref(), self.client_addr, &cursor.path)?;
+
+        let escapes = Path::new(pattern)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(..) | Component::CurDir));
+        if escapes {
+            return Err(CursorError::AccessDenied { path: cursor.path.join(pattern), span_id: None });
+        }
+
+        let compiled = glob::Pattern::new(pattern)
+            .map_err(|_| CursorError::InvalidPattern { pattern: pattern.to_owned(), span_id: None })?;
+
+        let base = self.fs
+            .unmap(&cursor.path)
+            .map_err(|err| read_error(cursor.path.clone(), err))?;
+
+        // Without a '**', nothing deeper than the pattern itself can match
+        let max_depth = match pattern.contains("**") {
+            true => self.max_path_depth,
+            false => Path::new(pattern).components().count().try_into().unwrap_or(u32::MAX).min(self.max_path_depth),
+        };
+        let matches = tokio::task::spawn_blocking(move || glob_walk(&base, &compiled, max_depth))
+            .await
+            .map_err(|err| read_error(cursor.path.clone(), err))??;
+
+        let mut elements = Vec::with_capacity(matches.len());
+        for relative_path in matches {
+            // Matches which disappear before their metadata is read are skipped
+            if let Ok(mut element) = self.fs.metadata(cursor.path.join(&relative_path)).await {
+                element.name = relative_path.into_os_string();
+                elements.push(element);
+            }
+        }
+
+        sort_elements(cursor.sort.or(self.default_sort), &mut elements);
+        Ok(elements)
+    }
+
+    /// Walk the entire subtree below the Cursor's location, depth first. Each element is yielded with its path
+    /// within the file system. A directory is only listed once the element after it is requested, so the tree
+    /// is never held in memory all at once and a slow consumer slows down the walk. Subdirectories are entered as
+    /// in [`Browser::read_cursor_recursive`], so symbolic links are never entered and the walk stops at the
+    /// maximum path depth, see [`Browser::set_max_path_depth`]
+    pub fn depth_first_walk(&self, id: u16) -> impl Stream<Item = Result<(PathBuf, FSElement), CursorError>> + '_ {
+        let walk = get_cursor(&self.cursors, id).and_then(|cursor| {
+            check_acl(self.acl.as_ref(), self.client_addr, &cursor.path)?;
+            Ok(Walk { stack: vec![], expand: Some((cursor.path.clone(), 0)), sort: cursor.sort })
+        });
+
+        stream::unfold(Some(walk), move |walk| async move {
+            let mut walk = match walk? {
+                Ok(walk) => walk,
+                Err(err) => return Some((Err(err), None)),
+            };
+
+            if let Some((path, depth)) = walk.expand.take() {
+                match self.fs.list_with_options(&path, self.list_options).await {
+                    Ok(mut elements) => {
+                        if !self.show_hidden {
+                            elements.retain(|element| !is_hidden(element));
+                        }
+
+                        // Reverse the order so the first element ends up on top of the stack
+                        sort_elements(walk.sort.or(self.default_sort), &mut elements);
+                        elements.reverse();
+                        walk.stack.extend(elements
+                            .into_iter()
+                            .map(|element| (path.join(&element.name), element, depth))
+                            .filter(|(path, _, _)| check_acl(self.acl.as_ref(), self.client_addr, path).is_ok()));
+                    }
+                    Err(err) => return Some((Err(read_error(path, err)), Some(Ok(walk)))),
+                }
+            }
+
+            let (path, element, depth) = walk.stack.pop()?;
+            if super::enters(&element, depth, Some(self.max_path_depth as usize)) {
+                walk.expand = Some((path.clone(), depth + 1));
+            }
+
+            Some((Ok((path, element)), Some(Ok(walk))))
+        })
+    }
+
+    /// Obtain the next unsolicited frame which should be sent to the client, if there is one. This should be
+    /// called repeatedly after each request has been processed until it returns None
+    pub fn next_frame(&mut self) -> Option<Response<'static>> {
+        let download = self.pending_download.as_mut()?;
+
+        let end = download.data.len().min(download.position + DOWNLOAD_CHUNK_SIZE);
+        let data = download.data[download.position..end].to_vec();
+        download.position = end;
+
+        let bytes_remaining = (download.data.len() - end).try_into().unwrap();
+        let frame = Response::DownloadChunk { download_id: download.id, data, bytes_remaining };
+
+        if bytes_remaining == 0 {
+            self.pending_download = None;
+        }
+
+        Some(frame)
+    }
+
+    pub async fn process(&mut self, request: Request) -> Response<'_> {
+        let span = tracing::debug_span!("browser", connection_id = self.connection_id);
+        self.process_request(request).instrument(span).await
+    }
+
+    async fn process_request(&mut self, request: Request) -> Response<'_> {
+        if let Some(cursor) = request.cursor_id().and_then(|id| self.cursors.get_mut(id)) {
+            cursor.last_used = Instant::now();
+        }
+
         match request {
             Request::Create => Response::Create(self.create_cursor()),
             Request::Destroy { id } => Response::Destroy(self.destroy_cursor(id)),
-            Request::Read { id } => Response::Read(self.read_cursor(id).await.cloned()),
+            Request::Read { id } => Response::Read(self.read_response(id).await),
+            // The server writes each element of a streaming read as its own frame. When the Browser is used
+            // without a server, the whole listing is returned at once instead
+            Request::ReadStreaming { id } => Response::Read(self.read_response(id).await),
+            Request::ReadPage { id, offset, count } => Response::ReadPage(self.read_cursor_page(id, offset, count).await),
+            Request::ReadRecursive { id, max_depth } => Response::ReadRecursive(self.read_cursor_recursive(id, max_depth).await),
+            Request::Stat { id, path } => Response::Stat(self.stat(id, path).await),
             Request::GetLocation { id } => Response::GetLocation(self.get_location_cursor(id)
                 .map(ToOwned::to_owned)),
-            Request::Move { id, path } => Response::Move(self.move_cursor(id, path)),
+            Request::Navigate { id, path } => Response::Navigate(self.move_cursor(id, path)),
+            Request::Back { id } => Response::Back(self.back_cursor(id)),
+            Request::Forward { id } => Response::Forward(self.forward_cursor(id)),
+            Request::Copy { id, from, to } => Response::Copy(self.copy(id, from, to).await),
+            Request::Rename { id, path, new_name } => Response::Rename(self.rename_file(id, path, &new_name).await),
+            Request::Archive { id, paths, format } => Response::Archive(self.archive(id, paths, format).await),
+            Request::Download { id, path } => Response::Download(self.download_file(id, path).await),
+            Request::Upload { id, dest_path, data } => Response::Upload(self.upload_file(id, dest_path, &data).await),
+            Request::StartStream { id, path, chunk_size } => Response::StartStream(self.start_stream(id, path, chunk_size).await),
+            Request::NextChunk { transfer_id } => {
+                Response::NextChunk(self.next_chunk(transfer_id).await.map(|(data, bytes_remaining)| (ByteBuf::from(data), bytes_remaining)))
+            }
+            Request::CancelStream { transfer_id } => Response::CancelStream(self.cancel_stream(transfer_id)),
+            Request::ChecksumMany { id, paths, algorithm } => Response::ChecksumMany(self.checksum_many(id, paths, algorithm).await),
+            Request::Checksum { id, path, algorithm } => Response::Checksum(self.compute_checksum(id, path, algorithm).await),
+            Request::SetSort { id, order, direction, directories_first } => {
+                Response::SetSort(self.set_cursor_sort(id, CursorSort { order, direction, directories_first }))
+            }
+            Request::SetShowHidden { show } => {
+                self.set_show_hidden(show);
+                Response::SetShowHidden(Ok(()))
+            }
+            Request::SetFollowSymlinks { follow } => {
+                self.set_follow_symlinks(follow);
+                Response::SetFollowSymlinks(Ok(()))
+            }
+            Request::Glob { id, pattern } => Response::Glob(self.glob(id, &pattern).await),
+            Request::Search { id, pattern, case_insensitive } => {
+                Response::Search(self.search_cursor(id, &pattern, case_insensitive).await)
+            }
+            Request::SearchRecursive { id, pattern, case_insensitive, max_depth } => {
+                Response::SearchRecursive(self.search_cursor_recursive(id, &pattern, case_insensitive, max_depth).await)
+            }
+            // Plugins are dispatched by the server before requests reach the Browser
+            Request::Plugin { type_id, .. } => Response::Plugin(Err(CursorError::UnknownPlugin { type_id, span_id: None })),
+            // Heartbeats are consumed by the server. Without one, they are simply echoed
+            Request::Heartbeat => Response::Heartbeat,
+            // Health checks are answered by the server, which knows when it started
+            Request::HealthCheck => Response::HealthCheck { uptime_secs: 0 },
+            Request::GetServerInfo => Response::GetServerInfo { version: crate_version(), capabilities: server_capabilities() },
+            // The server closes the connection after answering
+            Request::Goodbye => Response::Goodbye,
+        }
+    }
+}
+
+/// A Browser's Cursors stop counting towards its quota once it is gone, such as when its connection closes
+impl<F, S: CursorStore, R: RngCore> Drop for Browser<F, S, R> {
+    fn drop(&mut self) {
+        if let Some(quota) = &self.cursor_quota {
+            quota.release(self.cursors.len().try_into().unwrap_or(u32::MAX));
+        }
+    }
+}
+
+/// The state of a [`Browser::read_cursor_streaming`]
+enum StreamingRead {
+    // The Cursor's location, which has not been opened yet
+    Start(Result<PathBuf, CursorError>),
+    // A directory on the real file system being read entry by entry
+    Directory { path: PathBuf, read_dir: tokio::fs::ReadDir },
+    // A listing which had to be read in full
+    Listed(std::vec::IntoIter<FSElement>),
+    Done
+}
+
+/// The state of a [`Browser::depth_first_walk`]
+struct Walk {
+    // Elements which have been listed but not yet yielded, with the number of levels they are below the Cursor
+    stack: Vec<(PathBuf, FSElement, usize)>,
+    // A directory which must be listed before the next element is yielded, with the depth of its contents
+    expand: Option<(PathBuf, usize)>,
+    // The order of the Cursor the walk started from
+    sort: Option<CursorSort>
+}
+
+/// The output of a checksum task: the index of the path and the checksum
+type ChecksumTaskOutput = (usize, Result<Vec<u8>, io::Error>);
+
+/// Record the result of a finished checksum task. A task which panicked leaves its result empty
+fn store_checksum(results: &mut [Option<String>], finished: Option<Result<ChecksumTaskOutput, JoinError>>) {
+    if let Some(Ok((index, checksum))) = finished {
+        results[index] = Some(match checksum {
+            Ok(checksum) => hex::encode(checksum),
+            Err(err) => format!("ERROR: {err}"),
+        });
+    }
+}
+
+/// Copy a file, or a directory and all of its contents, within the real file system. Nothing at `to` is ever
+/// replaced. Symbolic links are not followed: a link at `from` is refused, and links inside of a directory are
+/// skipped, as are sockets and pipes. Whatever a failed copy created is removed again
+async fn copy_recursive(from: &Path, to: &Path) -> Result<(), io::Error> {
+    let metadata = tokio::fs::symlink_metadata(from).await?;
+    if metadata.is_file() {
+        return copy_new_file(from, to).await;
+    }
+    if !metadata.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only files and directories can be copied"));
+    }
+
+    tokio::fs::create_dir(to).await?;
+    let result = copy_dir_contents(from, to).await;
+    if result.is_err() {
+        // The copy has already failed, so a failure to clean up is not reported
+        let _ = tokio::fs::remove_dir_all(to).await;
+    }
+    result
+}
+
+/// Copy everything inside of the directory `from` into the empty directory `to`
+async fn copy_dir_contents(from: &Path, to: &Path) -> Result<(), io::Error> {
+    // Walk the tree with an explicit stack rather than recursing
+    let mut pending = vec![(from.to_owned(), to.to_owned())];
+    while let Some((from, to)) = pending.pop() {
+        let mut read_dir = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let destination = to.join(entry.file_name());
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                tokio::fs::create_dir(&destination).await?;
+                pending.push((entry.path(), destination));
+            } else if file_type.is_file() {
+                copy_new_file(&entry.path(), &destination).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the contents and permissions of a file to `to`, which must not exist yet. A partial copy is removed
+async fn copy_new_file(from: &Path, to: &Path) -> Result<(), io::Error> {
+    let mut source = tokio::fs::File::open(from).await?;
+    let mut destination = tokio::fs::OpenOptions::new().write(true).create_new(true).open(to).await?;
+
+    let result = async {
+        tokio::io::copy(&mut source, &mut destination).await?;
+        destination.set_permissions(source.metadata().await?.permissions()).await
+    }.await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(to).await;
+    }
+    result
+}
+
+/// Walk the real directory `base` for [`Browser::glob`], returning the paths relative to it which match `pattern`.
+/// Directories which cannot be read are skipped. This performs blocking I/O
+fn glob_walk(base: &Path, pattern: &glob::Pattern, max_depth: u32) -> Result<Vec<PathBuf>, CursorError> {
+    // '*' must not match across directories, as in a shell
+    let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+
+    let mut matches = vec![];
+    let mut pending = vec![(PathBuf::new(), 0)];
+    while let Some((relative_dir, depth)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(base.join(&relative_dir)) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            // The file type of a DirEntry describes the link itself rather than its target
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_symlink() {
+                continue;
+            }
+
+            let relative_path = relative_dir.join(entry.file_name());
+            if pattern.matches_path_with(&relative_path, options) {
+                if matches.len() == MAX_GLOB_RESULTS {
+                    return Err(CursorError::TooManyResults { limit: MAX_GLOB_RESULTS, span_id: None });
+                }
+                matches.push(relative_path.clone());
+            }
+            if file_type.is_dir() && depth + 1 < max_depth {
+                pending.push((relative_path, depth + 1));
+            }
         }
     }
+
+    Ok(matches)
+}
+
+/// The name of a path within an archive created at the location `base`
+fn archive_name(base: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(base) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_owned(),
+        _ => path.components()
+            .filter(|component| matches!(component, Component::Normal(..)))
+            .collect()
+    }
+}
+
+/// Seed the default RNG from the OS. `SmallRng::from_entropy` panics if the OS cannot provide entropy, so the
+/// current time is used as the seed instead in that case
+fn default_rng() -> SmallRng {
+    SmallRng::from_rng(OsRng).unwrap_or_else(|err| {
+        tracing::warn!("Could not seed the cursor ID generator from the OS, using the current time: {err}");
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        SmallRng::seed_from_u64(now.as_nanos() as u64)
+    })
 }
 
-fn cmp_fs_elements(element1: &FSElement, element2: &FSElement) -> Ordering {
-    element1.name.cmp(&element2.name)
+/// A panic caught while the file system was listing a directory
+#[derive(Error, Debug)]
+#[error("The file system panicked: {0}")]
+struct FSPanic(String);
+
+/// Await a listing of `path`, turning a panic into a [`CursorError::ReadError`]. A bug in an FS implementation then
+/// fails the request instead of ending the connection
+async fn catch_fs_panic<T>(path: &Path, listing: impl Future<Output = T>) -> Result<T, CursorError> {
+    AssertUnwindSafe(listing).catch_unwind().await.map_err(|payload| {
+        let message = payload.downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_owned());
+        tracing::error!("The file system panicked while listing {}: {message}", path.display());
+
+        CursorError::ReadError {
+            path: path.to_owned(),
+            kind: "internal error".to_owned(),
+            source: Box::new(FSPanic(message)),
+            span_id: None
+        }
+    })
+}
+
+/// Fail with [`CursorError::PermissionDenied`] unless the client at `client_addr` may list the virtual root which
+/// `path` is inside of. The root itself may always be listed
+fn check_acl(acl: Option<&Acl>, client_addr: Option<SocketAddr>, path: &Path) -> Result<(), CursorError> {
+    let root = path.components().find_map(|component| match component {
+        Component::Normal(name) => Some(name),
+        _ => None,
+    });
+
+    match (acl, root) {
+        (Some(acl), Some(root)) if !acl.is_permitted(root, client_addr.map(|addr| addr.ip())) => {
+            Err(CursorError::PermissionDenied { path: path.to_owned(), span_id: None })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Leave out the elements listed at `directory` which the client may not list, which are only ever denied virtual
+/// roots
+fn retain_permitted(acl: Option<&Acl>, client_addr: Option<SocketAddr>, directory: &Path, elements: &mut Vec<FSElement>) {
+    if acl.is_some() {
+        elements.retain(|element| check_acl(acl, client_addr, &directory.join(&element.name)).is_ok());
+    }
+}
+
+/// Describe why reading `path` failed, using the first io::Error found in the chain of sources of `err`
+fn read_error<E: Error + Send + Sync + 'static>(path: PathBuf, err: E) -> CursorError {
+    match io_error_kind(&err) {
+        io::ErrorKind::NotFound => CursorError::NotFound { path, span_id: None },
+        io::ErrorKind::PermissionDenied => CursorError::PermissionDenied { path, span_id: None },
+        io::ErrorKind::NotADirectory => CursorError::NotADirectory { path, span_id: None },
+        kind => CursorError::ReadError { path, kind: format!("{kind:?}"), source: Box::new(err), span_id: None },
+    }
+}
+
+/// The kind of the first io::Error found in the chain of sources of `err`, or `Other` if there is none
+fn io_error_kind(err: &(dyn Error + 'static)) -> io::ErrorKind {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        match err.downcast_ref::<io::Error>() {
+            Some(err) => return err.kind(),
+            None => source = err.source(),
+        }
+    }
+    io::ErrorKind::Other
+}
+
+/// Matches the names of elements against the glob pattern of a [`Request::Search`]
+struct NameMatcher {
+    // None matches every name
+    pattern: Option<glob::Pattern>,
+    options: glob::MatchOptions
+}
+
+impl NameMatcher {
+    fn new(pattern: &str, case_insensitive: bool) -> Result<Self, CursorError> {
+        let pattern = match pattern {
+            "" => None,
+            pattern => Some(glob::Pattern::new(pattern)
+                .map_err(|_| CursorError::InvalidPattern { pattern: pattern.to_owned(), span_id: None })?),
+        };
+        let options = glob::MatchOptions { case_sensitive: !case_insensitive, ..glob::MatchOptions::new() };
+        Ok(NameMatcher { pattern, options })
+    }
+
+    fn matches(&self, element: &FSElement) -> bool {
+        self.pattern.as_ref().is_none_or(|pattern| pattern.matches_with(&element.name_lossy(), self.options))
+    }
+}
+
+/// Elements with names starting with '.' are hidden by convention
+fn is_hidden(element: &FSElement) -> bool {
+    element.name_lossy().starts_with('.')
+}
+
+/// Sort elements in the order of a Cursor, or by FSElement's own order if neither the Cursor nor the Browser
+/// chose one
+fn sort_elements(sort: Option<CursorSort>, elements: &mut [FSElement]) {
+    match sort {
+        Some(sort) => sort.sort(elements),
+        None => elements.sort_unstable(),
+    }
 }
 
-fn get_cursor(cursors: &HashMap<u16, Cursor>, id: u16) -> Result<&Cursor, CursorError> {
+fn get_cursor<S: CursorStore>(cursors: &S, id: u16) -> Result<&Cursor, CursorError> {
     cursors
-        .get(&id)
-        .ok_or(CursorError::UnknownCursor)
+        .get(id)
+        .ok_or(CursorError::UnknownCursor { span_id: None })
 }
 
-fn get_cursor_mut(cursors: &mut HashMap<u16, Cursor>, id: u16) -> Result<&mut Cursor, CursorError> {
+fn get_cursor_mut<S: CursorStore>(cursors: &mut S, id: u16) -> Result<&mut Cursor, CursorError> {
     cursors
-        .get_mut(&id)
-        .ok_or(CursorError::UnknownCursor)
+        .get_mut(id)
+        .ok_or(CursorError::UnknownCursor { span_id: None })
 }
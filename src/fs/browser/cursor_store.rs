@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::Cursor;
+
+/// Storage for the Cursors of a [`super::Browser`], keyed by Cursor ID
+pub trait CursorStore {
+    /// Look up a Cursor by ID
+    fn get(&self, id: u16) -> Option<&Cursor>;
+
+    /// Look up a Cursor by ID for modification
+    fn get_mut(&mut self, id: u16) -> Option<&mut Cursor>;
+
+    /// Store a Cursor, replacing any Cursor with the same ID
+    fn insert(&mut self, id: u16, cursor: Cursor);
+
+    /// Remove a Cursor, returning it if it existed
+    fn remove(&mut self, id: u16) -> Option<Cursor>;
+
+    /// The number of Cursors stored
+    fn len(&self) -> usize;
+
+    /// Returns true if no Cursors are stored
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over every (ID, Cursor) pair in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = (u16, &Cursor)> + '_>;
+}
+
+impl CursorStore for HashMap<u16, Cursor> {
+    fn get(&self, id: u16) -> Option<&Cursor> {
+        HashMap::get(self, &id)
+    }
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Cursor> {
+        HashMap::get_mut(self, &id)
+    }
+
+    fn insert(&mut self, id: u16, cursor: Cursor) {
+        HashMap::insert(self, id, cursor);
+    }
+
+    fn remove(&mut self, id: u16) -> Option<Cursor> {
+        HashMap::remove(self, &id)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (u16, &Cursor)> + '_> {
+        Box::new(HashMap::iter(self).map(|(id, cursor)| (*id, cursor)))
+    }
+}
+
+/// Keeps the Cursors ordered by ID, which makes iterating over many of them cheaper
+impl CursorStore for BTreeMap<u16, Cursor> {
+    fn get(&self, id: u16) -> Option<&Cursor> {
+        BTreeMap::get(self, &id)
+    }
+
+    fn get_mut(&mut self, id: u16) -> Option<&mut Cursor> {
+        BTreeMap::get_mut(self, &id)
+    }
+
+    fn insert(&mut self, id: u16, cursor: Cursor) {
+        BTreeMap::insert(self, id, cursor);
+    }
+
+    fn remove(&mut self, id: u16) -> Option<Cursor> {
+        BTreeMap::remove(self, &id)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (u16, &Cursor)> + '_> {
+        Box::new(BTreeMap::iter(self).map(|(id, cursor)| (*id, cursor)))
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::{Browser, Request, Response};
+use crate::fs::FS;
+
+/// A [`Browser`] which can be shared between tasks. Clones refer to the same Browser, so every task sees the same
+/// Cursors. Requests are processed one at a time, in the order the lock is acquired
+pub struct BrowserHandle<F: FS> {
+    browser: Arc<Mutex<Browser<F>>>
+}
+
+impl<F: FS> Clone for BrowserHandle<F> {
+    fn clone(&self) -> Self {
+        BrowserHandle { browser: self.browser.clone() }
+    }
+}
+
+impl<F: FS> BrowserHandle<F> {
+    pub fn new(browser: Browser<F>) -> Self {
+        BrowserHandle { browser: Arc::new(Mutex::new(browser)) }
+    }
+
+    /// Process a request with [`Browser::process`]. The lock is released before returning, so a listing lent by
+    /// the Browser is copied into the response
+    pub async fn process(&self, request: Request) -> Response<'static> {
+        self.browser.lock().await.process(request).await.into_owned()
+    }
+
+    /// Obtain the next unsolicited frame, see [`Browser::next_frame`]. Another task may process a request between
+    /// two calls, so frames are only guaranteed to follow their request if the Browser is locked with
+    /// [`BrowserHandle::lock`] for the whole exchange
+    pub async fn next_frame(&self) -> Option<Response<'static>> {
+        self.browser.lock().await.next_frame()
+    }
+
+    /// Lock the Browser for direct access. Every other user of the handle waits until the guard is dropped
+    pub async fn lock(&self) -> MutexGuard<'_, Browser<F>> {
+        self.browser.lock().await
+    }
+}
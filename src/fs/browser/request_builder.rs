@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use crate::fs::{archive::ArchiveFormat, checksum::ChecksumAlgorithm};
+
+use super::Request;
+
+/// Builds the [`Request`] variants which take several settings. Each constructor returns a builder which only
+/// offers the settings that apply to that kind of request, so for example a checksum algorithm can never be
+/// given to a read. The wire format is the same as constructing the [`Request`] directly
+pub struct RequestBuilder;
+
+impl RequestBuilder {
+    /// Read the elements at the Cursor's location
+    pub fn read(id: u16) -> ReadBuilder {
+        ReadBuilder { id, filter: None }
+    }
+
+    /// Compute checksums of files relative to the Cursor's location
+    pub fn checksum(id: u16, algorithm: ChecksumAlgorithm) -> ChecksumBuilder {
+        ChecksumBuilder { id, algorithm, paths: vec![] }
+    }
+
+    /// Create an archive of files and directories relative to the Cursor's location
+    pub fn archive(id: u16, format: ArchiveFormat) -> ArchiveBuilder {
+        ArchiveBuilder { id, format, paths: vec![] }
+    }
+}
+
+pub struct ReadBuilder {
+    id: u16,
+    filter: Option<String>
+}
+
+impl ReadBuilder {
+    /// Only read the elements matching a glob pattern, such as "*.rs"
+    pub fn filter<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.filter = Some(pattern.into());
+        self
+    }
+
+    pub fn build(self) -> Request {
+        match self.filter {
+            Some(pattern) => Request::Glob { id: self.id, pattern },
+            None => Request::Read { id: self.id },
+        }
+    }
+}
+
+pub struct ChecksumBuilder {
+    id: u16,
+    algorithm: ChecksumAlgorithm,
+    paths: Vec<PathBuf>
+}
+
+impl ChecksumBuilder {
+    pub fn path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    pub fn paths<I: IntoIterator<Item = P>, P: Into<PathBuf>>(mut self, paths: I) -> Self {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn build(self) -> Request {
+        Request::ChecksumMany { id: self.id, paths: self.paths, algorithm: self.algorithm }
+    }
+}
+
+pub struct ArchiveBuilder {
+    id: u16,
+    format: ArchiveFormat,
+    paths: Vec<PathBuf>
+}
+
+impl ArchiveBuilder {
+    pub fn path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    pub fn paths<I: IntoIterator<Item = P>, P: Into<PathBuf>>(mut self, paths: I) -> Self {
+        self.paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn build(self) -> Request {
+        Request::Archive { id: self.id, paths: self.paths, format: self.format }
+    }
+}
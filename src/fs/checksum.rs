@@ -0,0 +1,60 @@
+use std::{path::Path, io};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use tokio::io::{AsyncReadExt, BufReader};
+
+/// The hash algorithms which the server can compute checksums with
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Blake3
+}
+
+/// Incremental state for each of the supported algorithms
+enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>)
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Compute the checksum of a file in the real file system, reading it in pieces
+pub async fn checksum_file<P: AsRef<Path>>(path: P, algorithm: ChecksumAlgorithm) -> Result<Vec<u8>, io::Error> {
+    let mut reader = BufReader::new(tokio::fs::File::open(path).await?);
+    let mut hasher = Hasher::new(algorithm);
+
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
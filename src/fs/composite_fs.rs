@@ -0,0 +1,272 @@
+use std::{error::Error, ffi::{OsStr, OsString}, fmt, io, path::{Component, Path, PathBuf}};
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+use super::{FSElement, ListOptions, FS, Unsupported};
+
+/// Joins several file systems into one, each mounted as a directory at the root. Paths are routed by their first
+/// component, so "/scratch/notes.txt" is "/notes.txt" within the file system mounted as "scratch". The root lists
+/// one directory for each mounted file system
+#[derive(Default)]
+pub struct CompositeFS {
+    mounts: Vec<(OsString, Box<dyn DynFS>)>
+}
+
+impl fmt::Debug for CompositeFS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompositeFS").field("mounts", &self.mounted().collect::<Vec<_>>()).finish()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CompositeFSError {
+    /// Carries an io::Error of kind NotFound as its source, so [`super::browser::Browser`] reports a missing path
+    #[error("The path {0} is not inside a mounted file system")]
+    NotFound(PathBuf, #[source] io::Error),
+
+    #[error("{0}")]
+    Backend(#[source] Box<dyn Error + Send + Sync>),
+
+    #[error(transparent)]
+    Unsupported(#[from] Unsupported)
+}
+
+impl CompositeFSError {
+    fn not_found(path: &Path) -> Self {
+        CompositeFSError::NotFound(path.to_owned(), io::ErrorKind::NotFound.into())
+    }
+}
+
+/// The part of [`FS`] used by a [`CompositeFS`], with the path types and errors erased so file systems of different
+/// types can be kept side by side
+#[async_trait]
+trait DynFS: Send + Sync {
+    async fn list(&self, path: &Path) -> Result<Vec<FSElement>, CompositeFSError>;
+    async fn list_with_options(&self, path: &Path, options: ListOptions) -> Result<Vec<FSElement>, CompositeFSError>;
+    async fn list_cancellable(&self, path: &Path, cancel: CancellationToken) -> Result<Vec<FSElement>, CompositeFSError>;
+    async fn metadata(&self, path: &Path) -> Result<FSElement, CompositeFSError>;
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<(), CompositeFSError>;
+    async fn rename(&self, from: &Path, to: &Path) -> Result<(), CompositeFSError>;
+    async fn rename_no_replace(&self, from: &Path, to: &Path) -> Result<(), CompositeFSError>;
+    fn unmap(&self, path: &Path) -> Result<PathBuf, CompositeFSError>;
+    fn is_writable(&self, path: &Path) -> bool;
+}
+
+fn backend<E: Error + Send + Sync + 'static>(err: E) -> CompositeFSError {
+    CompositeFSError::Backend(Box::new(err))
+}
+
+#[async_trait]
+impl<F: FS> DynFS for F {
+    async fn list(&self, path: &Path) -> Result<Vec<FSElement>, CompositeFSError> {
+        F::list(self, path).await.map_err(backend)
+    }
+
+    async fn list_with_options(&self, path: &Path, options: ListOptions) -> Result<Vec<FSElement>, CompositeFSError> {
+        F::list_with_options(self, path, options).await.map_err(backend)
+    }
+
+    async fn list_cancellable(&self, path: &Path, cancel: CancellationToken) -> Result<Vec<FSElement>, CompositeFSError> {
+        F::list_cancellable(self, path, cancel).await.map_err(backend)
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<FSElement, CompositeFSError> {
+        F::metadata(self, path).await.map_err(backend)
+    }
+
+    async fn write(&self, path: &Path, data: &[u8]) -> Result<(), CompositeFSError> {
+        F::write(self, path, data).await.map_err(backend)
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> Result<(), CompositeFSError> {
+        F::rename(self, from, to).await.map_err(backend)
+    }
+
+    async fn rename_no_replace(&self, from: &Path, to: &Path) -> Result<(), CompositeFSError> {
+        F::rename_no_replace(self, from, to).await.map_err(backend)
+    }
+
+    fn unmap(&self, path: &Path) -> Result<PathBuf, CompositeFSError> {
+        F::unmap(self, path).map_err(backend)
+    }
+
+    fn is_writable(&self, path: &Path) -> bool {
+        F::is_writable(self, path)
+    }
+}
+
+/// Split a path into its first component and the rest of the path, which is made absolute. None if the path is the
+/// root. Paths which leave the root through ".." do not exist
+fn split_mount(path: &Path) -> Result<Option<(&OsStr, PathBuf)>, CompositeFSError> {
+    let mut components = path.components().filter(|component| !matches!(component, Component::RootDir | Component::CurDir));
+    let name = match components.next() {
+        Some(Component::Normal(name)) => name,
+        Some(_) => return Err(CompositeFSError::not_found(path)),
+        None => return Ok(None),
+    };
+
+    let mut rest = PathBuf::from("/");
+    for component in components {
+        match component {
+            Component::Normal(part) => rest.push(part),
+            _ => return Err(CompositeFSError::not_found(path)),
+        }
+    }
+    Ok(Some((name, rest)))
+}
+
+impl CompositeFS {
+    pub fn new() -> Self {
+        CompositeFS::default()
+    }
+
+    /// Mount a file system as the directory `name` at the root, replacing any file system already mounted there.
+    /// Panics if `name` is not a single path component
+    pub fn mount<F: FS + 'static>(&mut self, name: OsString, fs: F) -> &mut Self {
+        let mut components = Path::new(&name).components();
+        if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+            panic!("The name {name:?} cannot be mounted, since it is not a single path component");
+        }
+
+        let fs: Box<dyn DynFS> = Box::new(fs);
+        match self.mounts.iter_mut().find(|(mounted, _)| *mounted == name) {
+            Some((_, mounted)) => *mounted = fs,
+            None => self.mounts.push((name, fs)),
+        }
+        self
+    }
+
+    /// The names of the mounted file systems, in the order they were mounted
+    pub fn mounted(&self) -> impl Iterator<Item = &OsStr> {
+        self.mounts.iter().map(|(name, _)| name.as_os_str())
+    }
+
+    /// The file system a path is routed to and the path within it, or None for the root
+    fn route(&self, path: &Path) -> Result<Option<(&dyn DynFS, PathBuf)>, CompositeFSError> {
+        let Some((name, rest)) = split_mount(path)? else {
+            return Ok(None);
+        };
+
+        match self.mounts.iter().find(|(mounted, _)| mounted == name) {
+            Some((_, fs)) => Ok(Some((fs.as_ref(), rest))),
+            None => Err(CompositeFSError::not_found(path)),
+        }
+    }
+
+    /// The file system both sides of a rename are routed to, and the paths within it
+    fn route_rename(&self, from: &Path, to: &Path) -> Result<(&dyn DynFS, PathBuf, PathBuf), CompositeFSError> {
+        let from_mount = split_mount(from)?.map(|(name, _)| name);
+        let to_mount = split_mount(to)?.map(|(name, _)| name);
+        if from_mount.is_none() || to_mount.is_none() {
+            return Err(Unsupported("renaming the root of a composite file system").into());
+        }
+        if from_mount != to_mount {
+            return Err(Unsupported("renaming between mounted file systems").into());
+        }
+
+        match (self.route(from)?, self.route(to)?) {
+            (Some((fs, from)), Some((_, to))) => Ok((fs, from, to)),
+            _ => unreachable!("Neither path is the root"),
+        }
+    }
+
+    /// One directory for each mounted file system, described by the root of that file system
+    async fn list_mounts(&self) -> Result<Vec<FSElement>, CompositeFSError> {
+        let mut elements = Vec::with_capacity(self.mounts.len());
+        for (name, fs) in &self.mounts {
+            let element = fs.metadata(Path::new("/")).await?;
+            elements.push(FSElement { name: name.clone(), ..element });
+        }
+        Ok(elements)
+    }
+}
+
+#[async_trait]
+impl FS for CompositeFS {
+    type Error = CompositeFSError;
+
+    async fn list<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<Vec<FSElement>, CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => fs.list(&rest).await,
+            None => self.list_mounts().await,
+        }
+    }
+
+    async fn list_with_options<P: AsRef<Path> + Send + Sync>(&self, path: P, options: ListOptions) -> Result<Vec<FSElement>, CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => fs.list_with_options(&rest, options).await,
+            None => self.list_mounts().await,
+        }
+    }
+
+    async fn list_cancellable<P: AsRef<Path> + Send + Sync>(&self, path: P, cancel: CancellationToken) -> Result<Vec<FSElement>, CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => fs.list_cancellable(&rest, cancel).await,
+            None => self.list_mounts().await,
+        }
+    }
+
+    /// The root is described as an empty directory without timestamps, like the root of a
+    /// [`super::mapped_fs::MappedFS`]
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<FSElement, CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => {
+                let element = fs.metadata(&rest).await?;
+                match rest == Path::new("/") {
+                    // The root of the mounted file system is named after its mount
+                    true => Ok(FSElement { name: path.as_ref().file_name().unwrap_or_default().to_owned(), ..element }),
+                    false => Ok(element),
+                }
+            }
+            None => Ok(FSElement {
+                name: OsString::new(),
+                created: None,
+                modified: None,
+                size: 0,
+                is_file: false,
+                is_symlink: false,
+                hash: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                inode: None
+            }),
+        }
+    }
+
+    async fn write<P: AsRef<Path> + Send + Sync>(&self, path: P, data: &[u8]) -> Result<(), CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => fs.write(&rest, data).await,
+            None => Err(Unsupported("writing to the root of a composite file system").into()),
+        }
+    }
+
+    /// Both paths must be inside the same mounted file system
+    async fn rename<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), CompositeFSError> {
+        let (fs, from, to) = self.route_rename(from.as_ref(), to.as_ref())?;
+        fs.rename(&from, &to).await
+    }
+
+    /// Both paths must be inside the same mounted file system
+    async fn rename_no_replace<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), CompositeFSError> {
+        let (fs, from, to) = self.route_rename(from.as_ref(), to.as_ref())?;
+        fs.rename_no_replace(&from, &to).await
+    }
+
+    fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, CompositeFSError> {
+        match self.route(path.as_ref())? {
+            Some((fs, rest)) => fs.unmap(&rest),
+            None => Err(CompositeFSError::not_found(path.as_ref())),
+        }
+    }
+
+    /// The root is never writable
+    fn is_writable<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.route(path.as_ref()) {
+            Ok(Some((fs, rest))) => fs.is_writable(&rest),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::{atomic::{AtomicU32, Ordering}, Arc};
+
+/// Limits the number of Cursors open at once, shared between every Browser using it. A Browser's own limit still
+/// applies on top of this one
+#[derive(Clone, Debug)]
+pub struct CursorQuota {
+    used: Arc<AtomicU32>,
+    max: u32
+}
+
+impl CursorQuota {
+    /// Count the open Cursors in `used`, allowing at most `max` of them
+    pub fn new(used: Arc<AtomicU32>, max: u32) -> Self {
+        CursorQuota { used, max }
+    }
+
+    /// The number of Cursors currently open across every Browser using the quota
+    pub fn used(&self) -> u32 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// The most Cursors which may be open at once
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Count one more Cursor, unless the quota is already used up
+    pub(crate) fn try_acquire(&self) -> bool {
+        let mut used = self.used.load(Ordering::Relaxed);
+        while used < self.max {
+            match self.used.compare_exchange_weak(used, used + 1, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(current) => used = current,
+            }
+        }
+        false
+    }
+
+    /// Stop counting `count` Cursors
+    pub(crate) fn release(&self, count: u32) {
+        self.used.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    /// Count Cursors which were created before the quota applied to them. This may take the count past `max`
+    pub(crate) fn add(&self, count: u32) {
+        self.used.fetch_add(count, Ordering::Relaxed);
+    }
+}
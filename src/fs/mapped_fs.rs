@@ -1,35 +1,80 @@
-use std::{path::{PathBuf, Path, Component}, collections::{HashMap, hash_map::Entry}, ffi::{OsString, OsStr}, sync::{RwLock, Arc}};
+use std::{path::{PathBuf, Path, Component, Components}, ffi::{OsString, OsStr}, sync::{Arc, RwLock, atomic::{AtomicU64, Ordering}}, collections::HashMap};
 use std::{time::SystemTime, io};
 
 use anyhow::Context;
 use async_trait::async_trait;
+use futures::{stream::FuturesUnordered, StreamExt};
 use thiserror::Error;
 use time::{OffsetDateTime, UtcOffset};
+use tokio::{sync::Semaphore, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 
-use super::{FSElement, FS};
+use super::{FSElement, FS, ListOptions, ListMode, Unsupported, enters};
+
+use self::backend::{MapBackend, LockedMap, ConcurrentMap, Insertion, MappingEntry};
+use self::metrics::MappedFSMetrics;
+
+pub mod backend;
+pub mod metrics;
 
 /// Convert a SystemTime into a OffsetDateTime with the local offset
 fn convert_time(time: SystemTime) -> Result<OffsetDateTime, anyhow::Error>
 {
-    let duration = time.duration_since(SystemTime::UNIX_EPOCH)
-        .context("Time value too small for conversion")?;
-    let converted: i64 = duration.as_secs()
-        .try_into()
-        .context("Time value too large for conversion")?;
+    // Times before the epoch, such as 1969-12-31, become negative timestamps
+    let nanos: i128 = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_nanos().try_into(),
+        Err(err) => err.duration().as_nanos().try_into().map(|nanos: i128| -nanos),
+    }.context("Time value too large for conversion")?;
 
+    // Keep the sub-second part so that changes made within the same second can be told apart.
     // If we are capable of obtaining the system offset, use it to adjust the timestamp
-    let mut timestamp = OffsetDateTime::from_unix_timestamp(converted)?;
+    let mut timestamp = OffsetDateTime::from_unix_timestamp_nanos(nanos)?;
     if let Ok(offset) = UtcOffset::current_local_offset() {
         timestamp = timestamp.to_offset(offset);
     }
 
     Ok(timestamp)
-    // Ok(OffsetDateTime::from_unix_timestamp(converted)?)
+}
+
+/// The default number of elements whose metadata is read at the same time, see [`MappedFS::set_list_concurrency`]
+const DEFAULT_LIST_CONCURRENCY: usize = 64;
+
+/// The most directories [`MappedFS::list_recursive`] lists at the same time. Each one holds a file descriptor
+/// while its entries are read
+const RECURSIVE_LIST_CONCURRENCY: usize = 16;
+
+/// Obtain a file system element in a new task once one of `permits` is available, so only a bounded number of
+/// elements are looked up (each holding a file descriptor) at once. The task stops early and returns None once
+/// `cancel` is cancelled
+async fn spawn_get_element(
+    name: OsString,
+    path: PathBuf,
+    follow_symlinks: bool,
+    permits: Arc<Semaphore>,
+    cancel: CancellationToken
+) -> JoinHandle<Option<Result<FSElement, io::Error>>> {
+    let permit = tokio::select! {
+        permit = permits.acquire_owned() => permit.expect("The listing semaphore is never closed"),
+        _ = cancel.cancelled() => return tokio::spawn(async { None }),
+    };
+
+    tokio::spawn(async move {
+        // Returned to the semaphore when the task ends, however it ends
+        let _permit = permit;
+        tokio::select! {
+            element = get_element(name, path, follow_symlinks) => Some(element),
+            _ = cancel.cancelled() => None,
+        }
+    })
 }
 
 /// Obtain a file system element. Looks up metadata from the real file system and may fail
-async fn get_element<S: AsRef<OsStr>, P: AsRef<Path>>(name: S, path: P) -> Result<FSElement, io::Error> {
-    let metadata = tokio::fs::metadata(path).await?;
+pub(crate) async fn get_element<S: AsRef<OsStr>, P: AsRef<Path>>(name: S, path: P, follow_symlinks: bool) -> Result<FSElement, io::Error> {
+    let mut metadata = tokio::fs::symlink_metadata(&path).await?;
+    let is_symlink = metadata.is_symlink();
+    if is_symlink && follow_symlinks {
+        metadata = tokio::fs::metadata(&path).await?;
+    }
     
     let created = metadata
         .created()
@@ -43,12 +88,26 @@ async fn get_element<S: AsRef<OsStr>, P: AsRef<Path>>(name: S, path: P) -> Resul
         .and_then(convert_time)
         .ok();
 
+    #[cfg(unix)]
+    let (mode, uid, gid, inode) = {
+        use std::os::unix::fs::MetadataExt;
+        (Some(metadata.mode()), Some(metadata.uid()), Some(metadata.gid()), Some(metadata.ino()))
+    };
+    #[cfg(not(unix))]
+    let (mode, uid, gid, inode) = (None, None, None, None);
+
     let element = FSElement {
         name: name.as_ref().to_owned(),
         created,
         modified,
         size: metadata.len(),
         is_file: metadata.is_file(),
+        is_symlink,
+        hash: None,
+        mode,
+        uid,
+        gid,
+        inode
     };
 
     Ok(element)
@@ -60,7 +119,30 @@ pub enum MappedFSError {
     PathNotFound(PathBuf, #[source] anyhow::Error),
 
     #[error("The path {0} is not absolute. Only absolute paths can be added")]
-    PathNotAbsolute(PathBuf)
+    PathNotAbsolute(PathBuf),
+
+    #[error("The listing was cancelled")]
+    Cancelled,
+
+    #[error("The path {real_path} is already mapped as {virtual_name:?}")]
+    AlreadyMapped { real_path: PathBuf, virtual_name: OsString },
+
+    #[error("The name {0:?} is already used by a different path")]
+    NameTaken(OsString),
+
+    #[error("The name {0:?} cannot be used, since it is not a single path component")]
+    InvalidName(OsString),
+
+    #[error(transparent)]
+    Unsupported(#[from] Unsupported)
+}
+
+/// Lets errors be converted with `?` by pairing them with the path they concern, as in
+/// `.map_err(|err| (path.to_owned(), err))?`
+impl<E: Into<anyhow::Error>> From<(PathBuf, E)> for MappedFSError {
+    fn from((path, err): (PathBuf, E)) -> Self {
+        MappedFSError::PathNotFound(path, err.into())
+    }
 }
 
 enum ParsedPath {
@@ -72,44 +154,172 @@ fn parse_path<P: AsRef<Path>>(path: P) -> Result<ParsedPath, MappedFSError> {
     let path_not_found_err =
         |err| MappedFSError::PathNotFound(path.as_ref().to_owned(), err);
 
-    // Ensure the path is limited to the designated file system area
+    // Skip the start of the path. An absolute Windows path such as C:\Users\test begins with a Prefix (C:) and
+    // then a RootDir, before its first Normal component
     let mut components = path.as_ref().components();
-    let mut first_component = true;
+    skip_component(&mut components, |component| matches!(component, Component::Prefix(..)));
+    skip_component(&mut components, |component| matches!(component, Component::RootDir | Component::CurDir));
+
+    // Ensure the path is limited to the designated file system area
     let mut parsed_path = ParsedPath::Root;
     while let Some(component) = components.next() {
         match component {
-            Component::Prefix(..) | Component::CurDir if first_component => (), 
-            Component::RootDir => (),
-            Component::Normal(_name) if matches!(parsed_path, ParsedPath::Root) => {
+            Component::Normal(name) if matches!(parsed_path, ParsedPath::Root) => {
                 parsed_path = ParsedPath::Extended {
-                    root_element: _name.to_owned(), 
+                    root_element: name.to_owned(),
                     extension: components.as_path().to_owned()
                 };
             }
             Component::Normal(..) => (),
-            _ => return Err(path_not_found_err(anyhow::anyhow!("The path contains illegal components such as '.' or '..'")))
+            _ => return Err(path_not_found_err(not_found("The path contains illegal components such as '.' or '..'")))
         }
-        first_component = false;
     };
 
     Ok(parsed_path)
 }
 
+/// An error for a path which does not resolve to anything in the mapped FS. It is an io::Error, so its kind
+/// survives being wrapped by the callers
+fn not_found(message: &'static str) -> anyhow::Error {
+    io::Error::new(io::ErrorKind::NotFound, message).into()
+}
+
+/// Move the real element at `from` to `to`, failing if anything is already at `to`
+async fn rename_no_replace(from: &Path, to: &Path) -> Result<(), io::Error> {
+    if !tokio::fs::symlink_metadata(from).await?.is_dir() {
+        // Creating a hard link fails if the name is taken, where a rename would replace whatever has it
+        tokio::fs::hard_link(from, to).await?;
+        return tokio::fs::remove_file(from).await;
+    }
+
+    if cfg!(unix) {
+        // A directory may only replace an empty directory, so creating one claims the name. Anything put inside
+        // of it in the meantime makes the rename fail instead
+        tokio::fs::create_dir(to).await?;
+        let result = tokio::fs::rename(from, to).await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_dir(to).await;
+        }
+        result
+    } else {
+        if tokio::fs::symlink_metadata(to).await.is_ok() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        tokio::fs::rename(from, to).await
+    }
+}
+
+/// Advance past the next component if `f` returns true for it
+fn skip_component(components: &mut Components<'_>, f: impl FnOnce(Component<'_>) -> bool) {
+    let mut rest = components.clone();
+    if rest.next().is_some_and(f) {
+        *components = rest;
+    }
+}
+
+/// The version of the snapshot format written by [`MappedFS::to_json_snapshot`]
+#[cfg(feature = "json")]
+const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// A copy of every mapping in a mapped FS, for sharing it with another process
+#[cfg(feature = "json")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    format_version: u32,
+    mappings: Vec<(OsString, MappingEntry)>
+}
+
+/// Parse a snapshot written by [`MappedFS::to_json_snapshot`], failing if it uses a different format version
+#[cfg(feature = "json")]
+fn parse_snapshot(json: &str) -> Result<Snapshot, anyhow::Error> {
+    let snapshot: Snapshot = serde_json::from_str(json).context("The snapshot is not valid JSON")?;
+    if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+        anyhow::bail!(
+            "The snapshot format version {} is not supported, expected {SNAPSHOT_FORMAT_VERSION}",
+            snapshot.format_version
+        );
+    }
+    Ok(snapshot)
+}
+
+/// The locks inside a MappedFS are `std::sync::RwLock`s rather than Tokio's on purpose. A guard is only held for a
+/// single lookup or update of a map and never across an `.await` (the guards are not `Send`, so a task holding one
+/// across an `.await` would not compile when spawned), so waiting on one never stalls an executor thread for
+/// longer than a map operation. Tokio's lock would make `add`, `remove` and `unmap` async, and with them
+/// [`FS::unmap`] and every Browser method that resolves a path, without making any of them wait less
 #[derive(Clone)]
 pub struct MappedFS {
-    map: Arc<RwLock<HashMap<OsString, PathBuf>>>
+    map: Arc<dyn MapBackend>,
+    /// Elements fetched by [`MappedFS::add_with_prefetch`], keyed by virtual name. Shared between clones
+    metadata_cache: Arc<RwLock<HashMap<OsString, FSElement>>>,
+    metrics: Option<Arc<MappedFSMetrics>>,
+    /// Bounds the number of elements looked up at once by listings. Shared between clones
+    list_permits: Arc<Semaphore>
+}
+
+impl Default for MappedFS {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MappedFS {
+    /// Create a mapped FS backed by a `RwLock` protected `HashMap`
     pub fn new() -> Self {
-        MappedFS { map: Arc::new(RwLock::new(HashMap::new())) }
+        Self::with_backend(LockedMap::default())
+    }
+
+    /// Create a mapped FS backed by a `DashMap`, which performs better when many connections list concurrently
+    pub fn new_concurrent() -> Self {
+        Self::with_backend(ConcurrentMap::default())
     }
 
-    /// Add a new file or directory to the mapped filesystem. Does nothing if the element has already been
-    /// added previously. If two different elements with the same name are added, a number will be appended
-    /// to the name of the more recent element. For example, if two files named 'test.txt' are added, the
-    /// name of the second file within the virtual filesystem will be 'test.txt (1)'
-    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Result<(), MappedFSError> {
+    /// Create a mapped FS using a custom map backend
+    pub fn with_backend<B: MapBackend + 'static>(backend: B) -> Self {
+        MappedFS {
+            map: Arc::new(backend),
+            metadata_cache: Default::default(),
+            metrics: None,
+            list_permits: Arc::new(Semaphore::new(DEFAULT_LIST_CONCURRENCY))
+        }
+    }
+
+    /// Limit how many elements listings look up at the same time, across this mapped FS and clones made
+    /// afterwards. Each lookup holds a file descriptor, so this keeps a listing of a large directory or of many
+    /// mappings from running out of them. Defaults to 64. A limit of 0 is raised to 1
+    pub fn set_list_concurrency(&mut self, limit: usize) {
+        self.list_permits = Arc::new(Semaphore::new(limit.max(1)));
+    }
+
+    /// Start counting calls to this mapped FS. Clones made afterwards share the same counters. Calling this
+    /// again returns the existing counters
+    pub fn with_metrics(&mut self) -> Arc<MappedFSMetrics> {
+        self.metrics.get_or_insert_with(Default::default).clone()
+    }
+
+    fn count(&self, counter: impl FnOnce(&MappedFSMetrics) -> &AtomicU64) {
+        if let Some(metrics) = &self.metrics {
+            counter(metrics).fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Add a new writable file or directory to the mapped filesystem. See [`MappedFS::add_with_access`]
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Result<OsString, MappedFSError> {
+        self.add_with_access(path, true)
+    }
+
+    /// Add a new read-only file or directory to the mapped filesystem. See [`MappedFS::add_with_access`]
+    pub fn add_readonly<P: AsRef<Path>>(&mut self, path: P) -> Result<OsString, MappedFSError> {
+        self.add_with_access(path, false)
+    }
+
+    /// Add a new file or directory to the mapped filesystem. If the element has already been added previously,
+    /// only its access rights are updated. If two different elements with the same name are added, a number
+    /// will be appended to the name of the more recent element. For example, if two files named 'test.txt' are
+    /// added, the name of the second file within the virtual filesystem will be 'test.txt (1)'. Returns the
+    /// name of the element within the virtual filesystem
+    pub fn add_with_access<P: AsRef<Path>>(&mut self, path: P, writable: bool) -> Result<OsString, MappedFSError> {
+        self.count(|metrics| &metrics.add_calls);
         let path = path.as_ref();
 
         if !path.is_absolute() {
@@ -135,8 +345,8 @@ impl MappedFS {
             .file_name()
             .unwrap();
 
+        let entry = MappingEntry { real_path: path.to_owned(), writable };
         let mut number: u32 = 0;
-        let mut map = self.map.write().unwrap();
         loop {
             let name = if number == 0 {
                 name_in_path.to_owned()
@@ -150,108 +360,488 @@ impl MappedFS {
                 name_with_number
             };
 
-            match map.entry(name) {
-                // The file/directory is already in the VFS, so nothing needs to be done
-                Entry::Occupied(entry) if entry.get() == path => break,
+            match self.map.insert_if_vacant(name.clone(), &entry) {
+                // The file/directory is already in the VFS, so nothing else needs to be done
+                Insertion::AlreadyPresent => return Ok(name),
 
                 // An existing file/directory has the same name, so add a number to the end
-                Entry::Occupied(_) => {
+                Insertion::Occupied => {
                     assert!(number != u32::MAX);
                     number += 1;
                 }
 
-                // The name is unique and this is a new file/directory, we can insert!
-                Entry::Vacant(entry) => {
-                    entry.insert(path.to_owned());
-                    break;
-                }
+                // The name is unique and this is a new file/directory, we inserted it!
+                Insertion::Inserted => return Ok(name)
             }
         }
+    }
 
-        Ok(())
+    /// Add a new writable file or directory under the virtual name `virtual_name` rather than the last component of
+    /// its path, so /data/project can be served as myapp. Unlike [`MappedFS::add`], no number is appended if the
+    /// name is taken by a different path; [`MappedFSError::NameTaken`] is returned instead
+    pub fn add_with_alias<P: AsRef<Path>, N: AsRef<OsStr>>(&mut self, path: P, virtual_name: N) -> Result<OsString, MappedFSError> {
+        self.count(|metrics| &metrics.add_calls);
+        let path = path.as_ref();
+        let virtual_name = virtual_name.as_ref();
+
+        if !path.is_absolute() {
+            return Err(MappedFSError::PathNotAbsolute(path.to_owned()));
+        }
+
+        // The name must survive parse_path unchanged, so separators, '.' and '..' are not allowed
+        let mut components = Path::new(virtual_name).components();
+        let is_single_component = matches!(components.next(), Some(Component::Normal(name)) if name == virtual_name)
+            && components.next().is_none();
+        if !is_single_component {
+            return Err(MappedFSError::InvalidName(virtual_name.to_owned()));
+        }
+
+        let entry = MappingEntry { real_path: path.to_owned(), writable: true };
+        match self.map.insert_if_vacant(virtual_name.to_owned(), &entry) {
+            Insertion::Inserted | Insertion::AlreadyPresent => Ok(virtual_name.to_owned()),
+            Insertion::Occupied => Err(MappedFSError::NameTaken(virtual_name.to_owned())),
+        }
+    }
+
+    /// Add a new writable file or directory like [`MappedFS::add`], after resolving symbolic links and relative
+    /// components in the path. Unmapped paths then stay valid if a symbolic link in the original path is deleted.
+    /// The element is named after the resolved path. The path must exist, so a broken symbolic link is rejected.
+    /// Use [`MappedFS::add`] for paths which may not exist yet
+    pub fn add_canonical<P: AsRef<Path>>(&mut self, path: P) -> Result<OsString, MappedFSError> {
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(MappedFSError::PathNotAbsolute(path.to_owned()));
+        }
+
+        let canonical = std::fs::canonicalize(path).map_err(|err| (path.to_owned(), err))?;
+        self.add(canonical)
+    }
+
+    /// Add a new writable file or directory like [`MappedFS::add`], and fetch its metadata right away. Listing
+    /// the root of the mapped FS uses the fetched element rather than reading the metadata again, so its size
+    /// and timestamps are those from when it was added
+    pub async fn add_with_prefetch<P: AsRef<Path>>(&mut self, path: P) -> Result<OsString, MappedFSError> {
+        let path = path.as_ref();
+
+        // Fetch first so that a path which cannot be read is not added
+        let mut element = get_element(OsString::new(), path.to_owned(), true)
+            .await
+            .map_err(|err| (path.to_owned(), err))?;
+
+        let name = self.add(path)?;
+        element.name = name.clone();
+        self.metadata_cache.write().unwrap().insert(name.clone(), element);
+        Ok(name)
+    }
+
+    /// Add a new writable file or directory like [`MappedFS::add`], but fail with
+    /// [`MappedFSError::AlreadyMapped`] if the path has already been added
+    pub fn add_strict<P: AsRef<Path>>(&mut self, path: P) -> Result<OsString, MappedFSError> {
+        let existing = self.map
+            .entries()
+            .into_iter()
+            .find(|(_, entry)| entry.real_path == path.as_ref());
+
+        match existing {
+            Some((virtual_name, entry)) => Err(MappedFSError::AlreadyMapped { real_path: entry.real_path, virtual_name }),
+            None => self.add(path),
+        }
     }
 
     /// Returns a list of the currently registered paths
     pub fn registered(&self) -> Vec<PathBuf> {
-        self.map.read().unwrap().values().cloned().collect()
+        self.map.entries().into_iter().map(|(_, entry)| entry.real_path).collect()
+    }
+
+    /// Returns the virtual name and real path of every registered path, sorted by virtual name
+    pub fn registered_with_names(&self) -> Vec<(OsString, PathBuf)> {
+        let mut registered: Vec<_> = self.map.entries().into_iter().map(|(name, entry)| (name, entry.real_path)).collect();
+        registered.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        registered
     }
 
     /// Remove a path from the mapped FS
     pub fn remove<P: AsRef<Path>>(&mut self, path: P) {
-        self.map.write().unwrap().retain(|_, _path| _path != path.as_ref());
+        self.count(|metrics| &metrics.remove_calls);
+        let mut cache = self.metadata_cache.write().unwrap();
+        self.map.retain(&mut |name, entry| {
+            let keep = entry.real_path != path.as_ref();
+            if !keep {
+                cache.remove(name);
+            }
+            keep
+        });
+    }
+
+    /// Remove the path registered under a virtual name, for callers which only know the virtual namespace. Returns
+    /// the real path which was removed, or None if no path uses the name
+    pub fn remove_by_virtual_name(&mut self, name: &OsStr) -> Option<PathBuf> {
+        self.count(|metrics| &metrics.remove_calls);
+        let mut cache = self.metadata_cache.write().unwrap();
+        let mut removed = None;
+        self.map.retain(&mut |entry_name, entry| {
+            if entry_name != name {
+                return true;
+            }
+
+            cache.remove(entry_name);
+            removed = Some(entry.real_path.clone());
+            false
+        });
+        removed
+    }
+
+    /// Create a new, independent mapped FS containing only the entries for which `f` returns true. `f` is given
+    /// the virtual name and the real path of each entry. Useful for giving each connection its own view
+    pub fn filter<F: Fn(&OsStr, &Path) -> bool>(&self, f: F) -> MappedFS {
+        let map = self.map.empty();
+        let mut metadata_cache = HashMap::new();
+        let cache = self.metadata_cache.read().unwrap();
+        for (name, entry) in self.map.entries() {
+            if f(&name, &entry.real_path) {
+                if let Some(element) = cache.get(&name) {
+                    metadata_cache.insert(name.clone(), element.clone());
+                }
+                map.insert_if_vacant(name, &entry);
+            }
+        }
+
+        MappedFS {
+            map,
+            metadata_cache: Arc::new(RwLock::new(metadata_cache)),
+            metrics: self.metrics.clone(),
+            list_permits: self.list_permits.clone()
+        }
+    }
+
+    /// Serialize every mapping, including its virtual name and access rights, to JSON. Mappings are sorted by
+    /// virtual name so the output is stable
+    #[cfg(feature = "json")]
+    pub fn to_json_snapshot(&self) -> String {
+        let mut mappings = self.map.entries();
+        mappings.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let snapshot = Snapshot { format_version: SNAPSHOT_FORMAT_VERSION, mappings };
+        serde_json::to_string(&snapshot).expect("Mappings are always representable as JSON")
+    }
+
+    /// Recreate a mapped FS from a snapshot produced by [`MappedFS::to_json_snapshot`]. The mappings keep
+    /// their virtual names. Fails if the snapshot was written in a different format version
+    #[cfg(feature = "json")]
+    pub fn from_json_snapshot(json: &str) -> Result<Self, anyhow::Error> {
+        let mapped_fs = MappedFS::new();
+        for (name, entry) in parse_snapshot(json)?.mappings {
+            mapped_fs.map.insert_if_vacant(name, &entry);
+        }
+        Ok(mapped_fs)
+    }
+
+    /// Write every mapping to the file at `path` as a snapshot, see [`MappedFS::to_json_snapshot`]. The snapshot is
+    /// written to a temporary file next to `path` and then renamed over it, so the file is never left half written
+    #[cfg(feature = "json")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        // Each save has a temporary file of its own, so overlapping saves never write to the same one
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(format!(".tmp-{:016x}", rand::random::<u64>()));
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let result = std::fs::write(&temporary_path, self.to_json_snapshot())
+            .with_context(|| format!("Could not write {}", temporary_path.display()))
+            .and_then(|_| std::fs::rename(&temporary_path, path)
+                .with_context(|| format!("Could not replace {}", path.display())));
+        if result.is_err() {
+            // Nothing else would ever remove it
+            _ = std::fs::remove_file(&temporary_path);
+        }
+        result
+    }
+
+    /// Create a mapped FS from a file written by [`MappedFS::save`]. See [`MappedFS::extend_from_file`]
+    #[cfg(feature = "json")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let mut mapped_fs = MappedFS::new();
+        mapped_fs.extend_from_file(path)?;
+        Ok(mapped_fs)
+    }
+
+    /// Add the mappings saved to a file by [`MappedFS::save`], keeping their virtual names and access rights. A
+    /// mapping whose real path no longer exists, or whose name is used by a different path, is skipped with a
+    /// warning. Returns the number of mappings which were added
+    #[cfg(feature = "json")]
+    pub fn extend_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, anyhow::Error> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+
+        let mut added = 0;
+        for (name, entry) in parse_snapshot(&json)?.mappings {
+            match entry.real_path.try_exists() {
+                Ok(true) => (),
+                Ok(false) => {
+                    tracing::warn!("Skipping {name:?}, since {} no longer exists", entry.real_path.display());
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!("Skipping {name:?}, since {} could not be checked: {err}", entry.real_path.display());
+                    continue;
+                }
+            }
+
+            match self.map.insert_if_vacant(name.clone(), &entry) {
+                Insertion::Inserted | Insertion::AlreadyPresent => added += 1,
+                Insertion::Occupied => tracing::warn!("Skipping {name:?}, since the name is used by a different path"),
+            }
+        }
+        Ok(added)
     }
 
     /// Unmap a mapped path to obtain the path within the real file system
     pub fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, MappedFSError> {
-        let path_not_found_err =
-            |err| MappedFSError::PathNotFound(path.as_ref().to_owned(), err);
+        self.count(|metrics| &metrics.unmap_calls);
+        let path_not_found_err = |err| {
+            self.count(|metrics| &metrics.unmap_misses);
+            MappedFSError::PathNotFound(path.as_ref().to_owned(), err)
+        };
 
         match parse_path(&path)? {
             ParsedPath::Extended { root_element, extension } => {
-                let map = self.map.read().unwrap();
-                let absolute_path = map.get(&root_element)
-                    .ok_or_else(|| path_not_found_err(anyhow::anyhow!("The root element of the path does not exist")))?;
+                let entry = self.map.get(&root_element)
+                    .ok_or_else(|| path_not_found_err(not_found("The root element of the path does not exist")))?;
 
-                Ok(absolute_path.join(extension))
+                Ok(entry.real_path.join(extension))
             }
             ParsedPath::Root => Err(path_not_found_err(anyhow::anyhow!("A path to the root of the mapped file system cannot be unmapped"))),
         }
     }
 
+    /// Returns true if the path is inside of a mapping which clients may modify. The root of the mapped FS and
+    /// paths which are not mapped are never writable
+    pub fn is_writable<P: AsRef<Path>>(&self, path: P) -> bool {
+        match parse_path(path) {
+            Ok(ParsedPath::Extended { root_element, .. }) => self.map
+                .get(&root_element)
+                .map(|entry| entry.writable)
+                .unwrap_or(false),
+            _ => false
+        }
+    }
+
+    /// Obtain the FSElement for a single path within the mapped FS. The root of the mapped FS has no real
+    /// counterpart, so it is described as an empty directory without timestamps
+    pub async fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<FSElement, MappedFSError> {
+        match parse_path(&path)? {
+            ParsedPath::Root => Ok(FSElement {
+                name: OsString::new(),
+                created: None,
+                modified: None,
+                size: 0,
+                is_file: false,
+                is_symlink: false,
+                hash: None,
+                mode: None,
+                uid: None,
+                gid: None,
+                inode: None
+            }),
+            ParsedPath::Extended { .. } => {
+                let real_path = self.unmap(&path)?;
+                let name = path.as_ref().file_name().unwrap_or_default();
+
+                get_element(name, real_path, true)
+                    .await
+                    .map_err(|err| (path.as_ref().to_owned(), err).into())
+            }
+        }
+    }
+
+    /// Replace the contents of the file at the specified path, creating it if it does not exist. The data is written to
+    /// a temporary file next to it, which is then renamed over the file, so the file never holds a partial write.
+    /// Access rights are not checked here, see [`MappedFS::is_writable`]
+    pub async fn write<P: AsRef<Path>>(&self, path: P, data: &[u8]) -> Result<(), MappedFSError> {
+        let path = path.as_ref();
+        let real_path = self.unmap(path)?;
+        let file_name = real_path.file_name()
+            .ok_or_else(|| MappedFSError::PathNotFound(path.to_owned(), not_found("The path does not name a file")))?;
+
+        let mut temporary_name = file_name.to_owned();
+        temporary_name.push(format!(".tmp-{:016x}", rand::random::<u64>()));
+        let temporary_path = real_path.with_file_name(temporary_name);
+
+        tokio::fs::write(&temporary_path, data)
+            .await
+            .map_err(|err| (path.to_owned(), err))?;
+        if let Err(err) = tokio::fs::rename(&temporary_path, &real_path).await {
+            // Nothing else would ever remove it
+            _ = tokio::fs::remove_file(&temporary_path).await;
+            return Err((path.to_owned(), err).into());
+        }
+        Ok(())
+    }
+
+    /// Rename or move the element at `from` to `to`, which may be in a different registered path. Registered paths
+    /// themselves cannot be renamed, since their mappings would be left pointing at nothing
+    pub async fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<(), MappedFSError> {
+        let (real_from, real_to) = self.unmap_for_rename(from.as_ref(), to.as_ref())?;
+        tokio::fs::rename(&real_from, &real_to)
+            .await
+            .map_err(|err| (from.as_ref().to_owned(), err).into())
+    }
+
+    /// Rename like [`MappedFS::rename`], but fail with an io::Error of kind `AlreadyExists` if anything is at `to`.
+    /// The name is claimed by the same call which fails if it is taken, so nothing created at `to` by someone else
+    /// in the meantime is ever replaced. Outside of Unix, directories are still checked before they are renamed
+    pub async fn rename_no_replace<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<(), MappedFSError> {
+        let (real_from, real_to) = self.unmap_for_rename(from.as_ref(), to.as_ref())?;
+        rename_no_replace(&real_from, &real_to)
+            .await
+            .map_err(|err| (from.as_ref().to_owned(), err).into())
+    }
+
+    /// The real paths of both sides of a rename. Registered paths themselves cannot be renamed
+    fn unmap_for_rename(&self, from: &Path, to: &Path) -> Result<(PathBuf, PathBuf), MappedFSError> {
+        for path in [from, to] {
+            match parse_path(path)? {
+                ParsedPath::Extended { extension, .. } if !extension.as_os_str().is_empty() => (),
+                _ => return Err(Unsupported("renaming registered paths").into()),
+            }
+        }
+
+        Ok((self.unmap(from)?, self.unmap(to)?))
+    }
+
     /// List the FSElements at the specified path within the mapped FS
     pub async fn list<P: AsRef<Path>>(&self, path: P) -> Result<Vec<FSElement>, MappedFSError> {
+        self.list_with_options(path, ListOptions::default()).await
+    }
+
+    /// List the FSElements at the specified path within the mapped FS using the given options
+    pub async fn list_with_options<P: AsRef<Path>>(&self, path: P, options: ListOptions) -> Result<Vec<FSElement>, MappedFSError> {
+        self.list_with_cancel(path, options, CancellationToken::new()).await
+    }
+
+    /// List the FSElements at the specified path, failing with [`MappedFSError::Cancelled`] once `cancel` is
+    /// cancelled. Metadata which is still being read when that happens is abandoned
+    pub async fn list_cancellable<P: AsRef<Path>>(&self, path: P, cancel: CancellationToken) -> Result<Vec<FSElement>, MappedFSError> {
+        self.list_with_cancel(path, ListOptions::default(), cancel).await
+    }
+
+    /// List every element below the specified path, see [`FS::list_recursive`]. Subdirectories are listed at the same
+    /// time as each other rather than one after another, so a deep tree does not take one round of file system calls
+    /// per level
+    pub async fn list_recursive<P: AsRef<Path>>(&self, path: P, max_depth: Option<usize>) -> Result<Vec<(PathBuf, FSElement)>, MappedFSError> {
+        let list = |directory: PathBuf, depth: usize| async move {
+            let elements = self.list(&directory).await;
+            (directory, depth, elements)
+        };
+
+        let mut output = vec![];
+        let mut pending = vec![];
+        let mut listings = FuturesUnordered::new();
+        listings.push(list(path.as_ref().to_owned(), 0));
+
+        while let Some((directory, depth, elements)) = listings.next().await {
+            for element in elements? {
+                let element_path = directory.join(&element.name);
+                if enters(&element, depth, max_depth) {
+                    pending.push((element_path.clone(), depth + 1));
+                }
+                output.push((element_path, element));
+            }
+
+            while listings.len() < RECURSIVE_LIST_CONCURRENCY {
+                let Some((directory, depth)) = pending.pop() else { break };
+                listings.push(list(directory, depth));
+            }
+        }
+
+        output.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+        Ok(output)
+    }
+
+    async fn list_with_cancel<P: AsRef<Path>>(&self, path: P, options: ListOptions, cancel: CancellationToken) -> Result<Vec<FSElement>, MappedFSError> {
+        self.count(|metrics| &metrics.list_calls);
         let path_not_found_err =
             |err| MappedFSError::PathNotFound(path.as_ref().to_owned(), err);
 
-        let iter = match parse_path(&path)? {
+        let mut output = vec![];
+        let iter: Vec<_> = match parse_path(&path)? {
             ParsedPath::Root => {
-                // This is a path to the root of the mapped FS
-                let contents: Vec<(OsString, PathBuf)> = self.map
-                    .read()
-                    .unwrap()
-                    .iter()
-                    .map(|(name, path)| (name.to_owned(), path.to_owned()))
-                    .collect();
-
-                contents
-                    .into_iter()
-                    .map(|(name, absolute_path)| tokio::spawn(get_element(name, absolute_path)))
-                    .collect()
+                // This is a path to the root of the mapped FS. Prefetched elements always follow symbolic links
+                let mut entries = self.map.entries();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                // The cache is released before waiting for permits
+                let uncached: Vec<_> = {
+                    let cache = self.metadata_cache.read().unwrap();
+                    entries.into_iter()
+                        .filter(|(name, _)| match cache.get(name).filter(|_| options.follow_symlinks) {
+                            Some(element) => {
+                                output.push(element.clone());
+                                false
+                            }
+                            None => true,
+                        })
+                        .collect()
+                };
+
+                let mut futures = vec![];
+                for (name, entry) in uncached {
+                    let permits = self.list_permits.clone();
+                    futures.push((
+                        path.as_ref().join(&name),
+                        spawn_get_element(name, entry.real_path, options.follow_symlinks, permits, cancel.clone()).await
+                    ));
+                }
+
+                futures
             }
             ParsedPath::Extended { root_element, extension } => {
                 // This path goes deeper into the mapped FS
-                let path = {
-                    let map = self.map.read().unwrap();
-                    let absolute_path = map.get(&root_element)
-                        .ok_or_else(|| path_not_found_err(anyhow::anyhow!("The root element of the path does not exist")))?;
+                let real_path = self.map.get(&root_element)
+                    .ok_or_else(|| path_not_found_err(not_found("The root element of the path does not exist")))?
+                    .real_path
+                    .join(extension);
 
-                    absolute_path.join(extension)
-                };
-
-                let mut read_dir = tokio::fs::read_dir(path)
+                let mut read_dir = tokio::fs::read_dir(real_path)
                     .await
-                    .map_err(anyhow::Error::from)
-                    .map_err(path_not_found_err)?;
+                    .map_err(|err| (path.as_ref().to_owned(), err))?;
 
-                let mut futures = vec![];
+                let mut entries = vec![];
                 while let Some(entry) = read_dir.next_entry()
                     .await
-                    .map_err(anyhow::Error::from)
-                    .map_err(path_not_found_err)?
+                    .map_err(|err| (path.as_ref().to_owned(), err))?
                 {
-                    futures.push(tokio::spawn(get_element(entry.file_name(), entry.path())));
+                    if cancel.is_cancelled() {
+                        return Err(MappedFSError::Cancelled);
+                    }
+
+                    entries.push(entry);
+                }
+                entries.sort_by_key(|entry| entry.file_name());
+
+                let mut futures = vec![];
+                for entry in entries {
+                    let permits = self.list_permits.clone();
+                    futures.push((
+                        path.as_ref().join(entry.file_name()),
+                        spawn_get_element(entry.file_name(), entry.path(), options.follow_symlinks, permits, cancel.clone()).await
+                    ));
                 }
 
                 futures
             }
         };
 
-        let mut output = vec![];
-        for handle in iter {
-            if let Ok(elements) = handle.await.unwrap() {
-                output.push(elements);
+        for (element_path, handle) in iter {
+            match handle.await.unwrap() {
+                None => return Err(MappedFSError::Cancelled),
+                Some(Ok(element)) => output.push(element),
+                Some(Err(err)) if options.mode == ListMode::FailOnError => return Err((element_path, err).into()),
+                Some(Err(_)) => (),
             }
         }
+
+        // Prefetched elements are added before the spawned ones, so restore the order by name
+        output.sort();
         Ok(output)
     }
 }
@@ -263,4 +853,40 @@ impl FS for MappedFS {
     async fn list<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<Vec<FSElement>, MappedFSError> {
         self.list(path).await
     }
+
+    async fn list_with_options<P: AsRef<Path> + Send + Sync>(&self, path: P, options: ListOptions) -> Result<Vec<FSElement>, MappedFSError> {
+        self.list_with_options(path, options).await
+    }
+
+    async fn list_cancellable<P: AsRef<Path> + Send + Sync>(&self, path: P, cancel: CancellationToken) -> Result<Vec<FSElement>, MappedFSError> {
+        self.list_cancellable(path, cancel).await
+    }
+
+    async fn list_recursive<P: AsRef<Path> + Send + Sync>(&self, path: P, max_depth: Option<usize>) -> Result<Vec<(PathBuf, FSElement)>, MappedFSError> {
+        self.list_recursive(path, max_depth).await
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<FSElement, MappedFSError> {
+        self.metadata(path).await
+    }
+
+    async fn write<P: AsRef<Path> + Send + Sync>(&self, path: P, data: &[u8]) -> Result<(), MappedFSError> {
+        self.write(path, data).await
+    }
+
+    async fn rename<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), MappedFSError> {
+        self.rename(from, to).await
+    }
+
+    async fn rename_no_replace<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), MappedFSError> {
+        self.rename_no_replace(from, to).await
+    }
+
+    fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, MappedFSError> {
+        self.unmap(path)
+    }
+
+    fn is_writable<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.is_writable(path)
+    }
 }
@@ -0,0 +1,118 @@
+use std::{path::PathBuf, collections::{HashMap, hash_map}, ffi::{OsString, OsStr}, sync::{RwLock, Arc}};
+
+use dashmap::{DashMap, mapref};
+use serde::{Deserialize, Serialize};
+
+/// A real path registered in the mapped FS, along with its access rights
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingEntry {
+    pub real_path: PathBuf,
+    /// True if clients may modify the contents of this mapping
+    pub writable: bool
+}
+
+/// The outcome of attempting to insert a mapping into a [`MapBackend`]
+pub enum Insertion {
+    /// The name was free and the mapping has been inserted
+    Inserted,
+    /// The same real path is already mapped under this name. Its access rights have been updated
+    AlreadyPresent,
+    /// The name is already used by a different path
+    Occupied
+}
+
+/// Storage for the virtual name to real path mapping used by [`super::MappedFS`]
+pub trait MapBackend: Send + Sync {
+    /// Look up the mapping for a virtual name
+    fn get(&self, name: &OsStr) -> Option<MappingEntry>;
+
+    /// Insert a mapping only if the name is not already used by a different real path
+    fn insert_if_vacant(&self, name: OsString, entry: &MappingEntry) -> Insertion;
+
+    /// Returns a snapshot of every (virtual name, mapping) pair
+    fn entries(&self) -> Vec<(OsString, MappingEntry)>;
+
+    /// Keep only the mappings for which `f` returns true
+    fn retain(&self, f: &mut dyn FnMut(&OsStr, &MappingEntry) -> bool);
+
+    /// Create a new, empty map of the same kind
+    fn empty(&self) -> Arc<dyn MapBackend>;
+}
+
+/// A map guarded by a single `RwLock`. Simple and predictable, but every access contends on the same lock
+#[derive(Default)]
+pub struct LockedMap(RwLock<HashMap<OsString, MappingEntry>>);
+
+impl MapBackend for LockedMap {
+    fn get(&self, name: &OsStr) -> Option<MappingEntry> {
+        self.0.read().unwrap().get(name).cloned()
+    }
+
+    fn insert_if_vacant(&self, name: OsString, entry: &MappingEntry) -> Insertion {
+        match self.0.write().unwrap().entry(name) {
+            hash_map::Entry::Occupied(mut existing) if existing.get().real_path == entry.real_path => {
+                existing.insert(entry.clone());
+                Insertion::AlreadyPresent
+            }
+            hash_map::Entry::Occupied(_) => Insertion::Occupied,
+            hash_map::Entry::Vacant(vacant) => {
+                vacant.insert(entry.clone());
+                Insertion::Inserted
+            }
+        }
+    }
+
+    fn entries(&self) -> Vec<(OsString, MappingEntry)> {
+        self.0.read().unwrap()
+            .iter()
+            .map(|(name, entry)| (name.to_owned(), entry.to_owned()))
+            .collect()
+    }
+
+    fn retain(&self, f: &mut dyn FnMut(&OsStr, &MappingEntry) -> bool) {
+        self.0.write().unwrap().retain(|name, entry| f(name, entry));
+    }
+
+    fn empty(&self) -> Arc<dyn MapBackend> {
+        Arc::new(LockedMap::default())
+    }
+}
+
+/// A sharded map which only locks the shard being accessed, reducing contention between concurrent readers
+#[derive(Default)]
+pub struct ConcurrentMap(DashMap<OsString, MappingEntry>);
+
+impl MapBackend for ConcurrentMap {
+    fn get(&self, name: &OsStr) -> Option<MappingEntry> {
+        self.0.get(name).map(|entry| entry.value().clone())
+    }
+
+    fn insert_if_vacant(&self, name: OsString, entry: &MappingEntry) -> Insertion {
+        match self.0.entry(name) {
+            mapref::entry::Entry::Occupied(mut existing) if existing.get().real_path == entry.real_path => {
+                existing.insert(entry.clone());
+                Insertion::AlreadyPresent
+            }
+            mapref::entry::Entry::Occupied(_) => Insertion::Occupied,
+            mapref::entry::Entry::Vacant(vacant) => {
+                vacant.insert(entry.clone());
+                Insertion::Inserted
+            }
+        }
+    }
+
+    fn entries(&self) -> Vec<(OsString, MappingEntry)> {
+        self.0
+            .iter()
+            .map(|entry| (entry.key().to_owned(), entry.value().to_owned()))
+            .collect()
+    }
+
+    fn retain(&self, f: &mut dyn FnMut(&OsStr, &MappingEntry) -> bool) {
+        self.0.retain(|name, entry| f(name, entry));
+    }
+
+    fn empty(&self) -> Arc<dyn MapBackend> {
+        Arc::new(ConcurrentMap::default())
+    }
+}
@@ -0,0 +1,26 @@
+use std::{fmt, sync::atomic::{AtomicU64, Ordering}};
+
+/// Counts the calls made to a [`super::MappedFS`]. Enabled with [`super::MappedFS::with_metrics`]
+#[derive(Debug, Default)]
+pub struct MappedFSMetrics {
+    pub add_calls: AtomicU64,
+    pub remove_calls: AtomicU64,
+    pub list_calls: AtomicU64,
+    pub unmap_calls: AtomicU64,
+    /// Calls to unmap which failed because the path is not mapped
+    pub unmap_misses: AtomicU64
+}
+
+impl fmt::Display for MappedFSMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "add: {}, remove: {}, list: {}, unmap: {} ({} misses)",
+            self.add_calls.load(Ordering::Relaxed),
+            self.remove_calls.load(Ordering::Relaxed),
+            self.list_calls.load(Ordering::Relaxed),
+            self.unmap_calls.load(Ordering::Relaxed),
+            self.unmap_misses.load(Ordering::Relaxed)
+        )
+    }
+}
@@ -0,0 +1,305 @@
+use std::{collections::BTreeMap, ffi::OsString, io, path::{Component, Path, PathBuf}, sync::{Arc, RwLock}};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use super::{FSElement, FS, Unsupported};
+
+/// A file system held entirely in memory, so code which uses an [`FS`] can be tested without touching the disk.
+/// Clones share their contents, so a write through one clone is seen by every other
+#[derive(Debug, Clone, Default)]
+pub struct MemFS {
+    nodes: Arc<RwLock<BTreeMap<PathBuf, MemNode>>>
+}
+
+/// An element of a [`MemFS`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemNode {
+    File(Vec<u8>),
+    Dir
+}
+
+/// Each error carries an io::Error of the matching kind as its source, which is what [`super::browser::Browser`]
+/// uses to tell a missing path from any other failure
+#[derive(Error, Debug)]
+pub enum MemFSError {
+    #[error("The path {0} does not exist in the memory file system")]
+    NotFound(PathBuf, #[source] io::Error),
+
+    #[error("The path {0} is not a directory")]
+    NotADirectory(PathBuf, #[source] io::Error),
+
+    #[error("The path {0} is a directory")]
+    IsADirectory(PathBuf, #[source] io::Error),
+
+    #[error("The path {0} already exists")]
+    AlreadyExists(PathBuf, #[source] io::Error),
+
+    #[error("The directory {0} cannot be moved into itself")]
+    MoveIntoItself(PathBuf, #[source] io::Error),
+
+    #[error(transparent)]
+    Unsupported(#[from] Unsupported)
+}
+
+impl MemFSError {
+    fn not_found(path: &Path) -> Self {
+        MemFSError::NotFound(path.to_owned(), io::ErrorKind::NotFound.into())
+    }
+
+    fn not_a_directory(path: &Path) -> Self {
+        MemFSError::NotADirectory(path.to_owned(), io::ErrorKind::NotADirectory.into())
+    }
+
+    fn is_a_directory(path: &Path) -> Self {
+        MemFSError::IsADirectory(path.to_owned(), io::ErrorKind::IsADirectory.into())
+    }
+}
+
+/// Convert a path into the key it is stored under, which has no root and no "." components. The root itself is
+/// the empty path. Paths which leave the root through ".." do not exist
+fn normalize(path: &Path) -> Result<PathBuf, MemFSError> {
+    let mut key = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => key.push(name),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return Err(MemFSError::not_found(path)),
+        }
+    }
+
+    Ok(key)
+}
+
+fn element(key: &Path, node: &MemNode) -> FSElement {
+    let (size, is_file) = match node {
+        MemNode::File(data) => (data.len() as u64, true),
+        MemNode::Dir => (0, false),
+    };
+
+    FSElement {
+        name: key.file_name().map(OsString::from).unwrap_or_default(),
+        created: None,
+        modified: None,
+        size,
+        is_file,
+        is_symlink: false,
+        hash: None,
+        mode: None,
+        uid: None,
+        gid: None,
+        inode: None
+    }
+}
+
+impl MemFS {
+    /// Create an empty file system, which holds only the root directory
+    pub fn new() -> Self {
+        MemFS::default()
+    }
+
+    /// Start building a file system with some files and directories already in place
+    pub fn builder() -> MemFSBuilder {
+        MemFSBuilder { fs: MemFS::new() }
+    }
+
+    /// The contents of the file at the specified path
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, MemFSError> {
+        let key = normalize(path.as_ref())?;
+        match self.nodes.read().unwrap().get(&key) {
+            Some(MemNode::File(data)) => Ok(data.clone()),
+            Some(MemNode::Dir) => Err(MemFSError::is_a_directory(path.as_ref())),
+            None if key.as_os_str().is_empty() => Err(MemFSError::is_a_directory(path.as_ref())),
+            None => Err(MemFSError::not_found(path.as_ref())),
+        }
+    }
+
+    /// List the elements in the directory at the specified path, ordered by name
+    pub fn list<P: AsRef<Path>>(&self, path: P) -> Result<Vec<FSElement>, MemFSError> {
+        let key = normalize(path.as_ref())?;
+        let nodes = self.nodes.read().unwrap();
+        check_directory(&nodes, &key, path.as_ref())?;
+
+        Ok(nodes
+            .range(key.clone()..)
+            .take_while(|(child, _)| child.starts_with(&key))
+            .filter(|(child, _)| child.parent() == Some(&key))
+            .map(|(child, node)| element(child, node))
+            .collect())
+    }
+
+    /// Obtain the element at the specified path. The root is a directory with an empty name
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<FSElement, MemFSError> {
+        let key = normalize(path.as_ref())?;
+        if key.as_os_str().is_empty() {
+            return Ok(element(&key, &MemNode::Dir));
+        }
+
+        match self.nodes.read().unwrap().get(&key) {
+            Some(node) => Ok(element(&key, node)),
+            None => Err(MemFSError::not_found(path.as_ref())),
+        }
+    }
+
+    /// Replace the contents of the file at the specified path, creating it if it does not exist. Its parent
+    /// directory must already exist
+    pub fn write<P: AsRef<Path>>(&self, path: P, data: &[u8]) -> Result<(), MemFSError> {
+        let key = normalize(path.as_ref())?;
+        let mut nodes = self.nodes.write().unwrap();
+        let parent = key.parent().ok_or_else(|| MemFSError::is_a_directory(path.as_ref()))?;
+        check_directory(&nodes, parent, parent)?;
+
+        if let Some(MemNode::Dir) = nodes.get(&key) {
+            return Err(MemFSError::is_a_directory(path.as_ref()));
+        }
+        nodes.insert(key, MemNode::File(data.to_vec()));
+        Ok(())
+    }
+
+    /// Rename or move the element at `from` to `to`, along with everything below it. A file already at `to` is
+    /// replaced, but a directory is not. The parent directory of `to` must already exist
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<(), MemFSError> {
+        self.rename_with(from.as_ref(), to.as_ref(), true)
+    }
+
+    /// Rename like [`MemFS::rename`], but fail if anything is already at `to`
+    pub fn rename_no_replace<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<(), MemFSError> {
+        self.rename_with(from.as_ref(), to.as_ref(), false)
+    }
+
+    fn rename_with(&self, from: &Path, to: &Path, replace: bool) -> Result<(), MemFSError> {
+        let from_key = normalize(from)?;
+        let to_key = normalize(to)?;
+        let mut nodes = self.nodes.write().unwrap();
+
+        let node = nodes.get(&from_key).cloned().ok_or_else(|| MemFSError::not_found(from))?;
+        let parent = to_key.parent().ok_or_else(|| MemFSError::is_a_directory(to))?;
+        check_directory(&nodes, parent, parent)?;
+        if from_key == to_key {
+            return Ok(());
+        }
+        if to_key.starts_with(&from_key) {
+            return Err(MemFSError::MoveIntoItself(from.to_owned(), io::ErrorKind::InvalidInput.into()));
+        }
+
+        match (nodes.get(&to_key), &node) {
+            (Some(_), _) if !replace => return Err(MemFSError::AlreadyExists(to.to_owned(), io::ErrorKind::AlreadyExists.into())),
+            (Some(MemNode::Dir), _) => return Err(MemFSError::is_a_directory(to)),
+            (Some(MemNode::File(_)), MemNode::Dir) => return Err(MemFSError::not_a_directory(to)),
+            _ => (),
+        }
+
+        let moved: Vec<_> = nodes
+            .range(from_key.clone()..)
+            .take_while(|(key, _)| key.starts_with(&from_key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in moved {
+            let node = nodes.remove(&key).expect("The key was just listed");
+            let rest = key.strip_prefix(&from_key).expect("The key starts with from_key");
+            let new_key = if rest.as_os_str().is_empty() { to_key.clone() } else { to_key.join(rest) };
+            nodes.insert(new_key, node);
+        }
+        Ok(())
+    }
+
+    /// Add a node at the specified path, creating any missing parent directories. Fails if a parent is a file, or if
+    /// a file would replace a directory or the other way around
+    fn insert(&self, path: &Path, node: MemNode) -> Result<(), MemFSError> {
+        let key = normalize(path)?;
+        let mut nodes = self.nodes.write().unwrap();
+        for parent in key.ancestors().skip(1).filter(|parent| !parent.as_os_str().is_empty()) {
+            match nodes.get(parent) {
+                Some(MemNode::Dir) => {}
+                Some(MemNode::File(_)) => return Err(MemFSError::not_a_directory(parent)),
+                None => {
+                    nodes.insert(parent.to_owned(), MemNode::Dir);
+                }
+            }
+        }
+
+        match (nodes.get(&key), &node) {
+            (Some(MemNode::Dir), MemNode::File(_)) => Err(MemFSError::is_a_directory(path)),
+            (Some(MemNode::File(_)), MemNode::Dir) => Err(MemFSError::not_a_directory(path)),
+            _ if key.as_os_str().is_empty() => match node {
+                MemNode::Dir => Ok(()),
+                MemNode::File(_) => Err(MemFSError::is_a_directory(path)),
+            },
+            _ => {
+                nodes.insert(key, node);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fail unless `key` is the root or an existing directory
+fn check_directory(nodes: &BTreeMap<PathBuf, MemNode>, key: &Path, path: &Path) -> Result<(), MemFSError> {
+    match nodes.get(key) {
+        Some(MemNode::Dir) => Ok(()),
+        None if key.as_os_str().is_empty() => Ok(()),
+        Some(MemNode::File(_)) => Err(MemFSError::not_a_directory(path)),
+        None => Err(MemFSError::not_found(path)),
+    }
+}
+
+/// Fills a [`MemFS`] for a test. Parent directories are created as needed, so `add_file("a/b/c.txt", ..)` also
+/// creates "a" and "a/b"
+#[derive(Debug)]
+pub struct MemFSBuilder {
+    fs: MemFS
+}
+
+impl MemFSBuilder {
+    /// Add a file, replacing any file already at the path. Panics if the path is a directory or a parent of it is a
+    /// file
+    pub fn add_file<P: AsRef<Path>, D: Into<Vec<u8>>>(self, path: P, data: D) -> Self {
+        if let Err(err) = self.fs.insert(path.as_ref(), MemNode::File(data.into())) {
+            panic!("Could not add the file: {err}");
+        }
+        self
+    }
+
+    /// Add a directory, which may already exist. Panics if the path or a parent of it is a file
+    pub fn add_dir<P: AsRef<Path>>(self, path: P) -> Self {
+        if let Err(err) = self.fs.insert(path.as_ref(), MemNode::Dir) {
+            panic!("Could not add the directory: {err}");
+        }
+        self
+    }
+
+    pub fn build(self) -> MemFS {
+        self.fs
+    }
+}
+
+#[async_trait]
+impl FS for MemFS {
+    type Error = MemFSError;
+
+    async fn list<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<Vec<FSElement>, MemFSError> {
+        self.list(path)
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, path: P) -> Result<FSElement, MemFSError> {
+        self.metadata(path)
+    }
+
+    async fn write<P: AsRef<Path> + Send + Sync>(&self, path: P, data: &[u8]) -> Result<(), MemFSError> {
+        self.write(path, data)
+    }
+
+    async fn rename<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), MemFSError> {
+        self.rename(from, to)
+    }
+
+    async fn rename_no_replace<P: AsRef<Path> + Send + Sync, Q: AsRef<Path> + Send + Sync>(&self, from: P, to: Q) -> Result<(), MemFSError> {
+        self.rename_no_replace(from, to)
+    }
+
+    /// Nothing in a memory file system has a real path, so operations which work on real files, such as copying
+    /// and archiving, fail
+    fn unmap<P: AsRef<Path>>(&self, _path: P) -> Result<PathBuf, MemFSError> {
+        Err(Unsupported("real paths").into())
+    }
+}
@@ -0,0 +1,36 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits the number of files which may be open at once, shared between every Browser using it
+#[derive(Clone)]
+pub struct OpenFileLimit {
+    semaphore: Arc<Semaphore>,
+    timeout: Duration
+}
+
+/// Held while a file is open. The permit is returned to the [`OpenFileLimit`] when this is dropped
+pub struct OpenFilePermit {
+    _permit: OwnedSemaphorePermit
+}
+
+impl OpenFileLimit {
+    /// Wrap a semaphore with one permit per file which may be open. Waiting for a permit gives up after `timeout`
+    pub fn new(semaphore: Arc<Semaphore>, timeout: Duration) -> Self {
+        OpenFileLimit { semaphore, timeout }
+    }
+
+    /// The number of files which can currently be opened without waiting
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Wait for permission to open a file. Returns None if no permit became available before the timeout
+    pub async fn acquire(&self) -> Option<OpenFilePermit> {
+        tokio::time::timeout(self.timeout, self.semaphore.clone().acquire_owned())
+            .await
+            .ok()?
+            .ok()
+            .map(|permit| OpenFilePermit { _permit: permit })
+    }
+}
@@ -0,0 +1,147 @@
+use std::{ffi::OsString, cmp::Reverse, path::Path};
+
+use serde::{Serialize, Deserialize};
+use time::OffsetDateTime;
+
+use super::FSElement;
+
+/// A value which file system elements can be ordered by
+pub trait SortKey: Ord + Clone {
+    /// The order of a Cursor which sorts by this key
+    const ORDER: SortOrder;
+
+    /// Read the key of an element
+    fn of(element: &FSElement) -> Self;
+}
+
+/// A type which is ordered by a single sort key
+pub trait Sortable {
+    type Key: SortKey;
+    fn sort_key(&self) -> Self::Key;
+}
+
+/// Order elements by their name
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct NameKey(pub OsString);
+
+/// Order elements by their size in bytes
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct SizeKey(pub u64);
+
+/// Order elements by their modification time. Elements without a modification time come first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct ModifiedKey(pub Option<OffsetDateTime>);
+
+/// Order elements by their creation time. Elements without a creation time come first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct CreatedKey(pub Option<OffsetDateTime>);
+
+/// Order elements by the extension of their name. Elements without an extension come first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct ExtensionKey(pub Option<OsString>);
+
+impl SortKey for NameKey {
+    const ORDER: SortOrder = SortOrder::Name;
+
+    fn of(element: &FSElement) -> Self {
+        NameKey(element.name.clone())
+    }
+}
+
+impl SortKey for SizeKey {
+    const ORDER: SortOrder = SortOrder::Size;
+
+    fn of(element: &FSElement) -> Self {
+        SizeKey(element.size)
+    }
+}
+
+impl SortKey for ModifiedKey {
+    const ORDER: SortOrder = SortOrder::Modified;
+
+    fn of(element: &FSElement) -> Self {
+        ModifiedKey(element.modified)
+    }
+}
+
+impl SortKey for CreatedKey {
+    const ORDER: SortOrder = SortOrder::Created;
+
+    fn of(element: &FSElement) -> Self {
+        CreatedKey(element.created)
+    }
+}
+
+impl SortKey for ExtensionKey {
+    const ORDER: SortOrder = SortOrder::FileType;
+
+    fn of(element: &FSElement) -> Self {
+        ExtensionKey(Path::new(&element.name).extension().map(ToOwned::to_owned))
+    }
+}
+
+/// Elements are ordered by name unless a Cursor chooses another key
+impl Sortable for FSElement {
+    type Key = NameKey;
+
+    fn sort_key(&self) -> NameKey {
+        NameKey::of(self)
+    }
+}
+
+/// The property a Cursor orders its elements by, chosen at runtime with
+/// [`crate::fs::browser::Request::SetSort`]. Elements which are equal by that property are ordered by name
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Name,
+    Size,
+    Modified,
+    Created,
+    /// The extension of the element's name
+    FileType
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending
+}
+
+/// How a single Cursor orders its elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorSort {
+    pub order: SortOrder,
+    pub direction: SortDirection,
+    /// List every directory before every file. The direction only applies within each group
+    pub directories_first: bool
+}
+
+impl CursorSort {
+    /// Sort ascending by `K` alone, as chosen with [`crate::fs::browser::Browser::set_sort_key`]
+    pub fn by_key<K: SortKey>() -> Self {
+        CursorSort { order: K::ORDER, direction: SortDirection::Ascending, directories_first: false }
+    }
+
+    /// Sort `elements` in this order. The key is chosen once for the whole slice rather than for each comparison
+    pub fn sort(&self, elements: &mut [FSElement]) {
+        match self.order {
+            SortOrder::Name => self.sort_by::<NameKey>(elements),
+            SortOrder::Size => self.sort_by::<SizeKey>(elements),
+            SortOrder::Modified => self.sort_by::<ModifiedKey>(elements),
+            SortOrder::Created => self.sort_by::<CreatedKey>(elements),
+            SortOrder::FileType => self.sort_by::<ExtensionKey>(elements),
+        }
+    }
+
+    /// Each element's keys are read once, so names are not cloned for every comparison
+    fn sort_by<K: SortKey>(&self, elements: &mut [FSElement]) {
+        // Files come after directories when they are kept apart, whichever the direction
+        let group = |element: &FSElement| self.directories_first && element.is_file;
+        match self.direction {
+            SortDirection::Ascending =>
+                elements.sort_by_cached_key(|element| (group(element), K::of(element), element.sort_key())),
+            SortDirection::Descending =>
+                elements.sort_by_cached_key(|element| (group(element), Reverse((K::of(element), element.sort_key())))),
+        }
+    }
+}
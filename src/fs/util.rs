@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+
+use super::FSElement;
+
+/// Group elements with the same contents, as identified by [`FSElement::content_id`]. Groups are ordered by the
+/// first occurrence of their contents, and each group keeps the order of `elements`. Elements without a hash
+/// cannot be compared, so each is placed in a group of its own
+pub fn deduplicate(elements: &[FSElement]) -> Vec<Vec<&FSElement>> {
+    let mut groups: Vec<Vec<&FSElement>> = vec![];
+    let mut group_indices: HashMap<&[u8], usize> = HashMap::new();
+
+    for element in elements {
+        match element.content_id() {
+            Some(id) => match group_indices.get(id) {
+                Some(&index) => groups[index].push(element),
+                None => {
+                    group_indices.insert(id, groups.len());
+                    groups.push(vec![element]);
+                }
+            },
+            None => groups.push(vec![element]),
+        }
+    }
+
+    groups
+}
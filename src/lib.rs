@@ -1,6 +1,15 @@
-use std::io::{self, Write, BufRead};
+use std::{io::{self, Write, BufRead}, sync::OnceLock};
+
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Stdin}, sync::Mutex};
 
 pub mod fs;
+pub mod protocol;
+pub mod plugin;
+pub mod server;
+pub mod formatter;
+pub mod tls;
+pub mod config;
+pub mod acl;
 
 pub fn read_input(prompt: Option<&str>) -> Result<String, io::Error> {
     if let Some(prompt) = prompt {
@@ -9,5 +18,58 @@ pub fn read_input(prompt: Option<&str>) -> Result<String, io::Error> {
     }
 
     // Obtain one line and leave off the \n
-    Ok(io::stdin().lock().lines().nth(0).unwrap()?)
+    io::stdin().lock().lines().next().unwrap()
+}
+
+/// Read lines until a line equal to `terminator` is entered. The lines are joined with \n and the terminator
+/// line is not included
+pub fn read_lines(prompt: Option<&str>, terminator: &str) -> Result<String, io::Error> {
+    if let Some(prompt) = prompt {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+    }
+
+    let mut lines = vec![];
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line == terminator {
+            return Ok(lines.join("\n"));
+        }
+        lines.push(line);
+    }
+
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin was closed before the terminator was entered"))
+}
+
+/// Read one line without echoing it to the terminal, for entering secrets such as passwords and tokens
+pub fn read_password(prompt: &str) -> Result<String, io::Error> {
+    rpassword::prompt_password(prompt)
+}
+
+/// Asynchronous version of [`read_input`] which does not block the runtime. Input read by this function is
+/// buffered internally, so it should not be mixed with calls to [`read_input`]
+pub async fn read_input_async(prompt: Option<&str>) -> Result<String, io::Error> {
+    // A single reader is shared so that lines buffered by one call are not lost before the next
+    static STDIN: OnceLock<Mutex<BufReader<Stdin>>> = OnceLock::new();
+
+    if let Some(prompt) = prompt {
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(prompt.as_bytes()).await?;
+        stdout.flush().await?;
+    }
+
+    let mut line = String::new();
+    let mut stdin = STDIN
+        .get_or_init(|| Mutex::new(BufReader::new(tokio::io::stdin())))
+        .lock()
+        .await;
+
+    if stdin.read_line(&mut line).await? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin was closed"));
+    }
+
+    // Leave off the \n (and the \r on Windows)
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(line)
 }
@@ -0,0 +1,43 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::fs::{mapped_fs::MappedFS, browser::CursorError};
+
+/// A handler for a custom request type. Plugin requests arrive as `Request::Plugin` frames and carry opaque
+/// bytes, so a plugin is free to choose its own encoding for the request and response data
+#[async_trait]
+pub trait Plugin: Send + Sync {
+    /// The type ID which clients use to address this plugin
+    fn request_type_id(&self) -> u16;
+
+    /// Handle one request and produce the response data
+    async fn handle(&self, raw: &[u8], fs: &MappedFS) -> Vec<u8>;
+}
+
+/// The set of plugins available to every connection
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: HashMap<u16, Arc<dyn Plugin>>
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a plugin. A plugin which was previously registered with the same type ID is replaced
+    pub fn register_plugin<P: Plugin + 'static>(&mut self, plugin: P) -> &mut Self {
+        self.plugins.insert(plugin.request_type_id(), Arc::new(plugin));
+        self
+    }
+
+    /// Pass a request to the plugin registered for `type_id`
+    pub async fn dispatch(&self, type_id: u16, raw: &[u8], fs: &MappedFS) -> Result<Vec<u8>, CursorError> {
+        let plugin = self.plugins
+            .get(&type_id)
+            .ok_or(CursorError::UnknownPlugin { type_id, span_id: None })?;
+
+        Ok(plugin.handle(raw, fs).await)
+    }
+}
@@ -0,0 +1,305 @@
+use std::{borrow::Cow, collections::HashMap, io::{self, Read}, time::Duration};
+
+use serde::{Serialize, Deserialize, de::{DeserializeOwned, IgnoredAny}};
+use thiserror::Error;
+use tokio::{io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt}, time::{Instant, Interval, MissedTickBehavior}};
+
+/// Compression algorithms which can be applied to every frame after the handshake
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    None,
+    Zstd,
+    Lz4
+}
+
+/// The compression algorithms supported by this build, from most to least preferred
+pub const SUPPORTED_COMPRESSION: [CompressionAlgorithm; 3] =
+    [CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4, CompressionAlgorithm::None];
+
+impl CompressionAlgorithm {
+    pub fn compress(self, data: Vec<u8>) -> Result<Vec<u8>, io::Error> {
+        match self {
+            CompressionAlgorithm::None => Ok(data),
+            CompressionAlgorithm::Zstd => zstd::encode_all(data.as_slice(), 0),
+            CompressionAlgorithm::Lz4 => Ok(lz4_flex::compress_prepend_size(&data)),
+        }
+    }
+
+    /// Decompress a frame, refusing to produce more than [`MAX_FRAME_LEN`] bytes so that a small frame cannot
+    /// expand into an arbitrary amount of memory
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        match self {
+            CompressionAlgorithm::None => Ok(data.to_vec()),
+            CompressionAlgorithm::Zstd => {
+                let decoder = zstd::Decoder::new(data).map_err(|_| ProtocolError::MalformedFrame)?;
+                let mut output = vec![];
+                decoder.take(MAX_FRAME_LEN as u64 + 1).read_to_end(&mut output).map_err(|_| ProtocolError::MalformedFrame)?;
+                // The real size is unknown without decoding the rest, so report the first size over the limit
+                if output.len() > MAX_FRAME_LEN {
+                    return Err(ProtocolError::MessageTooLarge { announced: output.len(), limit: MAX_FRAME_LEN });
+                }
+                Ok(output)
+            }
+            CompressionAlgorithm::Lz4 => {
+                let prefix = data.get(..4).ok_or(ProtocolError::MalformedFrame)?;
+                let announced = u32::from_le_bytes(prefix.try_into().expect("The prefix is 4 bytes")) as usize;
+                if announced > MAX_FRAME_LEN {
+                    return Err(ProtocolError::MessageTooLarge { announced, limit: MAX_FRAME_LEN });
+                }
+                lz4_flex::decompress_size_prepended(data).map_err(|_| ProtocolError::MalformedFrame)
+            }
+        }
+    }
+}
+
+/// The version of the protocol implemented by this build
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Sent by both sides when a connection opens, before any frame. Peers from before version 2 send a u16 frame
+/// length here instead, so they are refused before either side misreads a frame
+pub const MAGIC: u32 = u32::from_be_bytes(*b"SFT2");
+
+/// Optional features a server may offer, reported by [`crate::fs::browser::Request::GetServerInfo`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Compression,
+    Authentication,
+    FileTransfer,
+    Watch,
+    Archive
+}
+
+/// The capabilities of this build
+pub fn server_capabilities() -> Vec<Capability> {
+    let mut capabilities = vec![Capability::Archive, Capability::FileTransfer];
+    if SUPPORTED_COMPRESSION.iter().any(|algorithm| *algorithm != CompressionAlgorithm::None) {
+        capabilities.push(Capability::Compression);
+    }
+    capabilities
+}
+
+/// The version of this crate as [major, minor, patch]
+pub fn crate_version() -> [u16; 3] {
+    [env!("CARGO_PKG_VERSION_MAJOR"), env!("CARGO_PKG_VERSION_MINOR"), env!("CARGO_PKG_VERSION_PATCH")]
+        .map(|part| part.parse().expect("Version numbers fit in a u16"))
+}
+
+/// The largest frame which can be sent or received. The u32 length prefix could announce more, but a frame is read
+/// into memory whole, so a peer must not be able to make the other side allocate an arbitrary amount
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Errors in the framing or handshake which happen before a request reaches a Browser. The side which detects
+/// one sends it in a protocol error frame and closes the connection
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum ProtocolError {
+    #[error("The connection does not use this protocol")]
+    MagicMismatch,
+
+    #[error("The client uses protocol version {client}, but the server uses version {server}")]
+    UnsupportedVersion { client: u16, server: u16 },
+
+    #[error("A message of {announced} bytes is larger than the limit of {limit} bytes")]
+    MessageTooLarge { announced: usize, limit: usize },
+
+    #[error("A frame could not be decoded")]
+    MalformedFrame,
+
+    #[error("Received a message of unknown type {name}")]
+    UnknownMessage { name: String },
+}
+
+/// Just the variant name of an encoded enum. Used to describe messages which could not be decoded
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VariantName {
+    Unit(String),
+    WithData(HashMap<String, IgnoredAny>)
+}
+
+impl VariantName {
+    fn into_name(self) -> Option<String> {
+        match self {
+            VariantName::Unit(name) => Some(name),
+            VariantName::WithData(map) => map.into_keys().next(),
+        }
+    }
+}
+
+/// The first frame sent by the client after connecting
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientHello {
+    pub supported_compression: Vec<CompressionAlgorithm>,
+    /// The heartbeat settings the client would like to use, or None to disable heartbeats
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Clients which predate versioning are treated as version 1
+    #[serde(default = "first_protocol_version")]
+    pub protocol_version: u16
+}
+
+/// The server's answer to the [`ClientHello`]. The selected settings apply to every following frame
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerHello {
+    pub selected_compression: CompressionAlgorithm,
+    #[serde(default)]
+    pub heartbeat: Option<HeartbeatConfig>
+}
+
+/// How often each side sends a heartbeat frame, and how long either side waits for any frame before it
+/// declares the connection dead
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub timeout: Duration
+}
+
+/// The shortest heartbeat interval the server accepts
+const MIN_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig { interval: Duration::from_secs(15), timeout: Duration::from_secs(60) }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Create an interval which first ticks one heartbeat interval from now
+    pub fn interval(&self) -> Interval {
+        let mut interval = tokio::time::interval_at(Instant::now() + self.interval, self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    }
+}
+
+/// Accept the client's heartbeat settings, raising the interval to a sensible minimum and the timeout to at
+/// least two intervals so a single late heartbeat does not end the connection
+pub fn negotiate_heartbeat(requested: Option<HeartbeatConfig>) -> Option<HeartbeatConfig> {
+    requested.map(|requested| {
+        let interval = requested.interval.max(MIN_HEARTBEAT_INTERVAL);
+        HeartbeatConfig { interval, timeout: requested.timeout.max(interval * 2) }
+    })
+}
+
+/// Wait until the next heartbeat is due. Never completes when heartbeats are disabled
+pub async fn next_heartbeat(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Choose the most preferred compression algorithm that both sides support, or no compression if there is none
+pub fn select_compression(client_supported: &[CompressionAlgorithm]) -> CompressionAlgorithm {
+    SUPPORTED_COMPRESSION
+        .into_iter()
+        .find(|algorithm| client_supported.contains(algorithm))
+        .unwrap_or(CompressionAlgorithm::None)
+}
+
+fn first_protocol_version() -> u16 {
+    1
+}
+
+/// Send [`MAGIC`]. The stream is not flushed
+pub async fn write_magic(stream: &mut (impl AsyncWrite + Unpin)) -> Result<(), anyhow::Error> {
+    stream.write_u32(MAGIC).await?;
+    Ok(())
+}
+
+/// Read the other side's [`MAGIC`], failing with [`ProtocolError::MagicMismatch`] if it sent something else
+pub async fn read_magic(stream: &mut (impl AsyncRead + Unpin)) -> Result<(), anyhow::Error> {
+    if stream.read_u32().await? != MAGIC {
+        return Err(ProtocolError::MagicMismatch.into());
+    }
+    Ok(())
+}
+
+/// Serialize and write one length-prefixed frame. The stream is not flushed
+pub async fn write_frame<T: Serialize>(
+    stream: &mut (impl AsyncWrite + Unpin),
+    compression: CompressionAlgorithm,
+    message: &T
+) -> Result<(), anyhow::Error> {
+    let data = compression.compress(rmp_serde::to_vec(message)?)?;
+    if data.len() > MAX_FRAME_LEN {
+        return Err(ProtocolError::MessageTooLarge { announced: data.len(), limit: MAX_FRAME_LEN }.into());
+    }
+
+    stream.write_u32(data.len().try_into()?).await?;
+    stream.write_all(&data).await?;
+    Ok(())
+}
+
+/// Send a [`ProtocolError`] and flush the stream. The connection should be closed afterwards. The error is
+/// marked by an empty frame, which is never sent otherwise, followed by an uncompressed frame holding the error
+pub async fn write_protocol_error(stream: &mut (impl AsyncWrite + Unpin), error: &ProtocolError) -> Result<(), anyhow::Error> {
+    stream.write_u32(0).await?;
+    write_frame(stream, CompressionAlgorithm::None, error).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Read and deserialize one length-prefixed frame, using `buffer` as scratch space. A [`ProtocolError`] sent by
+/// the other side, or a frame which cannot be decoded, is returned as an error which can be downcast to
+/// [`ProtocolError`]. A frame holding a message of an unknown type is reported as
+/// [`ProtocolError::UnknownMessage`]. The whole frame has been read in that case, so the stream can still be used
+pub async fn read_frame<T: DeserializeOwned>(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+    compression: CompressionAlgorithm
+) -> Result<T, anyhow::Error> {
+    let frame_len = read_frame_len(stream).await?;
+    if frame_len == 0 {
+        let error: ProtocolError = read_body(stream, buffer, CompressionAlgorithm::None).await?;
+        return Err(error.into());
+    }
+
+    read_frame_body(stream, buffer, frame_len, compression).await
+}
+
+/// Read the length prefix and body of a frame which cannot be a protocol error frame
+async fn read_body<T: DeserializeOwned>(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+    compression: CompressionAlgorithm
+) -> Result<T, anyhow::Error> {
+    let frame_len = read_frame_len(stream).await?;
+    read_frame_body(stream, buffer, frame_len, compression).await
+}
+
+/// Read a length prefix, refusing frames larger than [`MAX_FRAME_LEN`] before any space is reserved for them
+async fn read_frame_len(stream: &mut (impl AsyncRead + Unpin)) -> Result<usize, anyhow::Error> {
+    let frame_len: usize = stream.read_u32().await?.try_into()?;
+    if frame_len > MAX_FRAME_LEN {
+        return Err(ProtocolError::MessageTooLarge { announced: frame_len, limit: MAX_FRAME_LEN }.into());
+    }
+    Ok(frame_len)
+}
+
+async fn read_frame_body<T: DeserializeOwned>(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut Vec<u8>,
+    frame_len: usize,
+    compression: CompressionAlgorithm
+) -> Result<T, anyhow::Error> {
+    if frame_len > buffer.len() {
+        buffer.resize(frame_len, 0);
+    }
+
+    let slice = &mut buffer[..frame_len];
+    stream.read_exact(slice).await?;
+
+    let data = match compression {
+        CompressionAlgorithm::None => Cow::Borrowed(&*slice),
+        _ => Cow::Owned(compression.decompress(slice)?),
+    };
+
+    rmp_serde::from_slice(&data).map_err(|_| {
+        match rmp_serde::from_slice::<VariantName>(&data).ok().and_then(VariantName::into_name) {
+            Some(name) => ProtocolError::UnknownMessage { name }.into(),
+            None => ProtocolError::MalformedFrame.into(),
+        }
+    })
+}
@@ -0,0 +1,690 @@
+use std::{backtrace::Backtrace, ffi::OsStr, net::SocketAddr, io, path::{Path, PathBuf}, sync::{Arc, OnceLock, atomic::{AtomicU32, AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use clap::Parser;
+use serde_bytes::ByteBuf;
+use futures::{stream, StreamExt};
+use crate::{
+    acl::{Acl, Cidr},
+    fs::{browser::{Browser, Request, Response}, mapped_fs::{MappedFS, metrics::MappedFSMetrics}, open_files::OpenFileLimit, cursor_quota::CursorQuota},
+    plugin::{Plugin, PluginRegistry},
+    protocol::{
+        read_frame, read_magic, write_frame, write_magic, write_protocol_error, select_compression, negotiate_heartbeat, next_heartbeat,
+        ClientHello, ServerHello, CompressionAlgorithm, ProtocolError, PROTOCOL_VERSION
+    },
+    tls::TlsConfig,
+    config::Config,
+    read_input
+};
+use tokio::{io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter}, net::TcpListener, signal, sync::Semaphore};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::Instrument;
+
+#[derive(Parser)]
+struct Args {
+    /// TOML file holding the server's settings. The other arguments take precedence over it
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Address the server listens on. Defaults to the bind_addr of --config, or 127.0.0.1
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Port the server listens on. Defaults to the bind_addr of --config, or 8000
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Maximum number of requests per second processed across all connections combined
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    global_rps: Option<u32>,
+
+    /// Maximum number of requests which may be processed in a burst when --global-rps is set.
+    /// Defaults to the value of --global-rps
+    #[arg(long, requires = "global_rps", value_parser = clap::value_parser!(u32).range(1..))]
+    global_burst: Option<u32>,
+
+    /// Maximum number of Cursors which may be open at once across all connections combined
+    #[arg(long, default_value_t = 1024)]
+    max_total_cursors: u32,
+
+    /// Maximum number of files which may be open at once across all connections combined
+    #[arg(long, default_value_t = 256)]
+    max_open_files: usize,
+
+    /// Number of seconds a request waits for a file to become available to open before the server reports
+    /// that it is busy
+    #[arg(long, default_value_t = 5)]
+    open_file_timeout: u64,
+
+    /// Size in bytes of the read and write buffers of each connection
+    #[arg(long, default_value_t = 65536)]
+    buffer_size: usize,
+
+    /// Requests which take longer than this many milliseconds to answer are logged as warnings
+    #[arg(long, default_value_t = 1000)]
+    slow_request_threshold_ms: u64,
+
+    /// PEM file holding the certificate chain to present to clients. Connections use TLS when this is set
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file holding the private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM file holding the certificate authority which client certificates must be signed by. Clients without
+    /// such a certificate are refused
+    #[arg(long, requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+}
+
+impl Args {
+    fn tls_config(&self) -> Option<TlsConfig> {
+        Some(TlsConfig {
+            cert_path: self.tls_cert.clone()?,
+            key_path: self.tls_key.clone()?,
+            client_ca_path: self.tls_client_ca.clone()
+        })
+    }
+}
+
+/// Where the `snapshot` command writes the mappings, so other processes can load them
+#[cfg(feature = "json")]
+const SNAPSHOT_PATH: &str = "/tmp/sft_mappings.json";
+
+/// Counters for the whole server, printed by the `metrics` command
+struct Metrics {
+    mapped_fs: Arc<MappedFSMetrics>,
+    cursors: CursorQuota
+}
+
+fn run_cli(mut mapped_fs: MappedFS, acl: Acl, metrics: Metrics) -> Result<(), anyhow::Error> {
+    loop {
+        let input = read_input(Some("Enter a command: "))?;
+        match input.to_lowercase().as_str() {
+            command if command.split_whitespace().next() == Some("acl") => run_acl_command(&acl, &input),
+            "exit" => return Ok(()),
+            "add" => {
+                let path = read_input(Some("Enter an absolute path: "))?;
+                match mapped_fs.add(&path) {
+                    Ok(name) => println!("Successfully added the path {path} as {name:?}"),
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            "add-readonly" => {
+                let path = read_input(Some("Enter an absolute path: "))?;
+                match mapped_fs.add_readonly(&path) {
+                    Ok(name) => println!("Successfully added the read-only path {path} as {name:?}"),
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            "remove" => {
+                let input = read_input(Some("Enter the virtual name or absolute path to remove: "))?;
+                // A virtual name is a single component, so it is never an absolute path
+                if Path::new(&input).is_absolute() {
+                    remove_real_path(&mut mapped_fs, &input);
+                } else {
+                    remove_virtual_name(&mut mapped_fs, &input);
+                }
+            }
+            "remove-name" => {
+                let name = read_input(Some("Enter the virtual name to remove: "))?;
+                remove_virtual_name(&mut mapped_fs, &name);
+            }
+            "list" => {
+                let registered = mapped_fs.registered_with_names();
+                if registered.is_empty() {
+                    println!("No paths are registered");
+                }
+                for (name, path) in registered {
+                    println!("{name:?}: {}", path.display());
+                }
+            }
+            "metrics" => println!("Mapped FS calls: {}", metrics.mapped_fs),
+            "status" => println!("{} of {} cursors are open across all connections", metrics.cursors.used(), metrics.cursors.max()),
+            #[cfg(feature = "json")]
+            "snapshot" => {
+                match std::fs::write(SNAPSHOT_PATH, mapped_fs.to_json_snapshot()) {
+                    Ok(_) => println!("Wrote the mappings to {SNAPSHOT_PATH}"),
+                    Err(err) => println!("Error: {err}"),
+                }
+            }
+            #[cfg(feature = "json")]
+            "save" => {
+                let path = read_input(Some("Enter the file to save to: "))?;
+                match mapped_fs.save(&path) {
+                    Ok(()) => println!("Saved the mappings to {path}"),
+                    Err(err) => println!("Error: {err:#}"),
+                }
+            }
+            #[cfg(feature = "json")]
+            "load" => {
+                let path = read_input(Some("Enter the file to load from: "))?;
+                match mapped_fs.extend_from_file(&path) {
+                    Ok(added) => println!("Loaded {added} mapping(s) from {path}"),
+                    Err(err) => println!("Error: {err:#}"),
+                }
+            }
+            "help" => print_cli_help(),
+            _ => println!("Unknown command. Type 'help' for a list.")
+        }
+    }
+}
+
+/// Run `acl allow <virtual-name> <cidr>` or `acl deny <virtual-name> <cidr>`. Virtual names may contain spaces, so
+/// the range is taken from the end of the line
+fn run_acl_command(acl: &Acl, input: &str) {
+    let usage = "Usage: acl allow|deny <virtual-name> <cidr>";
+    let mut parts = input.trim().splitn(3, char::is_whitespace);
+    let (Some(_), Some(action), Some(arguments)) = (parts.next(), parts.next(), parts.next()) else {
+        return println!("{usage}");
+    };
+    let Some((name, range)) = arguments.trim().rsplit_once(char::is_whitespace) else {
+        return println!("{usage}");
+    };
+
+    let range: Cidr = match range.parse() {
+        Ok(range) => range,
+        Err(err) => return println!("Error: {err}"),
+    };
+    let name = name.trim();
+    match action.to_lowercase().as_str() {
+        "allow" => {
+            acl.allow(name, range);
+            println!("Clients in {range} may now list {name:?}");
+        }
+        "deny" => {
+            acl.deny(name, range);
+            println!("Clients in {range} may no longer list {name:?}");
+        }
+        _ => println!("{usage}"),
+    }
+}
+
+fn remove_real_path(mapped_fs: &mut MappedFS, path: &str) {
+    if mapped_fs.registered().iter().any(|registered| registered == Path::new(path)) {
+        mapped_fs.remove(path);
+        println!("Successfully removed the path {path}");
+    } else {
+        println!("Error: The path {path} is not registered");
+    }
+}
+
+fn remove_virtual_name(mapped_fs: &mut MappedFS, name: &str) {
+    match mapped_fs.remove_by_virtual_name(OsStr::new(name)) {
+        Some(path) => println!("Successfully removed {name:?}, which was the path {}", path.display()),
+        None => println!("Error: No path is registered as {name:?}"),
+    }
+}
+
+/// Print the commands accepted by [`run_cli`]
+fn print_cli_help() {
+    println!("add           Register a path");
+    println!("add-readonly  Register a path which clients cannot write to");
+    println!("remove        Unregister a path by its virtual name, or by its real path if an absolute path is entered");
+    println!("remove-name   Unregister the path with a virtual name");
+    println!("list          Show the registered paths and their virtual names");
+    println!("metrics       Show call counts for the mapped FS");
+    println!("status        Show how many cursors are open");
+    println!("acl allow     Let a range of addresses list a virtual name: acl allow <virtual-name> <cidr>");
+    println!("acl deny      Stop a range of addresses listing a virtual name: acl deny <virtual-name> <cidr>");
+    #[cfg(feature = "json")]
+    println!("snapshot      Write the mappings to {SNAPSHOT_PATH}");
+    #[cfg(feature = "json")]
+    println!("save          Write the mappings to a file of your choice");
+    #[cfg(feature = "json")]
+    println!("load          Add the mappings from a file written by save");
+    println!("help          Show this list");
+    println!("exit          Stop the server");
+}
+
+/// Add the initial paths of the configuration. Paths which do not exist yet are still added, since they may be
+/// created later, but are reported in case they are typos
+fn add_initial_paths(mapped_fs: &mut MappedFS, paths: &[PathBuf]) {
+    for path in paths {
+        if !path.try_exists().unwrap_or(false) {
+            tracing::warn!("The initial path {} does not currently exist", path.display());
+        }
+
+        if let Err(err) = mapped_fs.add(path) {
+            tracing::warn!("Could not add the initial path {}: {err}", path.display());
+        }
+    }
+}
+
+/// Log the virtual names of the registered paths, as a check that the expected paths were loaded
+fn log_registered_paths(mapped_fs: &MappedFS) {
+    let names: Vec<_> = mapped_fs.registered_with_names().into_iter().map(|(name, _)| name).collect();
+    if names.is_empty() {
+        tracing::warn!("No paths registered; clients will see an empty filesystem");
+    } else {
+        tracing::info!("Registered {} path(s): {names:?}", names.len());
+    }
+}
+
+/// Refill the global rate limit semaphore with `rps` permits every second, never exceeding `burst` permits
+async fn refill_rate_limit(shutdown: CancellationToken, semaphore: Arc<Semaphore>, rps: u32, burst: u32) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let burst: usize = burst.try_into().unwrap();
+    let rps: usize = rps.try_into().unwrap();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let missing = burst.saturating_sub(semaphore.available_permits());
+                semaphore.add_permits(missing.min(rps));
+            }
+            _ = shutdown.cancelled() => return
+        }
+    }
+}
+
+/// Tell the client about a protocol error before the connection is closed. The error is returned so it can be
+/// propagated
+async fn report_protocol_error(stream: &mut (impl AsyncWrite + Unpin), err: anyhow::Error) -> anyhow::Error {
+    if let Some(protocol_error) = err.downcast_ref::<ProtocolError>() {
+        _ = write_protocol_error(stream, protocol_error).await;
+    }
+    err
+}
+
+/// Tell the client the server is shutting down, then close the connection. The client may already be gone, so
+/// errors are ignored
+async fn send_shutdown(stream: &mut (impl AsyncWrite + Unpin), compression: CompressionAlgorithm, reason: &OnceLock<String>) {
+    let reason = reason.get().cloned().unwrap_or_else(|| "The server is shutting down".to_owned());
+    _ = write_frame(stream, compression, &Response::Shutdown { reason }).await;
+    _ = stream.flush().await;
+    _ = stream.shutdown().await;
+}
+
+/// Everything shared by all connections. Cloning is cheap
+#[derive(Clone)]
+struct ConnectionContext {
+    fs: Arc<MappedFS>,
+    rate_limit: Option<Arc<Semaphore>>,
+    plugins: Arc<PluginRegistry>,
+    open_files: OpenFileLimit,
+    cursor_quota: CursorQuota,
+    acl: Acl,
+    buffer_size: usize,
+    cursor_limit: u16,
+    /// Cursors unused for this long are destroyed. None keeps them until the connection closes
+    idle_cursor_timeout: Option<Duration>,
+    startup_time: Instant,
+    slow_request_threshold: Duration,
+    /// Sent to every client when the server shuts down. Set before the shutdown token is cancelled
+    shutdown_reason: Arc<OnceLock<String>>,
+    /// The ID of the most recently accepted connection
+    last_connection_id: Arc<AtomicU64>
+}
+
+/// Serve a single client over `socket`, which is either a plain TCP stream or one wrapped in TLS
+async fn handle_socket<S: AsyncRead + AsyncWrite + Send + 'static>(
+    shutdown: CancellationToken,
+    socket: S,
+    address: SocketAddr,
+    connection_id: u64,
+    context: ConnectionContext
+) -> Result<(), anyhow::Error> {
+    let ConnectionContext {
+        fs, rate_limit, plugins, open_files, cursor_quota, acl, buffer_size, cursor_limit, idle_cursor_timeout, startup_time,
+        slow_request_threshold,
+        shutdown_reason, ..
+    } = context;
+
+    let mut browser = Browser::new(cursor_limit, fs.clone());
+    browser.set_open_file_limit(open_files);
+    browser.set_cursor_quota(cursor_quota);
+    browser.set_connection_id(connection_id);
+    browser.set_client_addr(address);
+    browser.set_acl(acl);
+
+    // Scratch space for decoding frames, which grows to fit the largest frame received
+    const SIZE: usize = 4096;
+    let mut buffer = vec![0; SIZE];
+    let (reader, writer) = tokio::io::split(socket);
+    let mut reader = BufReader::with_capacity(buffer_size, reader);
+    let mut stream = BufWriter::with_capacity(buffer_size, writer);
+
+    if let Err(err) = read_magic(&mut reader).await {
+        return Err(report_protocol_error(&mut stream, err).await);
+    }
+
+    // The handshake is always uncompressed
+    let hello: ClientHello = match read_frame(&mut reader, &mut buffer, CompressionAlgorithm::None).await {
+        Ok(hello) => hello,
+        Err(err) => return Err(report_protocol_error(&mut stream, err).await),
+    };
+    if hello.protocol_version != PROTOCOL_VERSION {
+        let err = ProtocolError::UnsupportedVersion { client: hello.protocol_version, server: PROTOCOL_VERSION };
+        return Err(report_protocol_error(&mut stream, err.into()).await);
+    }
+
+    let compression = select_compression(&hello.supported_compression);
+    let heartbeat = negotiate_heartbeat(hello.heartbeat);
+    write_magic(&mut stream).await?;
+    write_frame(&mut stream, CompressionAlgorithm::None, &ServerHello { selected_compression: compression, heartbeat }).await?;
+    stream.flush().await?;
+
+    // Requests are read through a stream so that waiting for one can be interrupted by a heartbeat
+    // without losing a partially read frame
+    let requests = stream::unfold((reader, buffer), |(mut reader, mut buffer)| async move {
+        let request = read_frame::<Request>(&mut reader, &mut buffer, compression).await;
+        Some((request, (reader, buffer)))
+    });
+    tokio::pin!(requests);
+
+    let mut heartbeat_interval = heartbeat.map(|heartbeat| heartbeat.interval());
+    let mut last_received = Instant::now();
+
+    // Idle Cursors are looked for at least once a minute, so one lives on for at most a minute past the timeout
+    let mut gc = idle_cursor_timeout.map(|timeout| (timeout, tokio::time::interval(timeout.min(Duration::from_secs(60)))));
+
+    loop {
+        // A request which has started is always completed and answered, but no new request is started once the
+        // server is shutting down
+        if shutdown.is_cancelled() {
+            send_shutdown(&mut stream, compression, &shutdown_reason).await;
+            return Ok(());
+        }
+
+        let request = tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => {
+                send_shutdown(&mut stream, compression, &shutdown_reason).await;
+                return Ok(());
+            }
+            request = requests.next() => match request.unwrap() {
+                Ok(request) => request,
+                Err(err) => match err.downcast::<ProtocolError>() {
+                    // A client newer than the server may send requests it does not know about. The frame
+                    // was read completely, so the connection can carry on
+                    Ok(ProtocolError::UnknownMessage { name }) => {
+                        tracing::warn!("Received an unknown request type {name}");
+                        write_frame(&mut stream, compression, &Response::UnknownRequest { name }).await?;
+                        stream.flush().await?;
+                        last_received = Instant::now();
+                        continue;
+                    }
+                    Ok(err) => return Err(report_protocol_error(&mut stream, err.into()).await),
+                    Err(err) => return Err(err),
+                },
+            },
+            timeout = async {
+                let (timeout, interval) = gc.as_mut().unwrap();
+                interval.tick().await;
+                *timeout
+            }, if gc.is_some() => {
+                let removed = browser.gc_idle_cursors(timeout);
+                if removed > 0 {
+                    tracing::info!("Destroyed {removed} idle cursor(s)");
+                }
+                continue;
+            }
+            _ = next_heartbeat(&mut heartbeat_interval) => {
+                if heartbeat.is_some_and(|heartbeat| last_received.elapsed() > heartbeat.timeout) {
+                    tracing::info!("Connection timed out");
+                    return Ok(());
+                }
+
+                write_frame(&mut stream, compression, &Response::Heartbeat).await?;
+                stream.flush().await?;
+                continue;
+            }
+        };
+        last_received = Instant::now();
+
+        if let Request::Heartbeat = request {
+            continue;
+        }
+
+        // Every earlier request has already been answered, so the connection can be closed straight away
+        if let Request::Goodbye = request {
+            tracing::info!("Client said goodbye");
+            write_frame(&mut stream, compression, &Response::Goodbye).await?;
+            stream.flush().await?;
+            stream.shutdown().await?;
+            return Ok(());
+        }
+
+        // Wait for the global rate limiter before doing any work. The permit is consumed and only
+        // returned to the pool by the refill task, which stops once the server is shutting down
+        if let Some(rate_limit) = &rate_limit {
+            tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => {
+                    send_shutdown(&mut stream, compression, &shutdown_reason).await;
+                    return Ok(());
+                }
+                permit = rate_limit.acquire() => permit?.forget(),
+            }
+        }
+
+        // Errors sent to the client carry the ID of this span, so they can be found in the log
+        let span = tracing::info_span!("request");
+        let span_id = span.id().map(|id| id.into_u64());
+
+        let started = Instant::now();
+        let mut response = match request {
+            Request::ReadStreaming { id } => {
+                // Each element is written as soon as it is read. The final EndOfStream frame is the response
+                let elements = browser.read_cursor_streaming(id);
+                tokio::pin!(elements);
+
+                let mut result = Ok(());
+                while let Some(element) = elements.next().await {
+                    match element {
+                        Ok(element) => write_frame(&mut stream, compression, &Response::Element(element)).await?,
+                        Err(err) => {
+                            result = Err(err);
+                            break;
+                        }
+                    }
+                }
+                Response::EndOfStream(result)
+            }
+            request => {
+                let work = async {
+                    match request {
+                        Request::Plugin { type_id, data } => Response::Plugin(plugins.dispatch(type_id, &data, &fs).await.map(ByteBuf::from)),
+                        Request::HealthCheck => Response::HealthCheck { uptime_secs: startup_time.elapsed().as_secs() },
+                        request => browser.process(request).await,
+                    }
+                }.instrument(span.clone());
+
+                // Keep sending heartbeats while a slow request is processed so the client does not give up
+                tokio::pin!(work);
+                loop {
+                    tokio::select! {
+                        response = &mut work => break response,
+                        _ = next_heartbeat(&mut heartbeat_interval) => {
+                            write_frame(&mut stream, compression, &Response::Heartbeat).await?;
+                            stream.flush().await?;
+                        }
+                    }
+                }
+            }
+        };
+        if let Some(err) = response.error_mut() {
+            err.set_span_id(span_id);
+            tracing::warn!(parent: &span, "Request failed: {err}");
+        }
+
+        if let Err(err) = write_frame(&mut stream, compression, &response).await {
+            return Err(report_protocol_error(&mut stream, err).await);
+        }
+
+        while let Some(frame) = browser.next_frame() {
+            write_frame(&mut stream, compression, &frame).await?;
+        }
+        stream.flush().await?;
+
+        // Measured until the response is flushed, so slow clients and large responses are included
+        let latency = started.elapsed();
+        let request_latency_ms = latency.as_millis() as u64;
+        tracing::debug!(parent: &span, request_latency_ms, "Request answered");
+        if latency > slow_request_threshold {
+            tracing::warn!(parent: &span, request_latency_ms, "Slow request took {request_latency_ms}ms");
+        }
+
+        // Time spent processing does not count towards the client's timeout
+        last_received = Instant::now();
+    }
+}
+
+/// Configures the server before it starts. The remaining settings come from the command line when it is run
+#[derive(Default)]
+pub struct ServerBuilder {
+    plugins: PluginRegistry
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `Request::Plugin` frames addressed to the plugin's type ID with the plugin
+    pub fn register_plugin<P: Plugin + 'static>(mut self, plugin: P) -> Self {
+        self.plugins.register_plugin(plugin);
+        self
+    }
+
+    /// Parse the command line arguments and serve clients until the server is stopped from its command line or
+    /// with Ctrl-C
+    pub async fn run(self) -> Result<(), anyhow::Error> {
+        run(self.plugins).await
+    }
+}
+
+async fn run(plugins: PluginRegistry) -> Result<(), anyhow::Error> {
+    let startup_time = Instant::now();
+    let args = Args::parse();
+    tracing_subscriber::fmt::init();
+
+    // A Browser turns a panic in the file system into an error response, so the panic is only seen here. The
+    // backtrace is captured by the hook because it runs where the panic happened
+    std::panic::set_hook(Box::new(|info| {
+        tracing::error!("{info}\n{}", Backtrace::force_capture());
+    }));
+
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let mut mapped_fs = MappedFS::new();
+    let cursor_quota = CursorQuota::new(Arc::new(AtomicU32::new(0)), args.max_total_cursors);
+    let metrics = Metrics { mapped_fs: mapped_fs.with_metrics(), cursors: cursor_quota.clone() };
+
+    add_initial_paths(&mut mapped_fs, &config.initial_paths);
+    log_registered_paths(&mapped_fs);
+
+    // The CLI and every connection share the same rules
+    let acl = Acl::new();
+    let mapped_fs_for_cli = mapped_fs.clone();
+    let acl_for_cli = acl.clone();
+    let cli_future = tokio::task::spawn_blocking(|| run_cli(mapped_fs_for_cli, acl_for_cli, metrics));
+
+    let shutdown = CancellationToken::new();
+    let shutdown_reason = Arc::new(OnceLock::new());
+    let connections = TaskTracker::new();
+
+    let rate_limit = args.global_rps.map(|rps| {
+        let burst = args.global_burst.unwrap_or(rps);
+        let semaphore = Arc::new(Semaphore::new(burst.try_into().unwrap()));
+        tokio::task::spawn(refill_rate_limit(shutdown.clone(), semaphore.clone(), rps, burst));
+        semaphore
+    });
+
+    let open_files_sem = Arc::new(Semaphore::new(args.max_open_files));
+    let open_files = OpenFileLimit::new(open_files_sem, Duration::from_secs(args.open_file_timeout));
+
+    let context = ConnectionContext {
+        // Every connection shares the same mappings, including those added later through the CLI
+        fs: Arc::new(mapped_fs),
+        rate_limit,
+        plugins: Arc::new(plugins),
+        open_files,
+        cursor_quota,
+        acl,
+        buffer_size: args.buffer_size,
+        cursor_limit: config.cursor_limit,
+        idle_cursor_timeout: config.idle_cursor_timeout(),
+        startup_time,
+        slow_request_threshold: Duration::from_millis(args.slow_request_threshold_ms),
+        shutdown_reason: shutdown_reason.clone(),
+        last_connection_id: Arc::new(AtomicU64::new(0))
+    };
+
+    // The certificates are read once at startup, so a mistake in them stops the server before it accepts anyone
+    let tls_acceptor = args.tls_config().map(|config| config.acceptor()).transpose()?;
+
+    let bind_address = (
+        args.host.unwrap_or_else(|| config.bind_addr.ip().to_string()),
+        args.port.unwrap_or(config.bind_addr.port())
+    );
+    let listener_shutdown = shutdown.clone();
+    let connection_shutdown = shutdown.clone();
+    let listener_connections = connections.clone();
+    tokio::task::spawn(async move {
+        let listener = match TcpListener::bind(bind_address).await {
+            Ok(listener) => listener,
+            Err(error) => return Err::<(), io::Error>(error),
+        };
+        tracing::info!("Server ready in {}ms", startup_time.elapsed().as_millis());
+
+        tokio::select! {
+            _ = async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((socket, address)) => {
+                            let connection_id = context.last_connection_id.fetch_add(1, Ordering::Relaxed) + 1;
+                            tracing::info!(
+                                connection_id,
+                                client_addr = %address,
+                                "Connection recieved ({} file handles available)",
+                                context.open_files.available()
+                            );
+
+                            // Everything logged while handling the connection is tagged with its ID
+                            let span = tracing::info_span!("connection", connection_id, client_addr = %address);
+                            let shutdown = connection_shutdown.clone();
+                            let context = context.clone();
+                            match tls_acceptor.clone() {
+                                // The TLS handshake runs in the connection's task, so a slow client does not hold up
+                                // the listener
+                                Some(acceptor) => listener_connections.spawn(async move {
+                                    match acceptor.accept(socket).await {
+                                        Ok(stream) => handle_socket(shutdown, stream, address, connection_id, context).await,
+                                        Err(err) => {
+                                            tracing::warn!("TLS handshake failed: {err}");
+                                            Ok(())
+                                        }
+                                    }
+                                }.instrument(span)),
+                                None => listener_connections.spawn(
+                                    handle_socket(shutdown, socket, address, connection_id, context).instrument(span)
+                                ),
+                            };
+                        }
+                        Err(error) => {
+                            tracing::error!("Error: {error}");
+                        }
+                    }
+                }
+            } => Ok(()),
+            _ = listener_shutdown.cancelled() => Ok(())
+        }
+    });
+
+    let reason = tokio::select! {
+        _ = signal::ctrl_c() => "The server was stopped with Ctrl-C",
+        _ = cli_future => "The server was stopped from its command line"
+    };
+
+    // Let every connection finish the request it is working on, then tell its client why it is being closed
+    _ = shutdown_reason.set(reason.to_owned());
+    shutdown.cancel();
+    connections.close();
+    connections.wait().await;
+
+    Ok(())
+}
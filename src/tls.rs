@@ -0,0 +1,99 @@
+//! Optional TLS for the TCP transport, configured from PEM files
+
+use std::{fs::File, io::BufReader, path::{Path, PathBuf}, sync::Arc};
+
+use anyhow::Context;
+use tokio_rustls::{
+    rustls::{server::AllowAnyAuthenticatedClient, Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName},
+    TlsAcceptor, TlsConnector
+};
+
+/// The certificate and key a server presents to its clients, all read from PEM files
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// The certificate authority client certificates must be signed by. Clients without such a certificate are
+    /// refused, which makes this mutual TLS. None accepts every client
+    pub client_ca_path: Option<PathBuf>
+}
+
+impl TlsConfig {
+    /// Read the certificates and key, and create the acceptor which wraps each accepted connection
+    pub fn acceptor(&self) -> Result<TlsAcceptor, anyhow::Error> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca_path {
+            Some(client_ca_path) => {
+                let roots = load_roots(client_ca_path)?;
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(load_certs(&self.cert_path)?, load_key(&self.key_path)?)
+            .context("The certificate does not match the key")?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Create a connector which trusts servers whose certificate is signed by the certificate authority at `ca_path`.
+/// `identity` is the certificate and key presented to servers which require mutual TLS
+pub fn connector(ca_path: &Path, identity: Option<(&Path, &Path)>) -> Result<TlsConnector, anyhow::Error> {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(load_roots(ca_path)?);
+
+    let config = match identity {
+        Some((cert_path, key_path)) => builder
+            .with_client_auth_cert(load_certs(cert_path)?, load_key(key_path)?)
+            .context("The client certificate does not match the key")?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// The name a server's certificate is checked against, taken from the host part of an address such as
+/// "example.com:8000" or "127.0.0.1:8000"
+pub fn server_name(address: &str) -> Result<ServerName, anyhow::Error> {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host).with_context(|| format!("{host} is not a valid server name"))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let certs: Vec<_> = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Could not read the certificates in {}", path.display()))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    if certs.is_empty() {
+        anyhow::bail!("{} does not contain any certificates", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, anyhow::Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(&cert).with_context(|| format!("{} contains an invalid certificate", path.display()))?;
+    }
+    Ok(roots)
+}
+
+/// Read the first private key in a PEM file, in any of the formats rustls accepts
+fn load_key(path: &Path) -> Result<PrivateKey, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut reader).with_context(|| format!("Could not read {}", path.display()))? {
+            Some(rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::ECKey(key)) => {
+                return Ok(PrivateKey(key));
+            }
+            Some(_) => continue,
+            None => anyhow::bail!("{} does not contain a private key", path.display()),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Restricting which client addresses may list each virtual root
+
+mod common;
+
+use std::{ffi::OsStr, net::{IpAddr, SocketAddr}};
+
+use futures::StreamExt;
+
+use simple_file_transfer_v2::{
+    acl::{Acl, AclError, Cidr},
+    fs::{browser::{Browser, CursorError, Request, Response}, mem_fs::MemFS}
+};
+
+use common::{describe, TestClient, TestServer};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn cidr(s: &str) -> Cidr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn ranges_contain_their_addresses() {
+    assert!(cidr("10.0.0.0/8").contains(ip("10.200.3.4")));
+    assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+    assert!(cidr("0.0.0.0/0").contains(ip("203.0.113.9")));
+    assert!(cidr("192.168.1.7").contains(ip("192.168.1.7")));
+    assert!(!cidr("192.168.1.7").contains(ip("192.168.1.8")));
+    assert!(cidr("fd00::/8").contains(ip("fd12::1")));
+    assert!(!cidr("fd00::/8").contains(ip("10.0.0.1")));
+    // IPv4 clients of a dual stack listener have IPv6 addresses
+    assert!(cidr("127.0.0.0/8").contains(ip("::ffff:127.0.0.1")));
+}
+
+#[test]
+fn invalid_ranges_are_rejected() {
+    assert_eq!("10.0.0.0/33".parse::<Cidr>(), Err(AclError::InvalidPrefixLength { prefix_len: 33, max: 32 }));
+    assert_eq!("10.0.0/8".parse::<Cidr>(), Err(AclError::InvalidAddress("10.0.0".to_owned())));
+    assert_eq!("10.0.0.0/x".parse::<Cidr>(), Err(AclError::InvalidPrefix("x".to_owned())));
+}
+
+#[test]
+fn denied_ranges_take_precedence() {
+    let acl = Acl::new();
+    let name = OsStr::new("private");
+    assert!(acl.is_permitted(name, Some(ip("203.0.113.9"))));
+
+    acl.allow("private", cidr("10.0.0.0/8"));
+    assert!(acl.is_permitted(name, Some(ip("10.1.1.1"))));
+    assert!(!acl.is_permitted(name, Some(ip("203.0.113.9"))));
+    assert!(!acl.is_permitted(name, None));
+
+    acl.deny("private", cidr("10.1.0.0/16"));
+    assert!(!acl.is_permitted(name, Some(ip("10.1.1.1"))));
+    assert!(acl.is_permitted(name, Some(ip("10.2.1.1"))));
+
+    // Roots with only denied ranges are open to everyone else
+    acl.deny("public", cidr("203.0.113.0/24"));
+    assert!(acl.is_permitted(OsStr::new("public"), Some(ip("10.1.1.1"))));
+    assert!(acl.is_permitted(OsStr::new("public"), None));
+}
+
+#[tokio::test]
+async fn browsers_refuse_denied_roots() {
+    let fs = MemFS::builder().add_file("private/secret.txt", "").add_file("public/notes.txt", "").build();
+    let acl = Acl::new();
+    acl.allow("private", cidr("10.0.0.0/8"));
+
+    let mut browser = Browser::new(4, fs);
+    browser.set_client_addr(SocketAddr::new(ip("192.0.2.1"), 5000));
+    browser.set_acl(acl.clone());
+    let id = browser.create_cursor().unwrap();
+
+    browser.move_cursor(id, "/private").unwrap();
+    assert!(matches!(browser.read_cursor(id).await, Err(CursorError::PermissionDenied { .. })));
+    browser.move_cursor(id, "/public").unwrap();
+    assert!(browser.read_cursor(id).await.is_ok());
+
+    // Denied roots are left out of every listing of the root
+    browser.move_cursor(id, "/").unwrap();
+    let names: Vec<_> = browser.read_cursor(id).await.unwrap().iter().map(|element| element.name_lossy().into_owned()).collect();
+    assert_eq!(names, ["public"]);
+    let streamed: Vec<_> = browser.read_cursor_streaming(id).map(|element| element.unwrap().name_lossy().into_owned()).collect().await;
+    assert_eq!(streamed, ["public"]);
+    let paths: Vec<_> = browser.read_cursor_recursive(id, None).await.unwrap().into_iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, ["/public", "/public/notes.txt"].map(std::path::PathBuf::from));
+
+    acl.allow("private", cidr("192.0.2.0/24"));
+    browser.move_cursor(id, "/private").unwrap();
+    assert!(browser.read_cursor(id).await.is_ok());
+}
+
+#[tokio::test]
+async fn every_operation_is_refused_in_denied_roots() {
+    let fs = MemFS::builder().add_file("private/secret.txt", "secret").add_file("public/notes.txt", "").build();
+    let acl = Acl::new();
+    acl.allow("private", cidr("10.0.0.0/8"));
+
+    let mut browser = Browser::new(4, fs);
+    browser.set_client_addr(SocketAddr::new(ip("192.0.2.1"), 5000));
+    browser.set_acl(acl);
+    let id = browser.create_cursor().unwrap();
+
+    assert!(matches!(browser.stat(id, "/private/secret.txt").await, Err(CursorError::PermissionDenied { .. })));
+    assert!(matches!(browser.download_file(id, "/private/secret.txt").await, Err(CursorError::PermissionDenied { .. })));
+    assert!(matches!(browser.upload_file(id, "/private/new.txt", b"").await, Err(CursorError::PermissionDenied { .. })));
+    assert!(matches!(
+        browser.rename_file(id, "/private/secret.txt", "public.txt".as_ref()).await,
+        Err(CursorError::PermissionDenied { .. })
+    ));
+    assert!(browser.stat(id, "/public/notes.txt").await.is_ok());
+}
+
+#[tokio::test]
+async fn the_cli_changes_the_rules_of_every_connection() {
+    let mut server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    let root = format!("/{}", server.virtual_name);
+    assert!(matches!(client.read_at(id, &root).await, Response::Read(Ok(_))));
+
+    let command = format!("acl deny {} 127.0.0.0/8", server.virtual_name);
+    server.command_confirmed(&command, "may no longer list");
+    match client.request(Request::Read { id }).await {
+        Response::Read(Err(CursorError::PermissionDenied { .. })) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
@@ -0,0 +1,92 @@
+//! Archives never follow symbolic links, and stay within their depth and size limits
+#![cfg(unix)]
+
+use std::{fs, io::{Cursor, Read}, os::unix::fs::symlink, path::{Path, PathBuf}};
+
+use flate2::read::GzDecoder;
+use simple_file_transfer_v2::fs::archive::{build_archive, ArchiveFormat, ArchiveLimits};
+use tempfile::TempDir;
+use zip::ZipArchive;
+
+const LIMITS: ArchiveLimits = ArchiveLimits { max_depth: 64, max_size: 1024 * 1024 };
+
+/// The names of the entries in an archive, with directories marked by a trailing '/'
+fn entry_names(format: ArchiveFormat, data: Vec<u8>) -> Vec<String> {
+    let mut names: Vec<String> = match format {
+        ArchiveFormat::Zip => {
+            let archive = ZipArchive::new(Cursor::new(data)).unwrap();
+            archive.file_names().map(str::to_owned).collect()
+        }
+        ArchiveFormat::TarGz => {
+            let mut archive = tar::Archive::new(GzDecoder::new(data.as_slice()));
+            archive.entries().unwrap().map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().into_owned();
+                match entry.header().entry_type() {
+                    tar::EntryType::Directory => format!("{}/", name.trim_end_matches('/')),
+                    _ => name,
+                }
+            }).collect()
+        }
+    };
+    names.sort();
+    names
+}
+
+fn archive(format: ArchiveFormat, root: &Path, limits: ArchiveLimits) -> Result<Vec<String>, std::io::Error> {
+    build_archive(format, &[(PathBuf::from("root"), root.to_owned())], limits).map(|data| entry_names(format, data))
+}
+
+#[test]
+fn symbolic_links_are_never_followed() {
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+    let root = TempDir::new().unwrap();
+    fs::create_dir(root.path().join("a")).unwrap();
+    fs::write(root.path().join("a/file.txt"), "file").unwrap();
+    // Two links back up the tree would make a walk which follows them grow exponentially
+    symlink("..", root.path().join("a/up")).unwrap();
+    symlink(".", root.path().join("a/here")).unwrap();
+    symlink(outside.path().join("secret.txt"), root.path().join("secret.txt")).unwrap();
+
+    for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+        assert_eq!(archive(format, root.path(), LIMITS).unwrap(), ["root/", "root/a/", "root/a/file.txt"], "{format:?}");
+    }
+}
+
+#[test]
+fn directories_below_the_depth_limit_are_left_empty() {
+    let root = TempDir::new().unwrap();
+    fs::create_dir_all(root.path().join("a/b/c")).unwrap();
+    fs::write(root.path().join("a/shallow.txt"), "shallow").unwrap();
+    fs::write(root.path().join("a/b/c/deep.txt"), "deep").unwrap();
+
+    let limits = ArchiveLimits { max_depth: 2, ..LIMITS };
+    for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+        assert_eq!(
+            archive(format, root.path(), limits).unwrap(),
+            ["root/", "root/a/", "root/a/b/", "root/a/shallow.txt"],
+            "{format:?}"
+        );
+    }
+}
+
+#[test]
+fn archives_larger_than_the_limit_are_refused() {
+    let root = TempDir::new().unwrap();
+    fs::write(root.path().join("one.txt"), [1; 600]).unwrap();
+    fs::write(root.path().join("two.txt"), [2; 600]).unwrap();
+
+    let limits = ArchiveLimits { max_size: 1000, ..LIMITS };
+    for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+        let err = archive(format, root.path(), limits).unwrap_err();
+        assert!(err.to_string().contains("limit of 1000 bytes"), "{format:?}: {err}");
+    }
+
+    // The contents of a file within the limit are archived whole
+    let data = build_archive(ArchiveFormat::Zip, &[(PathBuf::from("one.txt"), root.path().join("one.txt"))], limits).unwrap();
+    let mut contents = vec![];
+    ZipArchive::new(Cursor::new(data)).unwrap().by_name("one.txt").unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, [1; 600]);
+}
@@ -0,0 +1,80 @@
+//! Checksums of single files, for verifying downloads
+
+mod common;
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use sha2::{Digest, Sha256};
+use simple_file_transfer_v2::fs::{
+    browser::{Browser, CursorError, Request, Response}, checksum::ChecksumAlgorithm, mapped_fs::MappedFS, open_files::OpenFileLimit
+};
+use tempfile::TempDir;
+use tokio::sync::Semaphore;
+
+use common::{describe, TestClient, TestServer};
+
+async fn checksum(client: &mut TestClient, id: u16, path: &str, algorithm: ChecksumAlgorithm) -> Result<Vec<u8>, CursorError> {
+    match client.request(Request::Checksum { id, path: PathBuf::from(path), algorithm }).await {
+        Response::Checksum(result) => result,
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn checksums_match_the_downloaded_bytes() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    let data = client.download(id, "inside.txt").await.unwrap();
+    assert_eq!(checksum(&mut client, id, "inside.txt", ChecksumAlgorithm::Sha256).await.unwrap(), Sha256::digest(&data).to_vec());
+    assert_eq!(
+        checksum(&mut client, id, "inside.txt", ChecksumAlgorithm::Blake3).await.unwrap(),
+        blake3::hash(&data).as_bytes().to_vec()
+    );
+}
+
+#[tokio::test]
+async fn directories_and_missing_files_have_no_checksum() {
+    let server = TestServer::start();
+    std::fs::create_dir(server.root.path().join("folder")).unwrap();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    let result = checksum(&mut client, id, "folder", ChecksumAlgorithm::Sha256).await;
+    assert!(matches!(result, Err(CursorError::NotAFile { .. })), "{result:?}");
+    let result = checksum(&mut client, id, "missing.txt", ChecksumAlgorithm::Sha256).await;
+    assert!(matches!(result, Err(CursorError::NotFound { .. })), "{result:?}");
+}
+
+#[tokio::test]
+async fn busy_paths_are_reported_without_failing_the_rest() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+    std::fs::write(dir.path().join("b.txt"), "b").unwrap();
+    let mut fs = MappedFS::new();
+    let name = fs.add(dir.path()).unwrap();
+
+    let semaphore = Arc::new(Semaphore::new(1));
+    let mut browser = Browser::new(4, fs);
+    browser.set_open_file_limit(OpenFileLimit::new(semaphore.clone(), Duration::from_millis(50)));
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, format!("/{}", name.to_string_lossy())).unwrap();
+    let paths = vec![PathBuf::from("a.txt"), PathBuf::from("missing.txt"), PathBuf::from("b.txt")];
+
+    // Every file is held open elsewhere, so each path gives up waiting on its own
+    let held = semaphore.clone().acquire_owned().await.unwrap();
+    let results = browser.checksum_many(id, paths.clone(), ChecksumAlgorithm::Sha256).await.unwrap();
+    assert_eq!(results.len(), 3);
+    for (path, result) in &results {
+        assert!(result.starts_with("ERROR: The server is too busy"), "{path:?}: {result}");
+    }
+
+    drop(held);
+    let results = browser.checksum_many(id, paths, ChecksumAlgorithm::Sha256).await.unwrap();
+    assert_eq!(results[0].1, hex::encode(Sha256::digest(b"a")));
+    assert!(results[1].1.starts_with("ERROR: "), "{}", results[1].1);
+    assert_eq!(results[2].1, hex::encode(Sha256::digest(b"b")));
+}
@@ -0,0 +1,102 @@
+//! The client's non-interactive commands, run as a separate process
+
+mod common;
+
+use std::process::{Command, Output};
+
+use common::{connect, TestServer};
+
+/// Run a client command against the server, once it is accepting connections
+async fn client(server: &TestServer, command: &str, args: &[&str]) -> Output {
+    drop(connect(server.port).await);
+    let mut client = Command::new(env!("CARGO_BIN_EXE_client"));
+    client.args([command, &format!("127.0.0.1:{}", server.port)]).args(args);
+    tokio::task::spawn_blocking(move || client.output().unwrap()).await.unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[tokio::test]
+async fn list_prints_one_name_per_line() {
+    let server = TestServer::start();
+    std::fs::create_dir(server.root.path().join("subdir")).unwrap();
+    let root = format!("/{}", server.virtual_name);
+
+    let output = client(&server, "list", &[&root]).await;
+    assert_eq!(stdout(&output), "inside.txt\nsubdir/\n");
+}
+
+#[tokio::test]
+async fn files_can_be_uploaded_downloaded_and_checked() {
+    let server = TestServer::start();
+    let local = tempfile::TempDir::new().unwrap();
+    std::fs::write(local.path().join("source.txt"), "abc").unwrap();
+    let remote = format!("/{}/uploaded.txt", server.virtual_name);
+
+    let source = local.path().join("source.txt");
+    stdout(&client(&server, "upload", &[source.to_str().unwrap(), &remote]).await);
+    assert_eq!(std::fs::read(server.root.path().join("uploaded.txt")).unwrap(), b"abc");
+
+    let destination = local.path().join("downloaded.txt");
+    stdout(&client(&server, "download", &[&remote, destination.to_str().unwrap()]).await);
+    assert_eq!(std::fs::read(destination).unwrap(), b"abc");
+
+    let output = client(&server, "checksum", &[&remote]).await;
+    assert_eq!(stdout(&output), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n");
+    let output = client(&server, "checksum", &[&remote, "--algorithm", "blake3"]).await;
+    assert_eq!(stdout(&output).trim(), blake3::hash(b"abc").to_hex().as_str());
+}
+
+#[tokio::test]
+async fn batch_scripts_can_download_files() {
+    let server = TestServer::start();
+    let local = tempfile::TempDir::new().unwrap();
+    let destination = local.path().join("downloaded copy.txt");
+    let script = local.path().join("script.txt");
+    let lines = [
+        "create".to_owned(),
+        format!("navigate /{}", server.virtual_name),
+        format!("download inside.txt {}", destination.display()),
+        "download missing.txt elsewhere.txt".to_owned(),
+        "exit".to_owned(),
+    ];
+    std::fs::write(&script, lines.join("\n")).unwrap();
+
+    drop(connect(server.port).await);
+    let mut client = Command::new(env!("CARGO_BIN_EXE_client"));
+    client.args(["--server", &format!("127.0.0.1:{}", server.port), "--batch", script.to_str().unwrap()]);
+    let output = tokio::task::spawn_blocking(move || client.output().unwrap()).await.unwrap();
+
+    // The failed download is reported, and does not stop the script
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Error on line 4"), "{stdout}");
+    assert_eq!(std::fs::read(destination).unwrap(), b"inside");
+}
+
+#[tokio::test]
+async fn errors_exit_with_a_failure_code() {
+    let server = TestServer::start();
+    let missing = format!("/{}/missing", server.virtual_name);
+
+    let output = client(&server, "list", &[&missing]).await;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[cfg(feature = "json")]
+#[tokio::test]
+async fn list_can_print_json() {
+    use simple_file_transfer_v2::fs::FSElement;
+
+    let server = TestServer::start();
+    let root = format!("/{}", server.virtual_name);
+
+    let output = client(&server, "list", &[&root, "--json"]).await;
+    let elements: Vec<FSElement> = stdout(&output).lines().map(|line| FSElement::from_json(line).unwrap()).collect();
+    assert_eq!(elements.len(), 1);
+    assert_eq!(elements[0].name, "inside.txt");
+    assert_eq!(elements[0].size, 6);
+}
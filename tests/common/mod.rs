@@ -0,0 +1,218 @@
+//! A server and client pair for tests which exercise the whole request path. Each test file uses a different
+//! subset of these helpers
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    time::Duration,
+};
+
+use simple_file_transfer_v2::{
+    fs::browser::{CursorError, Request, Response},
+    protocol::{read_frame, read_magic, write_frame, write_magic, ClientHello, CompressionAlgorithm, ServerHello, PROTOCOL_VERSION},
+};
+use tempfile::TempDir;
+use tokio::{io::{AsyncRead, AsyncWrite, AsyncWriteExt}, net::TcpStream};
+use tokio_rustls::{client::TlsStream, rustls::ServerName, TlsConnector};
+
+/// A server process with a single temporary directory registered. The process is killed when this is dropped
+pub struct TestServer {
+    process: Child,
+    stdin: ChildStdin,
+    // Kept open, since the server stops once printing a prompt fails
+    stdout: BufReader<ChildStdout>,
+    pub port: u16,
+    pub virtual_name: String,
+    /// The registered directory. It contains a file named inside.txt
+    pub root: TempDir,
+}
+
+impl TestServer {
+    pub fn start() -> TestServer {
+        TestServer::start_with_args(&[])
+    }
+
+    /// Start a server with extra command line arguments, such as the TLS settings
+    pub fn start_with_args(args: &[&str]) -> TestServer {
+        let root = TempDir::new().unwrap();
+        std::fs::write(root.path().join("inside.txt"), "inside").unwrap();
+
+        // Let the OS pick a free port, then hand it to the server
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut process = Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--port", &port.to_string()])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut stdin = process.stdin.take().unwrap();
+        writeln!(stdin, "add\n{}", root.path().display()).unwrap();
+
+        // Wait for the CLI to confirm the path was added. The prompts are not followed by newlines, so they
+        // share a line with the confirmation
+        let mut stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut line = String::new();
+        while !line.contains("Successfully added") {
+            line.clear();
+            assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "The server exited before the path was added");
+        }
+
+        let virtual_name = root.path().file_name().unwrap().to_string_lossy().into_owned();
+        TestServer { process, stdin, stdout, port, virtual_name, root }
+    }
+}
+
+impl TestServer {
+    /// Type a command into the server's CLI
+    pub fn command(&mut self, command: &str) {
+        writeln!(self.stdin, "{command}").unwrap();
+    }
+
+    /// Type a command into the server's CLI and wait until it prints a line containing `confirmation`
+    pub fn command_confirmed(&mut self, command: &str, confirmation: &str) {
+        self.command(command);
+        let mut line = String::new();
+        while !line.contains(confirmation) {
+            line.clear();
+            assert_ne!(self.stdout.read_line(&mut line).unwrap(), 0, "The server exited before confirming {command:?}");
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "exit");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Open a TCP connection to a [`TestServer`] without starting the handshake
+pub async fn connect(port: u16) -> TcpStream {
+    // The listener may not be bound yet when the CLI is already accepting commands
+    let mut attempts = 0;
+    loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(_) if attempts < 50 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(err) => panic!("Could not connect to the server: {err}"),
+        }
+    }
+}
+
+/// A connection to a [`TestServer`] which has completed the handshake. The stream is either a plain TCP stream or
+/// one wrapped in TLS
+pub struct TestClient<S = TcpStream> {
+    stream: S,
+    buffer: Vec<u8>,
+    compression: CompressionAlgorithm,
+}
+
+impl TestClient {
+    pub async fn connect(port: u16) -> TestClient {
+        TestClient::handshake(connect(port).await).await
+    }
+}
+
+impl TestClient<TlsStream<TcpStream>> {
+    /// Connect using TLS, verifying the server's certificate against the name "localhost"
+    pub async fn connect_tls(port: u16, connector: &TlsConnector) -> Result<TestClient<TlsStream<TcpStream>>, std::io::Error> {
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let stream = connector.connect(server_name, connect(port).await).await?;
+        Ok(TestClient::handshake(stream).await)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TestClient<S> {
+    pub async fn handshake(mut stream: S) -> TestClient<S> {
+        let mut buffer = vec![];
+        let hello = ClientHello { supported_compression: vec![], heartbeat: None, protocol_version: PROTOCOL_VERSION };
+        write_magic(&mut stream).await.unwrap();
+        write_frame(&mut stream, CompressionAlgorithm::None, &hello).await.unwrap();
+        stream.flush().await.unwrap();
+        read_magic(&mut stream).await.unwrap();
+        let hello: ServerHello = read_frame(&mut stream, &mut buffer, CompressionAlgorithm::None).await.unwrap();
+
+        TestClient { stream, buffer, compression: hello.selected_compression }
+    }
+
+    pub async fn request(&mut self, request: Request) -> Response<'static> {
+        self.send(request).await;
+        read_frame(&mut self.stream, &mut self.buffer, self.compression).await.unwrap()
+    }
+
+    /// Send a request without waiting for its response
+    pub async fn send(&mut self, request: Request) {
+        write_frame(&mut self.stream, self.compression, &request).await.unwrap();
+        self.stream.flush().await.unwrap();
+    }
+
+    /// Read the next frame without sending a request, or fail if the connection is closed
+    pub async fn receive(&mut self) -> Result<Response<'static>, anyhow::Error> {
+        read_frame(&mut self.stream, &mut self.buffer, self.compression).await
+    }
+
+    pub async fn create_cursor(&mut self) -> u16 {
+        match self.request(Request::Create).await {
+            Response::Create(Ok(id)) => id,
+            response => panic!("Unexpected response: {}", describe(&response)),
+        }
+    }
+
+    /// Download the file at `path`, relative to the Cursor's location
+    pub async fn download(&mut self, id: u16, path: &str) -> Result<Vec<u8>, CursorError> {
+        let download_id = match self.request(Request::Download { id, path: PathBuf::from(path) }).await {
+            Response::Download(result) => result?,
+            response => panic!("Unexpected response: {}", describe(&response)),
+        };
+
+        let mut data = vec![];
+        loop {
+            match read_frame(&mut self.stream, &mut self.buffer, self.compression).await.unwrap() {
+                Response::DownloadChunk { download_id: chunk_id, data: chunk, bytes_remaining } => {
+                    assert_eq!(chunk_id, download_id);
+                    data.extend_from_slice(&chunk);
+                    if bytes_remaining == 0 {
+                        return Ok(data);
+                    }
+                }
+                response => panic!("Unexpected response: {}", describe(&response)),
+            }
+        }
+    }
+
+    /// Move a cursor to `path` and read it
+    pub async fn read_at(&mut self, id: u16, path: &str) -> Response<'static> {
+        match self.request(Request::Navigate { id, path: PathBuf::from(path) }).await {
+            Response::Navigate(Ok(())) => (),
+            response => panic!("Unexpected response: {}", describe(&response)),
+        }
+        self.request(Request::Read { id }).await
+    }
+}
+
+/// Describe a response in a failure message, since [`Response`] does not implement Debug
+pub fn describe(response: &Response) -> String {
+    match response {
+        Response::Read(Ok((_, elements))) => {
+            let names: Vec<_> = elements.iter().map(|element| &element.name).collect();
+            format!("listed {names:?}")
+        }
+        Response::Read(Err(err))
+        | Response::Navigate(Err(err))
+        | Response::Create(Err(err))
+        | Response::Download(Err(err))
+        | Response::Upload(Err(err))
+        | Response::Rename(Err(err)) => format!("error: {err}"),
+        _ => "a response of another type".to_owned(),
+    }
+}
@@ -0,0 +1,97 @@
+//! Several file systems mounted side by side in a CompositeFS
+
+use std::{ffi::OsString, io, path::Path};
+
+use simple_file_transfer_v2::fs::{
+    browser::{Browser, CursorError, Request, Response},
+    composite_fs::{CompositeFS, CompositeFSError},
+    mapped_fs::MappedFS,
+    mem_fs::MemFS,
+    FS
+};
+use tempfile::TempDir;
+
+fn names(elements: &[simple_file_transfer_v2::fs::FSElement]) -> Vec<String> {
+    elements.iter().map(|element| element.name_lossy().into_owned()).collect()
+}
+
+/// "archive" holds a MappedFS with one registered directory, and "scratch" a MemFS
+fn composite(dir: &TempDir) -> (CompositeFS, MemFS) {
+    std::fs::write(dir.path().join("report.txt"), "report").unwrap();
+    let mut mapped_fs = MappedFS::new();
+    mapped_fs.add(dir.path()).unwrap();
+    let scratch = MemFS::builder().add_file("notes.txt", "notes").build();
+
+    let mut fs = CompositeFS::new();
+    fs.mount("archive".into(), mapped_fs).mount("scratch".into(), scratch.clone());
+    (fs, scratch)
+}
+
+#[tokio::test]
+async fn the_root_lists_every_mount() {
+    let dir = TempDir::new().unwrap();
+    let (fs, _) = composite(&dir);
+
+    let elements = fs.list("/").await.unwrap();
+    assert_eq!(names(&elements), ["archive", "scratch"]);
+    assert!(elements.iter().all(|element| !element.is_file));
+    assert_eq!(fs.metadata("/scratch").await.unwrap().name, "scratch");
+}
+
+#[tokio::test]
+async fn paths_are_routed_by_their_first_component() {
+    let dir = TempDir::new().unwrap();
+    let (fs, scratch) = composite(&dir);
+    let name = dir.path().file_name().unwrap().to_string_lossy();
+
+    assert_eq!(names(&fs.list("/scratch").await.unwrap()), ["notes.txt"]);
+    assert_eq!(names(&fs.list(format!("/archive/{name}")).await.unwrap()), ["report.txt"]);
+    assert_eq!(fs.unmap(format!("/archive/{name}/report.txt")).unwrap(), dir.path().join("report.txt"));
+
+    fs.write("/scratch/new.txt", b"new").await.unwrap();
+    fs.rename("/scratch/new.txt", "/scratch/renamed.txt").await.unwrap();
+    assert_eq!(scratch.read("/renamed.txt").unwrap(), b"new");
+}
+
+#[tokio::test]
+async fn unmounted_paths_are_not_found() {
+    let dir = TempDir::new().unwrap();
+    let (fs, _) = composite(&dir);
+
+    match fs.list("/missing/file.txt").await {
+        Err(CompositeFSError::NotFound(path, source)) => {
+            assert_eq!(path, Path::new("/missing/file.txt"));
+            assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+
+    let mut browser = Browser::new(4, fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/missing").unwrap();
+    assert!(matches!(browser.process(Request::Read { id }).await, Response::Read(Err(CursorError::NotFound { .. }))));
+}
+
+#[tokio::test]
+async fn renames_stay_within_one_mount() {
+    let dir = TempDir::new().unwrap();
+    let (fs, _) = composite(&dir);
+    let name = dir.path().file_name().unwrap().to_string_lossy();
+
+    let result = fs.rename("/scratch/notes.txt", format!("/archive/{name}/notes.txt")).await;
+    assert!(matches!(result, Err(CompositeFSError::Unsupported(_))));
+    assert!(!fs.is_writable("/"));
+}
+
+#[test]
+fn mounting_a_name_twice_replaces_the_first() {
+    let mut fs = CompositeFS::new();
+    fs.mount("data".into(), MemFS::new()).mount("data".into(), MemFS::new());
+    assert_eq!(fs.mounted().collect::<Vec<_>>(), [OsString::from("data")]);
+}
+
+#[test]
+#[should_panic]
+fn names_must_be_a_single_component() {
+    CompositeFS::new().mount("a/b".into(), MemFS::new());
+}
@@ -0,0 +1,78 @@
+//! The server's TOML configuration file
+
+mod common;
+
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use simple_file_transfer_v2::{config::Config, fs::browser::{CursorError, Request, Response}};
+use tempfile::TempDir;
+
+use common::{describe, TestClient, TestServer};
+
+#[test]
+fn parses_every_setting() {
+    let config = Config::parse(r#"
+        bind_addr = "0.0.0.0:9000"
+        cursor_limit = 4
+        initial_paths = ["/srv/files", "/home/shared"]
+        idle_cursor_timeout_secs = 60
+    "#).unwrap();
+
+    assert_eq!(config, Config {
+        bind_addr: SocketAddr::from(([0, 0, 0, 0], 9000)),
+        cursor_limit: 4,
+        initial_paths: vec![PathBuf::from("/srv/files"), PathBuf::from("/home/shared")],
+        idle_cursor_timeout_secs: Some(60)
+    });
+}
+
+#[test]
+fn idle_cursor_collection_can_be_turned_off() {
+    assert_eq!(Config::default().idle_cursor_timeout(), Some(Duration::from_secs(1800)));
+    assert_eq!(Config::parse("idle_cursor_timeout_secs = 0").unwrap().idle_cursor_timeout(), None);
+}
+
+#[test]
+fn missing_settings_keep_their_defaults() {
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+    assert_eq!(Config::parse("cursor_limit = 2").unwrap(), Config { cursor_limit: 2, ..Config::default() });
+}
+
+#[test]
+fn relative_initial_paths_are_rejected() {
+    let err = Config::parse(r#"initial_paths = ["/srv/files", "relative/path"]"#).unwrap_err();
+    assert!(err.to_string().contains("relative/path"), "{err}");
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    assert!(Config::parse("cursor_limt = 2").is_err());
+    assert!(Config::parse(r#"bind_addr = "not an address""#).is_err());
+}
+
+#[tokio::test]
+async fn the_server_uses_the_configuration() {
+    let shared = TempDir::new().unwrap();
+    let config_dir = TempDir::new().unwrap();
+    let config_path = config_dir.path().join("server.toml");
+    std::fs::write(&config_path, format!(
+        "cursor_limit = 1\ninitial_paths = [{:?}, \"/does/not/exist\"]",
+        shared.path()
+    )).unwrap();
+
+    let server = TestServer::start_with_args(&["--config", config_path.to_str().unwrap()]);
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+
+    let names = match client.read_at(id, "/").await {
+        Response::Read(Ok((_, elements))) => elements.iter().map(|element| element.name_lossy().into_owned()).collect::<Vec<_>>(),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    };
+    assert!(names.contains(&shared.path().file_name().unwrap().to_string_lossy().into_owned()), "{names:?}");
+    assert!(names.contains(&server.virtual_name), "{names:?}");
+
+    match client.request(Request::Create).await {
+        Response::Create(Err(CursorError::CursorLimitReached { limit: 1, .. })) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
@@ -0,0 +1,116 @@
+//! Copies within the mapped file system never replace, follow links or leave partial copies behind
+#![cfg(unix)]
+
+use std::{fs, os::unix::fs::{symlink, PermissionsExt}};
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError}, mapped_fs::MappedFS};
+use tempfile::TempDir;
+
+/// A Browser with `dir` registered, and a Cursor at its virtual path
+fn browser(dir: &TempDir) -> (Browser<MappedFS>, u16) {
+    let mut fs = MappedFS::new();
+    let name = fs.add(dir.path()).unwrap();
+    let mut browser = Browser::new(4, fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, format!("/{}", name.to_string_lossy())).unwrap();
+    (browser, id)
+}
+
+#[tokio::test]
+async fn directories_are_copied_with_their_contents() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+    fs::write(dir.path().join("src/nested/file.txt"), "file").unwrap();
+    fs::set_permissions(dir.path().join("src/nested/file.txt"), fs::Permissions::from_mode(0o640)).unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    browser.copy(id, "src", "dest").await.unwrap();
+    let copied = dir.path().join("dest/nested/file.txt");
+    assert_eq!(fs::read_to_string(&copied).unwrap(), "file");
+    assert_eq!(fs::metadata(&copied).unwrap().permissions().mode() & 0o777, 0o640);
+}
+
+#[tokio::test]
+async fn existing_destinations_are_never_replaced() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("from.txt"), "from").unwrap();
+    fs::write(dir.path().join("to.txt"), "to").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::create_dir(dir.path().join("dest")).unwrap();
+    symlink(dir.path().join("missing"), dir.path().join("broken")).unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    for (from, to) in [("from.txt", "to.txt"), ("src", "dest"), ("from.txt", "dest"), ("from.txt", "broken")] {
+        assert!(matches!(browser.copy(id, from, to).await, Err(CursorError::AlreadyExists { .. })), "{from} -> {to}");
+    }
+    assert_eq!(fs::read_to_string(dir.path().join("to.txt")).unwrap(), "to");
+    assert!(!dir.path().join("missing").exists());
+}
+
+#[tokio::test]
+async fn symbolic_links_are_not_followed() {
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/file.txt"), "file").unwrap();
+    symlink(outside.path(), dir.path().join("src/outside")).unwrap();
+    symlink(outside.path().join("secret.txt"), dir.path().join("link.txt")).unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    browser.copy(id, "src", "dest").await.unwrap();
+    let mut names: Vec<_> = fs::read_dir(dir.path().join("dest")).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    names.sort();
+    assert_eq!(names, ["file.txt"]);
+
+    assert!(matches!(browser.copy(id, "link.txt", "copy.txt").await, Err(CursorError::CopyError { .. })));
+    assert!(!dir.path().join("copy.txt").exists());
+}
+
+#[tokio::test]
+async fn failed_copies_are_removed() {
+    let dir = TempDir::new().unwrap();
+    // The deepest directory fits within the limit on the length of a path, but its copy under a longer name does not
+    let mut deepest = dir.path().join("s");
+    while deepest.as_os_str().len() < 3900 {
+        deepest.push("d".repeat(100));
+    }
+    fs::create_dir_all(&deepest).unwrap();
+    fs::write(dir.path().join("s/file.txt"), "file").unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    let long_name = "c".repeat(250);
+    assert!(matches!(browser.copy(id, "s", &long_name).await, Err(CursorError::CopyError { .. })));
+    assert!(!dir.path().join(&long_name).exists());
+}
+
+#[tokio::test]
+async fn links_to_directories_elsewhere_cannot_be_written_through() {
+    let outside = TempDir::new().unwrap();
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("file.txt"), "file").unwrap();
+    fs::create_dir(dir.path().join("inside")).unwrap();
+    symlink(outside.path(), dir.path().join("link")).unwrap();
+    // A link which stays within the root may be written through
+    symlink(dir.path().join("inside"), dir.path().join("alias")).unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    assert!(matches!(browser.copy(id, "file.txt", "link/file.txt").await, Err(CursorError::AccessDenied { .. })));
+    assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+
+    browser.copy(id, "file.txt", "alias/file.txt").await.unwrap();
+    assert_eq!(fs::read_to_string(dir.path().join("inside/file.txt")).unwrap(), "file");
+}
+
+#[tokio::test]
+async fn copies_are_seen_by_cached_listings() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("file.txt"), "file").unwrap();
+    let (mut browser, id) = browser(&dir);
+    browser.set_cache_ttl(None);
+    assert_eq!(browser.read_cursor(id).await.unwrap().len(), 1);
+
+    browser.copy(id, "file.txt", "copy.txt").await.unwrap();
+    assert_eq!(browser.read_cursor(id).await.unwrap().len(), 2);
+}
@@ -0,0 +1,20 @@
+//! Creating Cursors until every ID is in use
+
+use std::collections::HashSet;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError}, mem_fs::MemFS};
+
+#[test]
+fn the_largest_limit_can_be_reached() {
+    let mut browser = Browser::new(u16::MAX, MemFS::default());
+
+    let ids: HashSet<u16> = (0..u16::MAX).map(|_| browser.create_cursor().unwrap()).collect();
+    assert_eq!(ids.len(), u16::MAX as usize);
+    assert!(matches!(browser.create_cursor(), Err(CursorError::CursorLimitReached { limit: u16::MAX, .. })));
+
+    // An ID which is freed while almost every other ID is taken can be handed out again
+    let id = *ids.iter().next().unwrap();
+    browser.destroy_cursor(id).unwrap();
+    browser.create_cursor().unwrap();
+    assert!(matches!(browser.create_cursor(), Err(CursorError::CursorLimitReached { .. })));
+}
@@ -0,0 +1,71 @@
+//! A limit on the number of Cursors open across every connection
+
+mod common;
+
+use std::{sync::{atomic::AtomicU32, Arc}, time::Duration};
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, cursor_quota::CursorQuota, mem_fs::MemFS};
+
+use common::{describe, TestClient, TestServer};
+
+#[test]
+fn browsers_share_the_quota() {
+    let used = Arc::new(AtomicU32::new(0));
+    let mut first = Browser::new_with_global_quota(16, used.clone(), 3, MemFS::new());
+    let mut second = Browser::new_with_global_quota(16, used.clone(), 3, MemFS::new());
+
+    let id = first.create_cursor().unwrap();
+    first.create_cursor().unwrap();
+    second.create_cursor().unwrap();
+    assert!(matches!(second.create_cursor(), Err(CursorError::GlobalLimitReached { limit: 3, .. })));
+    assert!(matches!(first.create_cursor(), Err(CursorError::GlobalLimitReached { limit: 3, .. })));
+
+    first.destroy_cursor(id).unwrap();
+    second.create_cursor().unwrap();
+    assert_eq!(CursorQuota::new(used, 3).used(), 3);
+}
+
+#[test]
+fn dropped_browsers_release_their_cursors() {
+    let quota = CursorQuota::new(Arc::new(AtomicU32::new(0)), 4);
+    let mut browser = Browser::new(16, MemFS::new());
+    browser.create_cursor().unwrap();
+    // Cursors created before the quota was set count as well
+    browser.set_cursor_quota(quota.clone());
+    browser.create_cursor().unwrap();
+    assert_eq!(quota.used(), 2);
+
+    drop(browser);
+    assert_eq!(quota.used(), 0);
+}
+
+#[test]
+fn the_per_browser_limit_still_applies() {
+    let mut browser = Browser::new_with_global_quota(1, Arc::new(AtomicU32::new(0)), 10, MemFS::new());
+    browser.create_cursor().unwrap();
+    assert!(matches!(browser.create_cursor(), Err(CursorError::CursorLimitReached { .. })));
+}
+
+#[tokio::test]
+async fn connections_share_the_server_quota() {
+    let server = TestServer::start_with_args(&["--max-total-cursors", "2"]);
+    let mut first = TestClient::connect(server.port).await;
+    let mut second = TestClient::connect(server.port).await;
+    first.create_cursor().await;
+    first.create_cursor().await;
+
+    match second.request(Request::Create).await {
+        Response::Create(Err(CursorError::GlobalLimitReached { limit: 2, .. })) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+
+    // The server notices the closed connection shortly after, and frees its Cursors
+    drop(first);
+    for _ in 0..50 {
+        if let Response::Create(Ok(_)) = second.request(Request::Create).await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The Cursors of the closed connection were not released");
+}
@@ -0,0 +1,35 @@
+//! Cached listings are checked against the modification time of their directory
+
+use std::{fs, sync::atomic::Ordering};
+
+use simple_file_transfer_v2::fs::{browser::Browser, mapped_fs::MappedFS};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn the_directory_is_only_read_when_its_listing_is_cached() {
+    let dir = TempDir::new().unwrap();
+    let mut fs = MappedFS::new();
+    // Listing a directory does not unmap its path, while reading its metadata does
+    let metrics = fs.with_metrics();
+    let name = fs.add(dir.path()).unwrap();
+    let mut browser = Browser::new(4, fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, format!("/{}", name.to_string_lossy())).unwrap();
+
+    // Every read lists the directory again by default, so there is nothing to check
+    browser.read_cursor(id).await.unwrap();
+    browser.read_cursor(id).await.unwrap();
+    assert_eq!(metrics.unmap_calls.load(Ordering::Relaxed), 0);
+
+    browser.set_cache_ttl(None);
+    browser.read_cursor(id).await.unwrap();
+    browser.read_cursor(id).await.unwrap();
+    assert_eq!(metrics.unmap_calls.load(Ordering::Relaxed), 2);
+    assert_eq!(metrics.list_calls.load(Ordering::Relaxed), 3);
+
+    // A new entry changes the modification time, so the cached listing is read again
+    fs::write(dir.path().join("new.txt"), "").unwrap();
+    let elements = browser.read_cursor(id).await.unwrap();
+    assert_eq!(elements.len(), 1);
+    assert_eq!(metrics.list_calls.load(Ordering::Relaxed), 4);
+}
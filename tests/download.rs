@@ -0,0 +1,60 @@
+//! Downloads through the real server, including contents which need several DownloadChunk frames
+
+mod common;
+
+use simple_file_transfer_v2::{
+    fs::browser::{CursorError, Response, MAX_DOWNLOAD_SIZE},
+    protocol::CompressionAlgorithm
+};
+
+use common::{TestClient, TestServer};
+
+#[tokio::test]
+async fn download_returns_the_file_contents() {
+    let server = TestServer::start();
+    let large: Vec<u8> = (0..100_000u32).map(|i| 0x80 | (i % 128) as u8).collect();
+    std::fs::write(server.root.path().join("large.bin"), &large).unwrap();
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    assert_eq!(client.download(id, "inside.txt").await.unwrap(), b"inside");
+    assert_eq!(client.download(id, "large.bin").await.unwrap(), large);
+}
+
+#[tokio::test]
+async fn download_of_a_missing_file_fails() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    assert!(matches!(client.download(id, "missing.txt").await, Err(CursorError::NotFound { .. })));
+    // The connection is still usable afterwards
+    assert_eq!(client.download(id, "inside.txt").await.unwrap(), b"inside");
+}
+
+#[tokio::test]
+async fn files_larger_than_the_limit_must_be_streamed() {
+    let server = TestServer::start();
+    // A sparse file, so the test does not write the whole limit to disk
+    let file = std::fs::File::create(server.root.path().join("huge.bin")).unwrap();
+    file.set_len(MAX_DOWNLOAD_SIZE + 1).unwrap();
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    let result = client.download(id, "huge.bin").await;
+    assert!(matches!(result, Err(CursorError::FileTooLarge { size, .. }) if size == MAX_DOWNLOAD_SIZE + 1), "{result:?}");
+}
+
+#[test]
+fn chunks_are_encoded_as_raw_bytes() {
+    // Bytes of 0x80 and above would take two bytes each if the data were encoded as an array of integers
+    let data = vec![0xff; 100_000];
+    let chunk = Response::DownloadChunk { download_id: 1, data: data.clone(), bytes_remaining: 0 };
+    let encoded = CompressionAlgorithm::None.compress(rmp_serde::to_vec(&chunk).unwrap()).unwrap();
+    assert!(encoded.len() < data.len() + 64, "{} bytes", encoded.len());
+}
@@ -0,0 +1,25 @@
+//! Timestamps of the elements in a MappedFS
+
+use std::time::{Duration, SystemTime};
+
+use simple_file_transfer_v2::fs::mapped_fs::MappedFS;
+use tempfile::TempDir;
+use time::{Date, Month, Time, UtcOffset};
+
+#[tokio::test]
+async fn times_before_the_epoch_are_converted() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("old.txt");
+    let file = std::fs::File::create(&path).unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH - Duration::from_secs(3600)).unwrap();
+    drop(file);
+
+    let mut mapped_fs = MappedFS::new();
+    let name = mapped_fs.add(dir.path()).unwrap();
+    let element = mapped_fs.metadata(format!("/{}/old.txt", name.to_string_lossy())).await.unwrap();
+
+    // The time is in the local offset, so it is compared in UTC
+    let modified = element.modified.unwrap().to_offset(UtcOffset::UTC);
+    assert_eq!(modified.date(), Date::from_calendar_date(1969, Month::December, 31).unwrap());
+    assert_eq!(modified.time(), Time::from_hms(23, 0, 0).unwrap());
+}
@@ -0,0 +1,72 @@
+//! Frames larger than the old u16 length prefix allowed, and peers which still use that prefix
+
+mod common;
+
+use simple_file_transfer_v2::protocol::{
+    read_frame, write_frame, ClientHello, CompressionAlgorithm, ProtocolError, MAX_FRAME_LEN
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use common::{connect, TestServer};
+
+/// Bytes of every value, so that MessagePack needs both its one and two byte encodings
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 256) as u8).collect()
+}
+
+#[tokio::test]
+async fn large_frames_round_trip() {
+    for compression in [CompressionAlgorithm::None, CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4] {
+        let sent = payload(100_000);
+        let (mut writer, mut reader) = tokio::io::duplex(MAX_FRAME_LEN);
+
+        write_frame(&mut writer, compression, &sent).await.unwrap();
+        let received: Vec<u8> = read_frame(&mut reader, &mut vec![], compression).await.unwrap();
+        assert_eq!(received, sent, "{compression:?}");
+    }
+}
+
+#[tokio::test]
+async fn oversized_frames_are_refused() {
+    let (mut writer, mut reader) = tokio::io::duplex(64);
+    writer.write_u32(MAX_FRAME_LEN as u32 + 1).await.unwrap();
+
+    let err = read_frame::<Vec<u8>>(&mut reader, &mut vec![], CompressionAlgorithm::None).await.unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(ProtocolError::MessageTooLarge { .. })));
+}
+
+#[tokio::test]
+async fn clients_without_the_magic_are_refused() {
+    let server = TestServer::start();
+    let mut stream = connect(server.port).await;
+
+    // A hello framed with a u16 length, as clients from before the magic was introduced sent it
+    let hello = ClientHello { supported_compression: vec![], heartbeat: None, protocol_version: 1 };
+    let data = rmp_serde::to_vec(&hello).unwrap();
+    stream.write_u16(data.len() as u16).await.unwrap();
+    stream.write_all(&data).await.unwrap();
+    stream.flush().await.unwrap();
+
+    let err = read_frame::<()>(&mut stream, &mut vec![], CompressionAlgorithm::None).await.unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(ProtocolError::MagicMismatch)));
+
+    // The server closes the connection after reporting the error
+    assert_eq!(stream.read(&mut [0; 1]).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn decompression_bombs_are_refused() {
+    let bomb = vec![0u8; MAX_FRAME_LEN * 4];
+    for compression in [CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4] {
+        // A frame well under the limit which expands to four times the limit
+        let data = compression.compress(bomb.clone()).unwrap();
+        assert!(data.len() < MAX_FRAME_LEN, "{compression:?}");
+
+        let (mut writer, mut reader) = tokio::io::duplex(MAX_FRAME_LEN);
+        writer.write_u32(data.len() as u32).await.unwrap();
+        writer.write_all(&data).await.unwrap();
+
+        let err = read_frame::<Vec<u8>>(&mut reader, &mut vec![], compression).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ProtocolError::MessageTooLarge { .. })), "{compression:?}");
+    }
+}
@@ -0,0 +1,64 @@
+//! The comparison traits of FSElement agree with each other
+
+use std::{cmp::Ordering, collections::{BTreeSet, HashSet}, ffi::OsString, hash::{BuildHasher, RandomState}};
+
+use simple_file_transfer_v2::fs::FSElement;
+use time::OffsetDateTime;
+
+fn file(name: &str, size: u64) -> FSElement {
+    FSElement {
+        name: OsString::from(name),
+        created: Some(OffsetDateTime::UNIX_EPOCH),
+        modified: Some(OffsetDateTime::UNIX_EPOCH),
+        size,
+        is_file: true,
+        is_symlink: false,
+        hash: None,
+        mode: None,
+        uid: None,
+        gid: None,
+        inode: None
+    }
+}
+
+#[test]
+fn elements_are_ordered_by_name_first() {
+    let mut elements = [file("b", 1), file("c", 0), file("a", 2)];
+    elements.sort();
+    let names: Vec<_> = elements.iter().map(|element| element.name_lossy().into_owned()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+}
+
+#[test]
+fn only_equal_elements_compare_as_equal() {
+    let small = file("a", 1);
+    let large = file("a", 2);
+    let modified = FSElement { modified: Some(OffsetDateTime::UNIX_EPOCH + time::Duration::SECOND), ..small.clone() };
+    let hashed = FSElement { hash: Some(vec![1]), ..small.clone() };
+
+    for other in [&large, &modified, &hashed] {
+        assert_ne!(small, *other);
+        assert_ne!(small.cmp(other), Ordering::Equal);
+        assert_eq!(small.cmp(other), other.cmp(&small).reverse());
+    }
+    assert_eq!(small.cmp(&small.clone()), Ordering::Equal);
+
+    // A set keeps elements which share a name but are not equal
+    let set: BTreeSet<_> = [small.clone(), large, modified, hashed, small].into_iter().collect();
+    assert_eq!(set.len(), 4);
+}
+
+#[test]
+fn equal_elements_hash_the_same() {
+    let hasher = RandomState::new();
+    let element = file("a", 1);
+    let modified = FSElement { modified: Some(OffsetDateTime::UNIX_EPOCH + time::Duration::SECOND), ..element.clone() };
+
+    assert_eq!(hasher.hash_one(&element), hasher.hash_one(element.clone()));
+    assert_ne!(hasher.hash_one(&element), hasher.hash_one(file("b", 1)));
+    assert_ne!(hasher.hash_one(&element), hasher.hash_one(file("a", 2)));
+
+    // Equal elements are merged by a set, while elements which differ only in their timestamps are both kept
+    let set: HashSet<_> = [element.clone(), modified, element].into_iter().collect();
+    assert_eq!(set.len(), 2);
+}
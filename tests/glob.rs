@@ -0,0 +1,82 @@
+//! Glob patterns expanded below a Cursor's location
+#![cfg(unix)]
+
+use std::{fs, os::unix::fs::symlink};
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, MAX_GLOB_RESULTS}, mapped_fs::MappedFS};
+use tempfile::TempDir;
+
+/// A Browser with `dir` registered, and a Cursor at its virtual path
+fn browser(dir: &TempDir) -> (Browser<MappedFS>, u16) {
+    let mut fs = MappedFS::new();
+    let name = fs.add(dir.path()).unwrap();
+    let mut browser = Browser::new(4, fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, format!("/{}", name.to_string_lossy())).unwrap();
+    (browser, id)
+}
+
+async fn glob_names(browser: &Browser<MappedFS>, id: u16, pattern: &str) -> Vec<String> {
+    let elements = browser.glob(id, pattern).await.unwrap();
+    let mut names: Vec<_> = elements.iter().map(|element| element.name_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn patterns_match_below_the_location() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("crates/core")).unwrap();
+    fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+    fs::write(dir.path().join("crates/core/Cargo.toml"), "").unwrap();
+    fs::write(dir.path().join("crates/core/lib.rs"), "").unwrap();
+    let (browser, id) = browser(&dir);
+
+    assert_eq!(glob_names(&browser, id, "*.toml").await, ["Cargo.toml"]);
+    assert_eq!(glob_names(&browser, id, "crates/*/*.toml").await, ["crates/core/Cargo.toml"]);
+    assert_eq!(glob_names(&browser, id, "**/*.toml").await, ["Cargo.toml", "crates/core/Cargo.toml"]);
+    assert!(matches!(browser.glob(id, "../*").await, Err(CursorError::AccessDenied { .. })));
+    assert!(matches!(browser.glob(id, "[").await, Err(CursorError::InvalidPattern { .. })));
+}
+
+#[tokio::test]
+async fn symbolic_links_are_neither_matched_nor_entered() {
+    let outside = TempDir::new().unwrap();
+    fs::write(outside.path().join("secret.toml"), "").unwrap();
+
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("a")).unwrap();
+    fs::write(dir.path().join("a/real.toml"), "").unwrap();
+    // Links back up the tree would make a walk which follows them grow exponentially
+    symlink("..", dir.path().join("a/up")).unwrap();
+    symlink(".", dir.path().join("a/here")).unwrap();
+    symlink(outside.path(), dir.path().join("outside")).unwrap();
+    symlink(dir.path().join("a/real.toml"), dir.path().join("link.toml")).unwrap();
+    let (browser, id) = browser(&dir);
+
+    assert_eq!(glob_names(&browser, id, "**/*.toml").await, ["a/real.toml"]);
+}
+
+#[tokio::test]
+async fn the_search_stops_at_the_maximum_path_depth() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+    fs::write(dir.path().join("a/shallow.toml"), "").unwrap();
+    fs::write(dir.path().join("a/b/c/deep.toml"), "").unwrap();
+    let (mut browser, id) = browser(&dir);
+
+    browser.set_max_path_depth(2);
+    assert_eq!(glob_names(&browser, id, "**/*.toml").await, ["a/shallow.toml"]);
+}
+
+#[tokio::test]
+async fn too_many_matches_are_refused() {
+    let dir = TempDir::new().unwrap();
+    for i in 0..=MAX_GLOB_RESULTS {
+        fs::write(dir.path().join(format!("{i}.txt")), "").unwrap();
+    }
+    let (browser, id) = browser(&dir);
+
+    assert!(matches!(browser.glob(id, "*.txt").await, Err(CursorError::TooManyResults { limit: MAX_GLOB_RESULTS, .. })));
+    assert_eq!(glob_names(&browser, id, "1?.txt").await.len(), 10);
+}
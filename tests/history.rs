@@ -0,0 +1,95 @@
+//! Moving a Cursor back and forward through the locations it has visited
+
+use std::path::PathBuf;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, mem_fs::MemFS, FS};
+
+async fn navigate(browser: &mut Browser<MemFS>, id: u16, path: &str) {
+    match browser.process(Request::Navigate { id, path: path.into() }).await {
+        Response::Navigate(Ok(())) => {}
+        _ => panic!("The navigation to {path} failed"),
+    }
+}
+
+async fn back(browser: &mut Browser<MemFS>, id: u16) -> Result<PathBuf, CursorError> {
+    match browser.process(Request::Back { id }).await {
+        Response::Back(result) => result,
+        _ => panic!("Unexpected response type"),
+    }
+}
+
+async fn forward(browser: &mut Browser<MemFS>, id: u16) -> Result<PathBuf, CursorError> {
+    match browser.process(Request::Forward { id }).await {
+        Response::Forward(result) => result,
+        _ => panic!("Unexpected response type"),
+    }
+}
+
+fn sample() -> MemFS {
+    MemFS::builder().add_dir("a/b").add_dir("c").build()
+}
+
+#[tokio::test]
+async fn back_and_forward_retrace_the_visited_locations() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    navigate(&mut browser, id, "/a").await;
+    navigate(&mut browser, id, "/a/b").await;
+
+    assert_eq!(back(&mut browser, id).await.unwrap(), PathBuf::from("/a"));
+    assert_eq!(back(&mut browser, id).await.unwrap(), PathBuf::new());
+    assert!(matches!(back(&mut browser, id).await, Err(CursorError::NoPreviousLocation { .. })));
+
+    assert_eq!(forward(&mut browser, id).await.unwrap(), PathBuf::from("/a"));
+    assert_eq!(forward(&mut browser, id).await.unwrap(), PathBuf::from("/a/b"));
+    assert!(matches!(forward(&mut browser, id).await, Err(CursorError::NoNextLocation { .. })));
+    assert_eq!(browser.get_location_cursor(id).unwrap(), PathBuf::from("/a/b"));
+}
+
+#[tokio::test]
+async fn navigating_discards_the_forward_locations() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    navigate(&mut browser, id, "/a").await;
+    navigate(&mut browser, id, "/a/b").await;
+    back(&mut browser, id).await.unwrap();
+
+    navigate(&mut browser, id, "/c").await;
+    assert!(matches!(forward(&mut browser, id).await, Err(CursorError::NoNextLocation { .. })));
+    assert_eq!(back(&mut browser, id).await.unwrap(), PathBuf::from("/a"));
+}
+
+#[tokio::test]
+async fn navigating_to_the_same_location_is_not_remembered() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    navigate(&mut browser, id, "/a").await;
+    navigate(&mut browser, id, "/a").await;
+
+    assert_eq!(back(&mut browser, id).await.unwrap(), PathBuf::new());
+    assert!(back(&mut browser, id).await.is_err());
+}
+
+#[tokio::test]
+async fn moving_through_the_history_reads_afresh() {
+    let fs = sample();
+    let mut browser = Browser::new(4, fs.clone());
+    let id = browser.create_cursor().unwrap();
+    navigate(&mut browser, id, "/c").await;
+    browser.process(Request::Read { id }).await;
+    navigate(&mut browser, id, "/a").await;
+
+    FS::write(&fs, "c/new.txt", b"").await.unwrap();
+    back(&mut browser, id).await.unwrap();
+    match browser.process(Request::Read { id }).await {
+        Response::Read(Ok((_, elements))) => assert_eq!(elements.len(), 1),
+        _ => panic!("The read failed"),
+    }
+}
+
+#[tokio::test]
+async fn unknown_cursors_have_no_history() {
+    let mut browser = Browser::new(4, sample());
+    assert!(matches!(back(&mut browser, 1).await, Err(CursorError::UnknownCursor { .. })));
+    assert!(matches!(forward(&mut browser, 1).await, Err(CursorError::UnknownCursor { .. })));
+}
@@ -0,0 +1,55 @@
+//! Destroying Cursors which have not been used for a while
+
+mod common;
+
+use std::{sync::{atomic::AtomicU32, Arc}, time::Duration};
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, cursor_quota::CursorQuota, mem_fs::MemFS};
+use tempfile::TempDir;
+
+use common::{describe, TestClient, TestServer};
+
+#[tokio::test]
+async fn only_idle_cursors_are_destroyed() {
+    let quota = CursorQuota::new(Arc::new(AtomicU32::new(0)), 8);
+    let mut browser = Browser::new(8, MemFS::new());
+    browser.set_cursor_quota(quota.clone());
+    let idle = browser.create_cursor().unwrap();
+    let used = browser.create_cursor().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(matches!(browser.process(Request::GetLocation { id: used }).await, Response::GetLocation(Ok(_))));
+
+    assert_eq!(browser.gc_idle_cursors(Duration::from_millis(40)), 1);
+    assert!(matches!(browser.get_location_cursor(idle), Err(CursorError::UnknownCursor { .. })));
+    assert!(browser.get_location_cursor(used).is_ok());
+    assert_eq!(quota.used(), 1);
+
+    assert_eq!(browser.gc_idle_cursors(Duration::from_secs(60)), 0);
+}
+
+#[test]
+fn requests_name_their_cursor() {
+    assert_eq!(Request::Read { id: 7 }.cursor_id(), Some(7));
+    assert_eq!(Request::Search { id: 3, pattern: "*".to_owned(), case_insensitive: false }.cursor_id(), Some(3));
+    assert_eq!(Request::Create.cursor_id(), None);
+    assert_eq!(Request::NextChunk { transfer_id: 1 }.cursor_id(), None);
+}
+
+#[tokio::test]
+async fn the_server_destroys_idle_cursors() {
+    let config_dir = TempDir::new().unwrap();
+    let config_path = config_dir.path().join("server.toml");
+    std::fs::write(&config_path, "idle_cursor_timeout_secs = 1").unwrap();
+
+    let server = TestServer::start_with_args(&["--config", config_path.to_str().unwrap()]);
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+
+    // Any request naming the Cursor would keep it alive, so wait for the collection without asking
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    match client.request(Request::GetLocation { id }).await {
+        Response::GetLocation(Err(CursorError::UnknownCursor { .. })) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
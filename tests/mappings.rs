@@ -0,0 +1,138 @@
+//! Adding and removing the paths of a MappedFS
+
+mod common;
+
+use std::ffi::OsStr;
+
+use simple_file_transfer_v2::fs::{browser::Response, mapped_fs::{MappedFS, MappedFSError}, FSElement};
+use tempfile::TempDir;
+
+use common::{describe, TestClient, TestServer};
+
+#[test]
+fn paths_can_be_removed_by_virtual_name() {
+    let dir = TempDir::new().unwrap();
+    let mut mapped_fs = MappedFS::new();
+    let name = mapped_fs.add(dir.path()).unwrap();
+
+    assert_eq!(mapped_fs.remove_by_virtual_name(&name), Some(dir.path().to_owned()));
+    assert!(mapped_fs.registered().is_empty());
+    assert_eq!(mapped_fs.remove_by_virtual_name(&name), None);
+}
+
+#[test]
+fn only_the_named_path_is_removed() {
+    // Both directories are named "shared", so the second is registered as "shared (1)"
+    let parents = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+    let paths = parents.each_ref().map(|parent| parent.path().join("shared"));
+    let mut mapped_fs = MappedFS::new();
+    for path in &paths {
+        std::fs::create_dir(path).unwrap();
+        mapped_fs.add(path).unwrap();
+    }
+
+    assert_eq!(mapped_fs.remove_by_virtual_name(OsStr::new("shared (1)")), Some(paths[1].clone()));
+    assert_eq!(mapped_fs.registered(), [paths[0].clone()]);
+    assert_eq!(mapped_fs.remove_by_virtual_name(OsStr::new("missing")), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn canonical_paths_resolve_symbolic_links() {
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("target");
+    std::fs::create_dir(&target).unwrap();
+    std::os::unix::fs::symlink(&target, dir.path().join("link")).unwrap();
+    let mut mapped_fs = MappedFS::new();
+
+    // The element is named after, and maps to, the directory the link points to
+    let name = mapped_fs.add_canonical(dir.path().join("link")).unwrap();
+    assert_eq!(name, "target");
+    let canonical = std::fs::canonicalize(&target).unwrap();
+    assert_eq!(mapped_fs.registered(), std::slice::from_ref(&canonical));
+
+    // Unmapped paths stay valid once the link is gone
+    std::fs::remove_file(dir.path().join("link")).unwrap();
+    assert_eq!(mapped_fs.unmap("/target/file.txt").unwrap(), canonical.join("file.txt"));
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_symbolic_links_cannot_be_added_canonically() {
+    let dir = TempDir::new().unwrap();
+    std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("broken")).unwrap();
+    let mut mapped_fs = MappedFS::new();
+
+    assert!(mapped_fs.add_canonical(dir.path().join("broken")).is_err());
+    assert!(matches!(mapped_fs.add_canonical("relative"), Err(MappedFSError::PathNotAbsolute(_))));
+    assert!(mapped_fs.registered().is_empty());
+}
+
+#[tokio::test]
+async fn listings_are_sorted_by_name_every_time() {
+    let parent = TempDir::new().unwrap();
+    let mut mapped_fs = MappedFS::new();
+    // Prefetched elements are listed from the cache, ahead of the others unless the listing is sorted
+    for name in ["d", "b", "e", "a", "c"] {
+        let path = parent.path().join(name);
+        std::fs::create_dir(&path).unwrap();
+        match name {
+            "b" | "e" => mapped_fs.add_with_prefetch(&path).await.unwrap(),
+            _ => mapped_fs.add(&path).unwrap(),
+        };
+    }
+    for i in (0..50).rev() {
+        std::fs::write(parent.path().join("a").join(format!("{i:02}.txt")), "").unwrap();
+    }
+
+    let names = |elements: Vec<FSElement>| -> Vec<String> {
+        elements.iter().map(|element| element.name_lossy().into_owned()).collect()
+    };
+    let expected: Vec<_> = (0..50).map(|i| format!("{i:02}.txt")).collect();
+    for _ in 0..10 {
+        assert_eq!(names(mapped_fs.list("/").await.unwrap()), ["a", "b", "c", "d", "e"]);
+        assert_eq!(names(mapped_fs.list("/a").await.unwrap()), expected);
+    }
+}
+
+#[tokio::test]
+async fn the_cli_removes_by_virtual_name_or_absolute_path() {
+    let mut server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    let root = format!("/{}", server.virtual_name);
+    let real_path = server.root.path().to_str().unwrap().to_owned();
+
+    server.command("remove");
+    server.command_confirmed(&server.virtual_name.clone(), "Successfully removed");
+    match client.read_at(id, &root).await {
+        Response::Read(Err(_)) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+
+    server.command("add");
+    server.command_confirmed(&real_path, "Successfully added");
+    server.command("remove");
+    server.command_confirmed(&real_path, "Successfully removed the path");
+    match client.read_at(id, &root).await {
+        Response::Read(Err(_)) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_prefixes_are_skipped_before_the_root_element() {
+    let dir = TempDir::new().unwrap();
+    let mut mapped_fs = MappedFS::new();
+    let name = mapped_fs.add(dir.path()).unwrap();
+    let name = name.to_str().unwrap();
+
+    // A drive letter and a UNC share are both a Prefix, which may be followed by a RootDir
+    for path in [format!(r"C:\{name}\sub"), format!(r"C:{name}\sub"), format!(r"\\server\share\{name}\sub")] {
+        assert_eq!(mapped_fs.unmap(&path).unwrap(), dir.path().join("sub"), "{path}");
+    }
+
+    // A path to the root of a drive is the root of the mapped FS, which cannot be unmapped
+    assert!(mapped_fs.unmap(r"C:\").is_err());
+}
@@ -0,0 +1,160 @@
+//! The in-memory file system, on its own and behind a Browser
+
+use std::{ffi::OsString, path::{Path, PathBuf}};
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, mem_fs::{MemFS, MemFSError}, FSElement, FS};
+
+fn names(elements: &[FSElement]) -> Vec<&str> {
+    elements.iter().map(|element| element.name_str().unwrap()).collect()
+}
+
+fn sample() -> MemFS {
+    MemFS::builder()
+        .add_file("readme.txt", "hello")
+        .add_file("docs/guide.md", "# Guide")
+        .add_file("docs/api/index.html", "<html>")
+        .add_dir("empty")
+        .build()
+}
+
+#[tokio::test]
+async fn lists_the_root() {
+    let elements = FS::list(&sample(), "").await.unwrap();
+    assert_eq!(names(&elements), ["docs", "empty", "readme.txt"]);
+    assert!(!elements[0].is_file);
+    assert!(elements[2].is_file);
+    assert_eq!(elements[2].size, 5);
+}
+
+#[tokio::test]
+async fn a_leading_slash_also_means_the_root() {
+    let fs = sample();
+    assert_eq!(FS::list(&fs, "/").await.unwrap(), FS::list(&fs, "").await.unwrap());
+    assert_eq!(FS::list(&fs, "/docs/./api").await.unwrap(), FS::list(&fs, "docs/api").await.unwrap());
+}
+
+#[tokio::test]
+async fn lists_nested_directories() {
+    let fs = sample();
+    assert_eq!(names(&FS::list(&fs, "docs").await.unwrap()), ["api", "guide.md"]);
+    assert_eq!(names(&FS::list(&fs, "docs/api").await.unwrap()), ["index.html"]);
+}
+
+#[tokio::test]
+async fn builder_creates_missing_parents() {
+    let fs = MemFS::builder().add_file("a/b/c/d.txt", "").build();
+    assert!(!FS::metadata(&fs, "a").await.unwrap().is_file);
+    assert!(!FS::metadata(&fs, "a/b/c").await.unwrap().is_file);
+}
+
+#[tokio::test]
+async fn lists_an_empty_directory() {
+    assert!(FS::list(&sample(), "empty").await.unwrap().is_empty());
+    assert!(FS::list(&MemFS::new(), "").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn listing_a_file_fails() {
+    let err = FS::list(&sample(), "readme.txt").await.unwrap_err();
+    assert!(matches!(err, MemFSError::NotADirectory(path, _) if path == Path::new("readme.txt")));
+}
+
+#[tokio::test]
+async fn listing_a_missing_path_fails() {
+    assert!(matches!(FS::list(&sample(), "missing").await, Err(MemFSError::NotFound(..))));
+    assert!(matches!(FS::list(&sample(), "docs/missing").await, Err(MemFSError::NotFound(..))));
+}
+
+#[tokio::test]
+async fn parent_components_cannot_leave_the_root() {
+    assert!(matches!(FS::list(&sample(), "..").await, Err(MemFSError::NotFound(..))));
+    assert!(matches!(FS::metadata(&sample(), "docs/../readme.txt").await, Err(MemFSError::NotFound(..))));
+}
+
+#[tokio::test]
+async fn keeps_non_ascii_names() {
+    let fs = MemFS::builder()
+        .add_file("données/été.txt", "soleil")
+        .add_file("données/日本語.md", "")
+        .add_dir("🦀")
+        .build();
+
+    assert_eq!(names(&FS::list(&fs, "").await.unwrap()), ["données", "🦀"]);
+    let elements = FS::list(&fs, "données").await.unwrap();
+    assert_eq!(names(&elements), ["été.txt", "日本語.md"]);
+    assert_eq!(elements[0].name, OsString::from("été.txt"));
+    assert_eq!(fs.read("données/été.txt").unwrap(), b"soleil");
+}
+
+#[tokio::test]
+async fn metadata_of_the_root_and_of_files() {
+    let fs = sample();
+    let root = FS::metadata(&fs, "").await.unwrap();
+    assert!(root.name.is_empty() && !root.is_file);
+
+    let guide = FS::metadata(&fs, "docs/guide.md").await.unwrap();
+    assert_eq!(guide.name_str(), Some("guide.md"));
+    assert_eq!(guide.size, 7);
+    assert!(matches!(FS::metadata(&fs, "docs/missing").await, Err(MemFSError::NotFound(..))));
+}
+
+#[tokio::test]
+async fn writes_replace_and_create_files() {
+    let fs = sample();
+    FS::write(&fs, "readme.txt", b"changed").await.unwrap();
+    FS::write(&fs, "docs/new.txt", b"new").await.unwrap();
+
+    assert_eq!(fs.read("readme.txt").unwrap(), b"changed");
+    assert_eq!(fs.read("docs/new.txt").unwrap(), b"new");
+    assert_eq!(names(&FS::list(&fs, "docs").await.unwrap()), ["api", "guide.md", "new.txt"]);
+}
+
+#[tokio::test]
+async fn writes_need_a_parent_directory() {
+    let fs = sample();
+    assert!(matches!(FS::write(&fs, "missing/file.txt", b"").await, Err(MemFSError::NotFound(..))));
+    assert!(matches!(FS::write(&fs, "readme.txt/file.txt", b"").await, Err(MemFSError::NotADirectory(..))));
+    assert!(matches!(FS::write(&fs, "docs", b"").await, Err(MemFSError::IsADirectory(..))));
+    assert!(matches!(FS::write(&fs, "", b"").await, Err(MemFSError::IsADirectory(..))));
+}
+
+#[tokio::test]
+async fn clones_share_their_contents() {
+    let fs = MemFS::new();
+    let clone = fs.clone();
+    tokio::spawn(async move { FS::write(&clone, "shared.txt", b"from a task").await.unwrap() }).await.unwrap();
+    assert_eq!(fs.read("shared.txt").unwrap(), b"from a task");
+}
+
+#[tokio::test]
+async fn lists_recursively() {
+    let paths: Vec<_> = FS::list_recursive(&sample(), "", None).await.unwrap().into_iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, ["docs", "docs/api", "docs/api/index.html", "docs/guide.md", "empty", "readme.txt"].map(PathBuf::from));
+}
+
+#[test]
+#[should_panic(expected = "Could not add the file")]
+fn builder_rejects_files_below_files() {
+    MemFS::builder().add_file("a.txt", "").add_file("a.txt/b.txt", "");
+}
+
+#[tokio::test]
+async fn a_browser_reads_and_navigates_it() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+
+    match browser.process(Request::Navigate { id, path: "/docs".into() }).await {
+        Response::Navigate(Ok(())) => {}
+        _ => panic!("The navigation failed"),
+    }
+    match browser.process(Request::Read { id }).await {
+        Response::Read(Ok((_, elements))) => assert_eq!(names(&elements), ["api", "guide.md"]),
+        _ => panic!("The read failed"),
+    }
+
+    browser.process(Request::Navigate { id, path: "/missing".into() }).await;
+    match browser.process(Request::Read { id }).await {
+        Response::Read(Err(CursorError::NotFound { .. })) => {}
+        _ => panic!("A missing directory was not reported as missing"),
+    }
+}
@@ -0,0 +1,38 @@
+//! Refusing to move Cursors to paths with too many components
+
+use std::path::PathBuf;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError}, mem_fs::MemFS};
+
+/// A relative path made of `depth` components
+fn path_of_depth(depth: usize) -> PathBuf {
+    vec!["d"; depth].iter().collect()
+}
+
+#[test]
+fn paths_deeper_than_the_limit_are_refused() {
+    let mut browser = Browser::new(4, MemFS::default());
+    let id = browser.create_cursor().unwrap();
+
+    assert!(browser.move_cursor(id, path_of_depth(64)).is_ok());
+    assert!(matches!(
+        browser.move_cursor(id, path_of_depth(65)),
+        Err(CursorError::PathTooDeep { depth: 65, max: 64, .. })
+    ));
+}
+
+#[test]
+fn the_limit_can_be_changed() {
+    let mut browser = Browser::new(4, MemFS::default());
+    browser.set_max_path_depth(3);
+    let id = browser.create_cursor().unwrap();
+
+    assert!(browser.move_cursor(id, path_of_depth(3)).is_ok());
+    assert!(matches!(
+        browser.move_cursor(id, path_of_depth(4)),
+        Err(CursorError::PathTooDeep { depth: 4, max: 3, .. })
+    ));
+
+    browser.set_max_path_depth(100);
+    assert!(browser.move_cursor(id, path_of_depth(65)).is_ok());
+}
@@ -0,0 +1,58 @@
+//! Unix permission bits and ownership in FSElement
+
+use std::ffi::OsString;
+
+use serde::{Deserialize, Serialize};
+use simple_file_transfer_v2::fs::{mapped_fs::MappedFS, FSElement};
+use tempfile::TempDir;
+use time::OffsetDateTime;
+
+/// FSElement as it was before the ownership fields were added
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct OldFSElement {
+    name: OsString,
+    created: Option<OffsetDateTime>,
+    modified: Option<OffsetDateTime>,
+    size: u64,
+    is_file: bool,
+    is_symlink: bool,
+    hash: Option<Vec<u8>>
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn mapped_elements_carry_permissions_and_ownership() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("file.txt");
+    std::fs::write(&path, b"contents").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let expected = std::fs::metadata(&path).unwrap();
+
+    let mut mapped_fs = MappedFS::new();
+    let name = mapped_fs.add(dir.path()).unwrap();
+    let element = mapped_fs.metadata(format!("/{}/file.txt", name.to_string_lossy())).await.unwrap();
+
+    assert_eq!(element.mode.map(|mode| mode & 0o7777), Some(0o640));
+    assert_eq!(element.uid, Some(expected.uid()));
+    assert_eq!(element.gid, Some(expected.gid()));
+    assert_eq!(element.inode, Some(expected.ino()));
+}
+
+#[test]
+fn elements_without_ownership_deserialize() {
+    let old = OldFSElement {
+        name: "file.txt".into(),
+        created: None,
+        modified: None,
+        size: 3,
+        is_file: true,
+        is_symlink: false,
+        hash: None
+    };
+
+    let element: FSElement = rmp_serde::from_slice(&rmp_serde::to_vec(&old).unwrap()).unwrap();
+    assert_eq!(element.name, "file.txt");
+    assert_eq!((element.mode, element.uid, element.gid, element.inode), (None, None, None, None));
+}
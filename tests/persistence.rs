@@ -0,0 +1,53 @@
+//! Saving the registered paths to a file and loading them again. Run with `--features json`
+#![cfg(feature = "json")]
+
+use simple_file_transfer_v2::fs::mapped_fs::MappedFS;
+use tempfile::TempDir;
+
+#[test]
+fn saved_mappings_are_loaded_with_their_names_and_access() {
+    let dir = TempDir::new().unwrap();
+    let (kept, readonly, deleted) = (dir.path().join("kept"), dir.path().join("readonly"), dir.path().join("deleted"));
+    for path in [&kept, &readonly, &deleted] {
+        std::fs::create_dir(path).unwrap();
+    }
+
+    let mut mapped_fs = MappedFS::new();
+    mapped_fs.add_with_alias(&kept, "alias").unwrap();
+    mapped_fs.add_readonly(&readonly).unwrap();
+    mapped_fs.add(&deleted).unwrap();
+
+    let file = dir.path().join("mappings.json");
+    mapped_fs.save(&file).unwrap();
+    // Only the snapshot is left next to the registered paths, without its temporary file
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 4);
+
+    // A path which disappeared since the save is skipped rather than failing the load
+    std::fs::remove_dir(&deleted).unwrap();
+    let loaded = MappedFS::load(&file).unwrap();
+    assert_eq!(loaded.registered_with_names(), [("alias".into(), kept.clone()), ("readonly".into(), readonly)]);
+    assert!(loaded.is_writable("/alias/file"));
+    assert!(!loaded.is_writable("/readonly/file"));
+
+    // Names which are already used by a different path are skipped when loading into a populated FS
+    let mut populated = MappedFS::new();
+    populated.add_with_alias(dir.path(), "alias").unwrap();
+    assert_eq!(populated.extend_from_file(&file).unwrap(), 1);
+    assert_eq!(populated.unmap("/alias").unwrap(), dir.path());
+}
+
+#[test]
+fn loading_a_missing_file_fails() {
+    let dir = TempDir::new().unwrap();
+    assert!(MappedFS::load(dir.path().join("missing.json")).is_err());
+}
+
+#[test]
+fn failed_saves_leave_no_temporary_file_behind() {
+    let dir = TempDir::new().unwrap();
+    // A file cannot be renamed over a directory which is not empty
+    std::fs::create_dir_all(dir.path().join("mappings.json/inner")).unwrap();
+
+    assert!(MappedFS::new().save(dir.path().join("mappings.json")).is_err());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
@@ -0,0 +1,47 @@
+//! The global rate limit shared by every connection
+
+mod common;
+
+use std::{process::{Command, Stdio}, time::Duration};
+
+use simple_file_transfer_v2::fs::browser::{Request, Response};
+
+use common::{describe, TestClient, TestServer};
+
+#[test]
+fn a_limit_of_zero_is_refused() {
+    for args in [["--global-rps", "0"].as_slice(), &["--global-rps", "1", "--global-burst", "0"]] {
+        let status = Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success(), "{args:?}");
+    }
+}
+
+#[tokio::test]
+async fn requests_waiting_for_the_limit_do_not_hold_up_shutdown() {
+    let mut server = TestServer::start_with_args(&["--global-rps", "1", "--global-burst", "1"]);
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+
+    // One request is let through each second, so most of these are still waiting when the server stops
+    for _ in 0..10 {
+        client.send(Request::Read { id }).await;
+    }
+    // Give the server time to start waiting for the limit
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    server.command("exit");
+
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), client.receive()).await.unwrap().unwrap() {
+            Response::Read(_) => continue,
+            Response::Shutdown { .. } => break,
+            response => panic!("Unexpected response: {}", describe(&response)),
+        }
+    }
+    assert!(client.receive().await.is_err());
+}
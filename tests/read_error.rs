@@ -0,0 +1,52 @@
+//! The cause of a failed read reaches the client, even though only its message can be sent
+
+use std::{error::Error, io, path::{Path, PathBuf}};
+
+use async_trait::async_trait;
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, FSElement, Unsupported, FS};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum FailingFSError {
+    #[error("The listing failed")]
+    Listing(#[source] io::Error),
+
+    #[error(transparent)]
+    Unsupported(#[from] Unsupported)
+}
+
+/// A file system whose listings always fail with a timeout caused by a failing disk
+struct FailingFS;
+
+#[async_trait]
+impl FS for FailingFS {
+    type Error = FailingFSError;
+
+    async fn list<P: AsRef<Path> + Send + Sync>(&self, _path: P) -> Result<Vec<FSElement>, FailingFSError> {
+        Err(FailingFSError::Listing(io::Error::new(io::ErrorKind::TimedOut, "the disk stopped responding")))
+    }
+
+    async fn metadata<P: AsRef<Path> + Send + Sync>(&self, _path: P) -> Result<FSElement, FailingFSError> {
+        Err(FailingFSError::Listing(io::Error::new(io::ErrorKind::TimedOut, "the disk stopped responding")))
+    }
+
+    fn unmap<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, FailingFSError> {
+        Ok(path.as_ref().to_owned())
+    }
+}
+
+#[tokio::test]
+async fn read_errors_keep_their_cause_over_the_wire() {
+    let mut browser = Browser::new(4, FailingFS);
+    let id = browser.create_cursor().unwrap();
+
+    let response = browser.process(Request::Read { id }).await;
+    let encoded = rmp_serde::to_vec(&response).unwrap();
+    let err = match rmp_serde::from_slice::<Response>(&encoded).unwrap() {
+        Response::Read(Err(err)) => err,
+        _ => panic!("The read did not fail"),
+    };
+
+    assert!(matches!(&err, CursorError::ReadError { kind, .. } if kind == "TimedOut"));
+    assert_eq!(err.source().unwrap().to_string(), "The listing failed: the disk stopped responding");
+}
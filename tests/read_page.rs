@@ -0,0 +1,40 @@
+//! Paginated reads, which are cut from the same listing a full Read returns
+
+mod common;
+
+use simple_file_transfer_v2::fs::{browser::{Request, Response}, FSElement};
+
+use common::{describe, TestClient, TestServer};
+
+async fn read_page(client: &mut TestClient, id: u16, offset: u32, count: u32) -> (Vec<FSElement>, u32) {
+    match client.request(Request::ReadPage { id, offset, count }).await {
+        Response::ReadPage(Ok(page)) => page,
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn pages_add_up_to_a_full_read() {
+    let server = TestServer::start();
+    for i in 0..20 {
+        std::fs::write(server.root.path().join(format!("file{i:02}.txt")), i.to_string()).unwrap();
+    }
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    let all = match client.read_at(id, &format!("/{}", server.virtual_name)).await {
+        Response::Read(Ok((_, elements))) => elements.into_owned(),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    };
+    assert_eq!(all.len(), 21);
+
+    let (first, total) = read_page(&mut client, id, 0, 8).await;
+    assert_eq!((first.len(), total), (8, 21));
+    let (rest, total) = read_page(&mut client, id, 8, 100).await;
+    assert_eq!((rest.len(), total), (13, 21));
+    assert_eq!([first, rest].concat(), all);
+
+    // A page past the end is empty rather than an error
+    assert_eq!(read_page(&mut client, id, 21, 5).await, (vec![], 21));
+    assert_eq!(read_page(&mut client, id, u32::MAX, u32::MAX).await, (vec![], 21));
+}
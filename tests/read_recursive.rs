@@ -0,0 +1,61 @@
+//! Recursive reads through the real server, which lists subdirectories concurrently
+
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use futures::StreamExt;
+use simple_file_transfer_v2::fs::{browser::{Browser, Request, Response}, mem_fs::MemFS};
+
+use common::{describe, TestClient, TestServer};
+
+async fn read_recursive(client: &mut TestClient, id: u16, max_depth: Option<u32>) -> Vec<PathBuf> {
+    match client.request(Request::ReadRecursive { id, max_depth }).await {
+        Response::ReadRecursive(Ok(elements)) => elements.into_iter().map(|(path, _)| path).collect(),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn read_recursive_honours_the_depth_limit() {
+    let server = TestServer::start();
+    fs::create_dir_all(server.root.path().join("a/b/c")).unwrap();
+    fs::create_dir_all(server.root.path().join("d")).unwrap();
+    fs::write(server.root.path().join("a/b/c/deep.txt"), "deep").unwrap();
+    fs::write(server.root.path().join("d/shallow.txt"), "shallow").unwrap();
+    fs::create_dir_all(server.root.path().join(".hidden")).unwrap();
+    fs::write(server.root.path().join(".hidden/secret.txt"), "secret").unwrap();
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    let root = PathBuf::from(format!("/{}", server.virtual_name));
+    client.read_at(id, root.to_str().unwrap()).await;
+
+    let paths = |relative: &[&str]| -> Vec<PathBuf> { relative.iter().map(|path| root.join(path)).collect() };
+
+    // Some(0) lists what a plain Read would
+    assert_eq!(read_recursive(&mut client, id, Some(0)).await, paths(&[".hidden", "a", "d", "inside.txt"]));
+
+    // Once hidden elements are not shown, hidden directories are left out along with their contents
+    assert!(matches!(client.request(Request::SetShowHidden { show: false }).await, Response::SetShowHidden(Ok(()))));
+    assert_eq!(read_recursive(&mut client, id, Some(0)).await, paths(&["a", "d", "inside.txt"]));
+    assert_eq!(
+        read_recursive(&mut client, id, Some(1)).await,
+        paths(&["a", "a/b", "d", "d/shallow.txt", "inside.txt"])
+    );
+    assert_eq!(
+        read_recursive(&mut client, id, None).await,
+        paths(&["a", "a/b", "a/b/c", "a/b/c/deep.txt", "d", "d/shallow.txt", "inside.txt"])
+    );
+}
+
+#[tokio::test]
+async fn depth_first_walks_stop_at_the_maximum_path_depth() {
+    let fs = MemFS::builder().add_file("a/b/c/d/deep.txt", "deep").add_file("e.txt", "").build();
+    let mut browser = Browser::new(4, fs);
+    browser.set_max_path_depth(2);
+    let id = browser.create_cursor().unwrap();
+
+    let paths: Vec<_> = browser.depth_first_walk(id).map(|result| result.unwrap().0).collect().await;
+    assert_eq!(paths, ["a", "a/b", "a/b/c", "e.txt"].map(PathBuf::from));
+}
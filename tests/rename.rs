@@ -0,0 +1,161 @@
+//! Renaming elements through a Browser
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use simple_file_transfer_v2::fs::{
+    browser::{Browser, CursorError, Request, Response},
+    mapped_fs::MappedFS,
+    mem_fs::{MemFS, MemFSError},
+    FS
+};
+use tempfile::TempDir;
+
+use common::{describe, TestClient, TestServer};
+
+fn names(response: Response) -> Vec<String> {
+    match response {
+        Response::Read(Ok((_, elements))) => elements.iter().map(|element| element.name_lossy().into_owned()).collect(),
+        _ => panic!("The read failed"),
+    }
+}
+
+async fn rename(browser: &mut Browser<MemFS>, id: u16, path: &str, new_name: &str) -> Result<(), CursorError> {
+    match browser.process(Request::Rename { id, path: path.into(), new_name: new_name.into() }).await {
+        Response::Rename(result) => result,
+        _ => panic!("Unexpected response type"),
+    }
+}
+
+#[tokio::test]
+async fn renaming_refreshes_cached_listings() {
+    let fs = MemFS::builder().add_file("docs/old.txt", "").add_dir("archive").build();
+    let mut browser = Browser::new(4, fs);
+    // Without a modification time to compare, only the rename itself can mark the listings as stale
+    browser.set_cache_ttl(None);
+    let docs = browser.create_cursor().unwrap();
+    let archive = browser.create_cursor().unwrap();
+    browser.move_cursor(docs, "/docs").unwrap();
+    browser.move_cursor(archive, "/archive").unwrap();
+    assert_eq!(names(browser.process(Request::Read { id: docs }).await), ["old.txt"]);
+    assert!(names(browser.process(Request::Read { id: archive }).await).is_empty());
+
+    rename(&mut browser, docs, "old.txt", "new.txt").await.unwrap();
+    assert_eq!(names(browser.process(Request::Read { id: docs }).await), ["new.txt"]);
+    assert!(names(browser.process(Request::Read { id: archive }).await).is_empty());
+}
+
+#[tokio::test]
+async fn cursors_inside_a_renamed_directory_see_it_gone() {
+    let fs = MemFS::builder().add_file("docs/guide.md", "").build();
+    let mut browser = Browser::new(4, fs);
+    browser.set_cache_ttl(None);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/docs").unwrap();
+    browser.process(Request::Read { id }).await;
+
+    let other = browser.create_cursor().unwrap();
+    rename(&mut browser, other, "/docs", "manuals").await.unwrap();
+    assert!(matches!(browser.process(Request::Read { id }).await, Response::Read(Err(CursorError::NotFound { .. }))));
+}
+
+#[tokio::test]
+async fn failed_renames_carry_the_cause() {
+    let mut browser = Browser::new(4, MemFS::builder().add_dir("docs").build());
+    let id = browser.create_cursor().unwrap();
+
+    let err = rename(&mut browser, id, "missing.txt", "other.txt").await.unwrap_err();
+    assert!(matches!(&err, CursorError::RenameError { from, .. } if from == Path::new("missing.txt")));
+    assert!(std::error::Error::source(&err).unwrap().to_string().contains("does not exist"), "{err}");
+}
+
+#[tokio::test]
+async fn names_which_are_not_a_single_component_are_refused() {
+    let mut browser = Browser::new(4, MemFS::builder().add_file("docs/old.txt", "old").add_dir("archive").build());
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/docs").unwrap();
+
+    for new_name in ["", ".", "..", "../archive/old.txt", "sub/new.txt", "new.txt/"] {
+        let err = rename(&mut browser, id, "old.txt", new_name).await.unwrap_err();
+        assert!(matches!(&err, CursorError::InvalidName { name, .. } if name == new_name), "{new_name:?}: {err}");
+    }
+    assert_eq!(names(browser.process(Request::Read { id }).await), ["old.txt"]);
+}
+
+#[tokio::test]
+async fn existing_elements_are_never_replaced() {
+    let fs = MemFS::builder().add_file("docs/old.txt", "old").add_file("docs/new.txt", "new").add_dir("docs/sub").build();
+    let mut browser = Browser::new(4, fs.clone());
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/docs").unwrap();
+
+    for new_name in ["new.txt", "sub"] {
+        let err = rename(&mut browser, id, "old.txt", new_name).await.unwrap_err();
+        assert!(matches!(&err, CursorError::AlreadyExists { path, .. } if *path == Path::new("/docs").join(new_name)), "{err}");
+    }
+    assert_eq!(fs.read("docs/old.txt").unwrap(), b"old");
+    assert_eq!(fs.read("docs/new.txt").unwrap(), b"new");
+}
+
+#[tokio::test]
+async fn renames_which_must_not_replace_refuse_taken_names() {
+    let fs = MemFS::builder().add_file("old.txt", "old").add_file("new.txt", "new").add_dir("sub").build();
+    for to in ["new.txt", "sub"] {
+        assert!(matches!(fs.rename_no_replace("old.txt", to), Err(MemFSError::AlreadyExists(..))), "{to}");
+    }
+    fs.rename_no_replace("old.txt", "free.txt").unwrap();
+    assert_eq!(fs.read("free.txt").unwrap(), b"old");
+
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("old.txt"), "old").unwrap();
+    std::fs::write(dir.path().join("new.txt"), "new").unwrap();
+    std::fs::create_dir_all(dir.path().join("docs/inner")).unwrap();
+    std::fs::create_dir(dir.path().join("empty")).unwrap();
+    let mut mapped_fs = MappedFS::new();
+    let root = Path::new("/").join(mapped_fs.add(dir.path()).unwrap());
+
+    // An empty directory is taken as well, although a plain rename of a directory would replace it
+    for (from, to) in [("old.txt", "new.txt"), ("old.txt", "empty"), ("docs", "empty"), ("docs", "new.txt")] {
+        let err = mapped_fs.rename_no_replace(root.join(from), root.join(to)).await.unwrap_err();
+        let io_err = std::error::Error::source(&err).and_then(|source| source.downcast_ref::<std::io::Error>()).unwrap();
+        assert_eq!(io_err.kind(), std::io::ErrorKind::AlreadyExists, "{from} -> {to}");
+    }
+    assert_eq!(std::fs::read_to_string(dir.path().join("new.txt")).unwrap(), "new");
+    assert!(dir.path().join("docs/inner").is_dir());
+
+    mapped_fs.rename_no_replace(root.join("old.txt"), root.join("free.txt")).await.unwrap();
+    mapped_fs.rename_no_replace(root.join("docs"), root.join("moved")).await.unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("free.txt")).unwrap(), "old");
+    assert!(!dir.path().join("old.txt").exists());
+    assert!(dir.path().join("moved/inner").is_dir());
+    assert!(!dir.path().join("docs").exists());
+}
+
+#[tokio::test]
+async fn memory_renames_move_whole_directories() {
+    let fs = MemFS::builder().add_file("a/b/c.txt", "c").add_dir("d").build();
+    fs.rename("a", "d/a").unwrap();
+
+    assert_eq!(fs.read("d/a/b/c.txt").unwrap(), b"c");
+    assert!(matches!(FS::list(&fs, "a").await, Err(MemFSError::NotFound(..))));
+    assert!(matches!(fs.rename("d", "d/a/e"), Err(MemFSError::MoveIntoItself(..))));
+}
+
+#[tokio::test]
+async fn registered_paths_cannot_be_renamed() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    let from = PathBuf::from(format!("/{}", server.virtual_name));
+
+    match client.request(Request::Rename { id, path: from.clone(), new_name: "moved".into() }).await {
+        Response::Rename(Err(CursorError::RenameError { .. })) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+    match client.request(Request::Rename { id, path: from.join("inside.txt"), new_name: "renamed.txt".into() }).await {
+        Response::Rename(Ok(())) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+    assert!(server.root.path().join("renamed.txt").exists());
+}
@@ -0,0 +1,35 @@
+//! Security regression tests: a client must never be able to read outside of the paths registered with the
+//! server. These start the real server binary and talk to it over TCP, so they cover the whole request path
+
+mod common;
+
+use simple_file_transfer_v2::fs::browser::{CursorError, Response};
+
+use common::{describe, TestClient, TestServer};
+
+/// Assert that reading `path` is refused rather than listing a directory outside of the registered paths
+async fn assert_escape_refused(client: &mut TestClient, id: u16, path: &str) {
+    match client.read_at(id, path).await {
+        Response::Read(Err(CursorError::AccessDenied { .. } | CursorError::NotFound { .. })) => (),
+        response => panic!("Reading {path} escaped the registered paths: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn parent_components_cannot_escape_the_registered_paths() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+
+    // The registered directory itself is readable, so a refusal below is not just a broken setup
+    match client.read_at(id, &format!("/{}", server.virtual_name)).await {
+        Response::Read(Ok((_, elements))) => assert!(elements.iter().any(|element| element.name == "inside.txt")),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+
+    assert_escape_refused(&mut client, id, "/../../../etc").await;
+    assert_escape_refused(&mut client, id, "/../../../../../../../../etc").await;
+    assert_escape_refused(&mut client, id, &format!("/{}/../../../etc", server.virtual_name)).await;
+    assert_escape_refused(&mut client, id, &format!("/{}/./../../etc", server.virtual_name)).await;
+    assert_escape_refused(&mut client, id, "/etc").await;
+}
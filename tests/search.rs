@@ -0,0 +1,85 @@
+//! Searching the names of the elements at and below a Cursor's location
+
+use std::path::PathBuf;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, mem_fs::MemFS, FSElement};
+
+fn sample() -> MemFS {
+    MemFS::builder()
+        .add_file("main.rs", "")
+        .add_file("lib.RS", "")
+        .add_file("doc1", "")
+        .add_file("doc22", "")
+        .add_file("apple.txt", "")
+        .add_file("cherry.txt", "")
+        .add_file("logs/server.log", "")
+        .add_file("logs/old/client.log", "")
+        .add_file("logs/old/notes.txt", "")
+        .build()
+}
+
+async fn search(browser: &mut Browser<MemFS>, id: u16, pattern: &str, case_insensitive: bool) -> Result<Vec<FSElement>, CursorError> {
+    match browser.process(Request::Search { id, pattern: pattern.to_owned(), case_insensitive }).await {
+        Response::Search(result) => result,
+        _ => panic!("Unexpected response type"),
+    }
+}
+
+async fn search_names(browser: &mut Browser<MemFS>, id: u16, pattern: &str, case_insensitive: bool) -> Vec<String> {
+    let elements = search(browser, id, pattern, case_insensitive).await.unwrap();
+    elements.iter().map(|element| element.name_lossy().into_owned()).collect()
+}
+
+#[tokio::test]
+async fn glob_patterns_match_names() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+
+    assert_eq!(search_names(&mut browser, id, "*.rs", false).await, ["main.rs"]);
+    assert_eq!(search_names(&mut browser, id, "doc?", false).await, ["doc1"]);
+    assert_eq!(search_names(&mut browser, id, "[abc]*", false).await, ["apple.txt", "cherry.txt"]);
+    assert!(search_names(&mut browser, id, "*.zip", false).await.is_empty());
+}
+
+#[tokio::test]
+async fn case_can_be_ignored() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    assert_eq!(search_names(&mut browser, id, "*.rs", true).await, ["lib.RS", "main.rs"]);
+}
+
+#[tokio::test]
+async fn an_empty_pattern_matches_everything() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    let all = match browser.process(Request::Read { id }).await {
+        Response::Read(Ok((_, elements))) => elements.into_owned(),
+        _ => panic!("The read failed"),
+    };
+    assert_eq!(search(&mut browser, id, "", false).await.unwrap(), all);
+}
+
+#[tokio::test]
+async fn invalid_patterns_are_rejected() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    assert!(matches!(search(&mut browser, id, "[", false).await, Err(CursorError::InvalidPattern { .. })));
+}
+
+#[tokio::test]
+async fn searches_below_the_location() {
+    let mut browser = Browser::new(4, sample());
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/logs").unwrap();
+
+    let paths = |response: Response| match response {
+        Response::SearchRecursive(Ok(elements)) => elements.into_iter().map(|(path, _)| path).collect::<Vec<_>>(),
+        _ => panic!("The search failed"),
+    };
+    let search = |max_depth| Request::SearchRecursive { id, pattern: "*.log".to_owned(), case_insensitive: false, max_depth };
+
+    let all = paths(browser.process(search(None)).await);
+    assert_eq!(all, ["/logs/old/client.log", "/logs/server.log"].map(PathBuf::from));
+    let shallow = paths(browser.process(search(Some(0))).await);
+    assert_eq!(shallow, [PathBuf::from("/logs/server.log")]);
+}
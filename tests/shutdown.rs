@@ -0,0 +1,84 @@
+//! Closing connections cleanly, at the client's request or because the server is stopping
+
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+
+use simple_file_transfer_v2::fs::browser::{Request, Response};
+
+use common::{describe, TestClient, TestServer};
+
+/// Read the next frame, failing the test if the server takes too long to send one
+async fn receive(client: &mut TestClient) -> Result<Response<'static>, anyhow::Error> {
+    tokio::time::timeout(Duration::from_secs(10), client.receive()).await.unwrap()
+}
+
+#[tokio::test]
+async fn goodbye_closes_the_connection() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    client.create_cursor().await;
+
+    match client.request(Request::Goodbye).await {
+        Response::Goodbye => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+    assert!(client.receive().await.is_err());
+
+    // Other clients are unaffected
+    TestClient::connect(server.port).await.create_cursor().await;
+}
+
+#[tokio::test]
+async fn clients_are_told_when_the_server_stops() {
+    let mut server = TestServer::start();
+    let mut clients = vec![TestClient::connect(server.port).await, TestClient::connect(server.port).await];
+    for client in &mut clients {
+        client.create_cursor().await;
+    }
+
+    server.command("exit");
+    for client in &mut clients {
+        let response = tokio::time::timeout(Duration::from_secs(5), client.receive()).await.unwrap().unwrap();
+        match response {
+            Response::Shutdown { reason } => assert_eq!(reason, "The server was stopped from its command line"),
+            response => panic!("Unexpected response: {}", describe(&response)),
+        }
+        assert!(client.receive().await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn requests_in_progress_are_answered_before_closing() {
+    // With a single file handle, a download waits for as long as another client holds the handle open
+    let mut server = TestServer::start_with_args(&["--max-open-files", "1", "--open-file-timeout", "30"]);
+    let root = PathBuf::from(format!("/{}", server.virtual_name));
+
+    let mut holder = TestClient::connect(server.port).await;
+    let id = holder.create_cursor().await;
+    match holder.request(Request::StartStream { id, path: root.join("inside.txt"), chunk_size: 0 }).await {
+        Response::StartStream(Ok(_)) => {}
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.send(Request::Download { id, path: root.join("inside.txt") }).await;
+    // Give the server time to start on the download, which then waits for the handle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Closing the holder's connection releases the handle, so the download can finish after the shutdown began
+    server.command("exit");
+    let download_id = match receive(&mut client).await.unwrap() {
+        Response::Download(Ok(download_id)) => download_id,
+        response => panic!("Unexpected response: {}", describe(&response)),
+    };
+    match receive(&mut client).await.unwrap() {
+        Response::DownloadChunk { download_id: chunk_id, data, bytes_remaining: 0 } if chunk_id == download_id => {
+            assert_eq!(data, b"inside");
+        }
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+    assert!(matches!(receive(&mut client).await.unwrap(), Response::Shutdown { .. }));
+    assert!(receive(&mut client).await.is_err());
+}
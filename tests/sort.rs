@@ -0,0 +1,81 @@
+//! Sort orders chosen for a Browser and for each Cursor
+
+mod common;
+
+use std::fs;
+
+use simple_file_transfer_v2::fs::{
+    browser::{Browser, Request, Response},
+    mem_fs::MemFS,
+    sort::{CursorSort, ExtensionKey, SizeKey, SortDirection, SortOrder},
+    FSElement
+};
+
+use common::{describe, TestClient, TestServer};
+
+async fn names(client: &mut TestClient, id: u16) -> Vec<String> {
+    match client.request(Request::Read { id }).await {
+        Response::Read(Ok((_, elements))) => elements.iter().map(|element| element.name_lossy().into_owned()).collect(),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+async fn set_sort(client: &mut TestClient, id: u16, order: SortOrder, direction: SortDirection, directories_first: bool) {
+    match client.request(Request::SetSort { id, order, direction, directories_first }).await {
+        Response::SetSort(Ok(())) => (),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn each_cursor_keeps_its_own_sort_order() {
+    let server = TestServer::start();
+    fs::write(server.root.path().join("big.txt"), vec![0; 100]).unwrap();
+    fs::write(server.root.path().join("small.rs"), vec![0; 1]).unwrap();
+    fs::create_dir(server.root.path().join("dir")).unwrap();
+    let root = format!("/{}", server.virtual_name);
+
+    let mut client = TestClient::connect(server.port).await;
+    let sorted = client.create_cursor().await;
+    let unsorted = client.create_cursor().await;
+    client.read_at(sorted, &root).await;
+    client.read_at(unsorted, &root).await;
+
+    // inside.txt holds 6 bytes. A directory's size depends on the platform, so it is kept apart
+    set_sort(&mut client, sorted, SortOrder::Size, SortDirection::Descending, true).await;
+    assert_eq!(names(&mut client, sorted).await, ["dir", "big.txt", "inside.txt", "small.rs"]);
+
+    // Equal extensions are ordered by name
+    set_sort(&mut client, sorted, SortOrder::FileType, SortDirection::Ascending, false).await;
+    assert_eq!(names(&mut client, sorted).await, ["dir", "small.rs", "big.txt", "inside.txt"]);
+
+    assert_eq!(names(&mut client, unsorted).await, ["big.txt", "dir", "inside.txt", "small.rs"]);
+}
+
+fn element_names(elements: &[FSElement]) -> Vec<&str> {
+    elements.iter().map(|element| element.name_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn the_browser_sorts_by_the_chosen_key() {
+    let fs = MemFS::builder()
+        .add_file("a.txt", "three")
+        .add_file("b.rs", "four")
+        .add_file("c.md", "4444")
+        .build();
+    let mut browser = Browser::new(2, fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/").unwrap();
+    assert_eq!(element_names(&browser.read_cursor(id).await.unwrap()), ["a.txt", "b.rs", "c.md"]);
+
+    // Elements with equal sizes are ordered by name
+    browser.set_sort_key::<SizeKey>();
+    assert_eq!(element_names(&browser.read_cursor(id).await.unwrap()), ["b.rs", "c.md", "a.txt"]);
+
+    browser.set_sort_key::<ExtensionKey>();
+    assert_eq!(element_names(&browser.read_cursor(id).await.unwrap()), ["c.md", "b.rs", "a.txt"]);
+
+    // A Cursor's own order takes precedence
+    browser.set_cursor_sort(id, CursorSort { order: SortOrder::Size, direction: SortDirection::Descending, directories_first: false }).unwrap();
+    assert_eq!(element_names(&browser.read_cursor(id).await.unwrap()), ["a.txt", "c.md", "b.rs"]);
+}
@@ -0,0 +1,126 @@
+//! Streaming downloads, which the client pulls one chunk at a time
+
+mod common;
+
+use std::path::PathBuf;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response}, mapped_fs::MappedFS};
+use tempfile::TempDir;
+
+use common::{describe, TestClient, TestServer};
+
+/// A Browser whose Cursor is at a temporary directory holding `files`
+fn browser_with(files: &[(&str, &[u8])]) -> (Browser<MappedFS>, u16, TempDir) {
+    let dir = TempDir::new().unwrap();
+    for (name, data) in files {
+        std::fs::write(dir.path().join(name), data).unwrap();
+    }
+
+    let mut mapped_fs = MappedFS::new();
+    let name = mapped_fs.add(dir.path()).unwrap();
+    let mut browser = Browser::new(4, mapped_fs);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, PathBuf::from("/").join(name)).unwrap();
+    (browser, id, dir)
+}
+
+#[tokio::test]
+async fn chunks_arrive_in_order_until_nothing_is_left() {
+    let data: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+    let (mut browser, id, _dir) = browser_with(&[("data.bin", &data)]);
+
+    let (transfer_id, total_bytes) = browser.start_stream(id, "data.bin", 1000).await.unwrap();
+    assert_eq!(total_bytes, 2500);
+
+    let mut received = vec![];
+    let mut remaining = vec![];
+    loop {
+        let (chunk, bytes_remaining) = browser.next_chunk(transfer_id).await.unwrap();
+        received.extend_from_slice(&chunk);
+        remaining.push(bytes_remaining);
+        if bytes_remaining == 0 {
+            break;
+        }
+    }
+    assert_eq!(received, data);
+    assert_eq!(remaining, [1500, 500, 0]);
+
+    // The transfer is closed once it is complete
+    assert!(matches!(browser.next_chunk(transfer_id).await, Err(CursorError::UnknownTransfer { .. })));
+}
+
+#[tokio::test]
+async fn empty_files_finish_with_one_empty_chunk() {
+    let (mut browser, id, _dir) = browser_with(&[("empty.txt", b"")]);
+    let (transfer_id, total_bytes) = browser.start_stream(id, "empty.txt", 0).await.unwrap();
+    assert_eq!(total_bytes, 0);
+    assert_eq!(browser.next_chunk(transfer_id).await.unwrap(), (vec![], 0));
+}
+
+#[tokio::test]
+async fn cancelled_transfers_are_closed() {
+    let (mut browser, id, _dir) = browser_with(&[("data.bin", &[1; 100])]);
+    let (transfer_id, _) = browser.start_stream(id, "data.bin", 10).await.unwrap();
+    browser.next_chunk(transfer_id).await.unwrap();
+
+    browser.cancel_stream(transfer_id).unwrap();
+    assert!(matches!(browser.next_chunk(transfer_id).await, Err(CursorError::UnknownTransfer { .. })));
+    assert!(matches!(browser.cancel_stream(transfer_id), Err(CursorError::UnknownTransfer { .. })));
+}
+
+#[tokio::test]
+async fn only_files_can_be_streamed() {
+    let (mut browser, id, dir) = browser_with(&[]);
+    std::fs::create_dir(dir.path().join("folder")).unwrap();
+
+    assert!(matches!(browser.start_stream(id, "folder", 0).await, Err(CursorError::NotAFile { .. })));
+    assert!(matches!(browser.start_stream(id, "missing.bin", 0).await, Err(CursorError::NotFound { .. })));
+}
+
+#[tokio::test]
+async fn each_browser_has_a_limited_number_of_open_streams() {
+    let (mut browser, id, _dir) = browser_with(&[("data.bin", b"data")]);
+    let mut transfers = vec![];
+    let busy = loop {
+        match browser.start_stream(id, "data.bin", 0).await {
+            Ok((transfer_id, _)) => transfers.push(transfer_id),
+            Err(err) => break err,
+        }
+        assert!(transfers.len() <= 64, "The number of open streams is not limited");
+    };
+    assert!(matches!(busy, CursorError::ServerBusy { .. }));
+
+    // Finishing a transfer makes room for another
+    browser.cancel_stream(transfers[0]).unwrap();
+    browser.start_stream(id, "data.bin", 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn streams_through_the_server() {
+    let server = TestServer::start();
+    let large: Vec<u8> = (0..300_000u32).map(|i| 0x80 | (i % 128) as u8).collect();
+    std::fs::write(server.root.path().join("large.bin"), &large).unwrap();
+
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    let transfer_id = match client.request(Request::StartStream { id, path: "large.bin".into(), chunk_size: 100_000 }).await {
+        Response::StartStream(Ok((transfer_id, 300_000))) => transfer_id,
+        response => panic!("Unexpected response: {}", describe(&response)),
+    };
+
+    let mut received = vec![];
+    loop {
+        match client.request(Request::NextChunk { transfer_id }).await {
+            Response::NextChunk(Ok((chunk, bytes_remaining))) => {
+                received.extend_from_slice(&chunk);
+                if bytes_remaining == 0 {
+                    break;
+                }
+            }
+            response => panic!("Unexpected response: {}", describe(&response)),
+        }
+    }
+    assert_eq!(received, large);
+}
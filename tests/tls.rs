@@ -0,0 +1,119 @@
+//! The server and client over TLS, using certificates generated for each test
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use simple_file_transfer_v2::{
+    fs::browser::Response,
+    protocol::{read_magic, write_frame, write_magic, ClientHello, CompressionAlgorithm, PROTOCOL_VERSION},
+    tls
+};
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::rustls::ServerName;
+
+use common::{connect, describe, TestClient, TestServer};
+
+/// A certificate authority and the PEM files of the certificates it signed
+struct Certificates {
+    dir: TempDir,
+    ca: Certificate
+}
+
+impl Certificates {
+    fn new() -> Certificates {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let certificates = Certificates { dir: TempDir::new().unwrap(), ca: Certificate::from_params(params).unwrap() };
+        std::fs::write(certificates.ca_path(), certificates.ca.serialize_pem().unwrap()).unwrap();
+        certificates
+    }
+
+    fn ca_path(&self) -> PathBuf {
+        self.dir.path().join("ca.pem")
+    }
+
+    /// Sign a certificate for `name`, returning the paths of the certificate and key
+    fn sign(&self, name: &str) -> (PathBuf, PathBuf) {
+        let certificate = Certificate::from_params(CertificateParams::new(vec![name.to_owned()])).unwrap();
+        let (cert_path, key_path) = (self.dir.path().join(format!("{name}.pem")), self.dir.path().join(format!("{name}.key")));
+        std::fs::write(&cert_path, certificate.serialize_pem_with_signer(&self.ca).unwrap()).unwrap();
+        std::fs::write(&key_path, certificate.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path)
+    }
+}
+
+fn start_tls_server(cert_path: &Path, key_path: &Path, client_ca_path: Option<&Path>) -> TestServer {
+    let mut args = vec!["--tls-cert", cert_path.to_str().unwrap(), "--tls-key", key_path.to_str().unwrap()];
+    if let Some(client_ca_path) = client_ca_path {
+        args.extend(["--tls-client-ca", client_ca_path.to_str().unwrap()]);
+    }
+    TestServer::start_with_args(&args)
+}
+
+/// Attempt the protocol handshake without panicking, returning whether the server answered it
+async fn handshake_succeeds(mut stream: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin) -> bool {
+    let hello = ClientHello { supported_compression: vec![], heartbeat: None, protocol_version: PROTOCOL_VERSION };
+    write_magic(&mut stream).await.is_ok()
+        && write_frame(&mut stream, CompressionAlgorithm::None, &hello).await.is_ok()
+        && stream.flush().await.is_ok()
+        && read_magic(&mut stream).await.is_ok()
+}
+
+#[tokio::test]
+async fn requests_are_served_over_tls() {
+    let certificates = Certificates::new();
+    let (cert_path, key_path) = certificates.sign("localhost");
+    let server = start_tls_server(&cert_path, &key_path, None);
+
+    let connector = tls::connector(&certificates.ca_path(), None).unwrap();
+    let mut client = TestClient::connect_tls(server.port, &connector).await.unwrap();
+    let id = client.create_cursor().await;
+    match client.read_at(id, &format!("/{}", server.virtual_name)).await {
+        Response::Read(Ok((_, elements))) => assert_eq!(elements[0].name, "inside.txt"),
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+
+    // A client which does not use TLS cannot talk to the server
+    assert!(!handshake_succeeds(connect(server.port).await).await);
+}
+
+#[tokio::test]
+async fn servers_signed_by_another_authority_are_refused() {
+    let (server_certificates, client_certificates) = (Certificates::new(), Certificates::new());
+    let (cert_path, key_path) = server_certificates.sign("localhost");
+    let server = start_tls_server(&cert_path, &key_path, None);
+
+    let connector = tls::connector(&client_certificates.ca_path(), None).unwrap();
+    assert!(TestClient::connect_tls(server.port, &connector).await.is_err());
+}
+
+#[tokio::test]
+async fn mutual_tls_requires_a_client_certificate() {
+    let certificates = Certificates::new();
+    let (cert_path, key_path) = certificates.sign("localhost");
+    let server = start_tls_server(&cert_path, &key_path, Some(&certificates.ca_path()));
+
+    // With TLS 1.3 the client may finish its side of the TLS handshake before the server refuses it, so the
+    // refusal is only seen once the protocol handshake is attempted
+    let anonymous = tls::connector(&certificates.ca_path(), None).unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+    if let Ok(stream) = anonymous.connect(server_name, connect(server.port).await).await {
+        assert!(!handshake_succeeds(stream).await);
+    }
+
+    let (client_cert_path, client_key_path) = certificates.sign("client");
+    let identified = tls::connector(&certificates.ca_path(), Some((&client_cert_path, &client_key_path))).unwrap();
+    let mut client = TestClient::connect_tls(server.port, &identified).await.unwrap();
+    client.create_cursor().await;
+}
+
+#[test]
+fn server_names_are_taken_from_addresses() {
+    assert!(tls::server_name("localhost:8000").is_ok());
+    assert!(tls::server_name("127.0.0.1:8000").is_ok());
+    assert!(tls::server_name("[::1]:8000").is_ok());
+    assert!(tls::server_name("not a name:8000").is_err());
+}
@@ -0,0 +1,91 @@
+//! Uploads, which replace files atomically
+
+mod common;
+
+use std::path::PathBuf;
+
+use simple_file_transfer_v2::fs::{browser::{Browser, CursorError, Request, Response, MAX_UPLOAD_SIZE}, mem_fs::MemFS};
+
+use common::{describe, TestClient, TestServer};
+
+async fn upload(client: &mut TestClient, id: u16, dest_path: &str, data: &[u8]) -> Result<(), CursorError> {
+    match client.request(Request::Upload { id, dest_path: PathBuf::from(dest_path), data: data.to_vec() }).await {
+        Response::Upload(result) => result,
+        response => panic!("Unexpected response: {}", describe(&response)),
+    }
+}
+
+#[tokio::test]
+async fn uploads_create_and_replace_files() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    upload(&mut client, id, "new.bin", &data).await.unwrap();
+    assert_eq!(std::fs::read(server.root.path().join("new.bin")).unwrap(), data);
+    assert_eq!(client.download(id, "new.bin").await.unwrap(), data);
+
+    upload(&mut client, id, "inside.txt", b"replaced").await.unwrap();
+    assert_eq!(std::fs::read(server.root.path().join("inside.txt")).unwrap(), b"replaced");
+
+    // No temporary files are left next to the uploads
+    let mut names: Vec<_> = std::fs::read_dir(server.root.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    names.sort();
+    assert_eq!(names, ["inside.txt", "new.bin"]);
+}
+
+#[tokio::test]
+async fn uploads_into_missing_directories_fail() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    assert!(matches!(upload(&mut client, id, "missing/file.txt", b"data").await, Err(CursorError::DestinationNotFound { .. })));
+    // A file is not a directory either
+    assert!(matches!(upload(&mut client, id, "inside.txt/file.txt", b"data").await, Err(CursorError::DestinationNotFound { .. })));
+    // Uploads cannot add entries to the root of the mapped file system
+    assert!(matches!(upload(&mut client, id, "/new.txt", b"data").await, Err(CursorError::AccessDenied { .. })));
+}
+
+#[tokio::test]
+async fn uploads_up_to_the_limit_fit_in_a_frame() {
+    let server = TestServer::start();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    // Bytes of 0x80 and above are the worst case for the encoding
+    let data = vec![0xff; MAX_UPLOAD_SIZE];
+    upload(&mut client, id, "limit.bin", &data).await.unwrap();
+    assert_eq!(std::fs::metadata(server.root.path().join("limit.bin")).unwrap().len(), MAX_UPLOAD_SIZE as u64);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn uploads_cannot_leave_the_root_through_links() {
+    let outside = tempfile::TempDir::new().unwrap();
+    let server = TestServer::start();
+    std::os::unix::fs::symlink(outside.path(), server.root.path().join("link")).unwrap();
+    let mut client = TestClient::connect(server.port).await;
+    let id = client.create_cursor().await;
+    client.read_at(id, &format!("/{}", server.virtual_name)).await;
+
+    assert!(matches!(upload(&mut client, id, "link/escaped.txt", b"escaped").await, Err(CursorError::AccessDenied { .. })));
+    assert_eq!(std::fs::read_dir(outside.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn uploads_are_seen_by_cached_listings() {
+    // Directories in memory have no modification time, so only the upload itself can mark the listing as stale
+    let mut browser = Browser::new(4, MemFS::builder().add_dir("docs").build());
+    browser.set_cache_ttl(None);
+    let id = browser.create_cursor().unwrap();
+    browser.move_cursor(id, "/docs").unwrap();
+    assert!(browser.read_cursor(id).await.unwrap().is_empty());
+
+    browser.upload_file(id, "new.txt", b"new").await.unwrap();
+    assert_eq!(browser.read_cursor(id).await.unwrap().len(), 1);
+}